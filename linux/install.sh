@@ -54,6 +54,16 @@ sudo cp "$PWD/target/release/scrypt-decrypt" "$DEST_DIR"
 sudo cp "$PWD/target/release/scrypt-encrypt" "$DEST_DIR"
 sudo cp "$PWD/target/release/decrypt-file" "$DEST_DIR"
 sudo cp "$PWD/target/release/encrypt-file" "$DEST_DIR"
+sudo cp "$PWD/target/release/kdf-bench" "$DEST_DIR"
+sudo cp "$PWD/target/release/hashsum" "$DEST_DIR"
+sudo cp "$PWD/target/release/random" "$DEST_DIR"
+sudo cp "$PWD/target/release/split-secret" "$DEST_DIR"
+sudo cp "$PWD/target/release/combine-secret" "$DEST_DIR"
+sudo cp "$PWD/target/release/keytool" "$DEST_DIR"
+sudo cp "$PWD/target/release/certtool" "$DEST_DIR"
+sudo cp "$PWD/target/release/keywrap" "$DEST_DIR"
+sudo cp "$PWD/target/release/agetool" "$DEST_DIR"
+sudo cp "$PWD/target/release/openssl-enc" "$DEST_DIR"
 
 sudo cp "$PWD/linux/systemd/$SERVICE_NAME" "/etc/systemd/system/$SERVICE_NAME"
 sudo chmod +x "$DEST_DIR/$EXECUTABLE_NAME"
@@ -69,6 +79,16 @@ sudo chmod +x "$DEST_DIR/decode52"
 sudo chmod +x "$DEST_DIR/encode52"
 sudo chmod +x "$DEST_DIR/scrypt-decrypt"
 sudo chmod +x "$DEST_DIR/scrypt-encrypt"
+sudo chmod +x "$DEST_DIR/kdf-bench"
+sudo chmod +x "$DEST_DIR/hashsum"
+sudo chmod +x "$DEST_DIR/random"
+sudo chmod +x "$DEST_DIR/split-secret"
+sudo chmod +x "$DEST_DIR/combine-secret"
+sudo chmod +x "$DEST_DIR/keytool"
+sudo chmod +x "$DEST_DIR/certtool"
+sudo chmod +x "$DEST_DIR/keywrap"
+sudo chmod +x "$DEST_DIR/agetool"
+sudo chmod +x "$DEST_DIR/openssl-enc"
 
 sudo systemctl daemon-reload
 sudo systemctl enable "$SERVICE_NAME"
@@ -36,6 +36,16 @@ scp -i $SSH_KEY_PATH $PWD/target/release/scrypt-decrypt $REMOTE_PC:$REMOTE_DEST_
 scp -i $SSH_KEY_PATH $PWD/target/release/scrypt-encrypt $REMOTE_PC:$REMOTE_DEST_DIR
 scp -i $SSH_KEY_PATH $PWD/target/release/decrypt-file $REMOTE_PC:$REMOTE_DEST_DIR
 scp -i $SSH_KEY_PATH $PWD/target/release/encrypt-file $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/kdf-bench $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/hashsum $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/random $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/split-secret $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/combine-secret $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/keytool $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/certtool $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/keywrap $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/agetool $REMOTE_PC:$REMOTE_DEST_DIR
+scp -i $SSH_KEY_PATH $PWD/target/release/openssl-enc $REMOTE_PC:$REMOTE_DEST_DIR
 scp -i $SSH_KEY_PATH $PWD/linux/systemd/$SERVICE_NAME $REMOTE_PC:$REMOTE_DEST_DIR
 
 # Set executable permission
@@ -53,7 +63,17 @@ ssh -i $SSH_KEY_PATH $REMOTE_PC \
      chmod +x $REMOTE_DEST_DIR/decode52; \
      chmod +x $REMOTE_DEST_DIR/encode52; \
      chmod +x $REMOTE_DEST_DIR/scrypt-decrypt; \
-     chmod +x $REMOTE_DEST_DIR/scrypt-encrypt"
+     chmod +x $REMOTE_DEST_DIR/scrypt-encrypt; \
+     chmod +x $REMOTE_DEST_DIR/kdf-bench; \
+     chmod +x $REMOTE_DEST_DIR/hashsum; \
+     chmod +x $REMOTE_DEST_DIR/random; \
+     chmod +x $REMOTE_DEST_DIR/split-secret; \
+     chmod +x $REMOTE_DEST_DIR/combine-secret; \
+     chmod +x $REMOTE_DEST_DIR/keytool; \
+     chmod +x $REMOTE_DEST_DIR/certtool; \
+     chmod +x $REMOTE_DEST_DIR/keywrap; \
+     chmod +x $REMOTE_DEST_DIR/agetool; \
+     chmod +x $REMOTE_DEST_DIR/openssl-enc"
 
 # Install systemd of this service and run the webserver as service
 echo "Installing dependencies and systemd . . ."
@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::methods::MethodGroup;
+
+/// Entry of an allow list that admits every caller, including anonymous ones
+pub const ANYONE: &str = "*";
+/// `log` target of denied calls, so they can be routed to their own file
+pub const AUDIT_TARGET: &str = "audit";
+
+/// Who made a call, as far as the worker can tell
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Caller {
+    /// Client name reported by the transport. ipc-broker 1.1 does not forward the
+    /// caller's connection to workers, so calls through the broker never have one.
+    pub identity: Option<String>,
+    /// Shared client token sent as the `token` argument
+    pub token: Option<String>,
+}
+
+impl Caller {
+    /// Caller of a broker call with `args`
+    pub fn from_args(args: &serde_json::Value) -> Self {
+        Self {
+            identity: None,
+            token: args
+                .get("token")
+                .and_then(|token| token.as_str())
+                .map(str::to_string),
+        }
+    }
+}
+
+/// Client a call is attributed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Client<'a> {
+    Named(&'a str),
+    /// A token was sent but matches no client
+    UnknownToken,
+    Anonymous,
+}
+
+impl Client<'_> {
+    fn name(&self) -> Option<&str> {
+        match self {
+            Client::Named(name) => Some(name),
+            Client::UnknownToken | Client::Anonymous => None,
+        }
+    }
+}
+
+impl fmt::Display for Client<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Client::Named(name) => write!(f, "client {name}"),
+            Client::UnknownToken => f.write_str("caller with an unknown token"),
+            Client::Anonymous => f.write_str("anonymous caller"),
+        }
+    }
+}
+
+/// Which clients may call which method groups
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessPolicy {
+    /// Client name → token the client sends with each call; tokens are redacted
+    /// when the policy is serialized
+    #[serde(serialize_with = "redacted")]
+    pub clients: BTreeMap<String, String>,
+    /// Method group → clients allowed to call it
+    pub groups: BTreeMap<MethodGroup, Vec<String>>,
+    /// Clients allowed to call the groups missing from `groups`
+    pub default: Vec<String>,
+}
+
+/// Written in place of each client token
+pub const REDACTED: &str = "<redacted>";
+
+fn redacted<S: Serializer>(
+    clients: &BTreeMap<String, String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(clients.keys().map(|name| (name, REDACTED)))
+}
+
+impl Default for AccessPolicy {
+    /// Everyone may call everything
+    fn default() -> Self {
+        Self {
+            clients: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            default: vec![ANYONE.to_string()],
+        }
+    }
+}
+
+impl AccessPolicy {
+    /// The broker identity of `caller`, else the client whose token it sent
+    pub fn client<'a>(&'a self, caller: &'a Caller) -> Client<'a> {
+        if let Some(identity) = &caller.identity {
+            return Client::Named(identity);
+        }
+        let Some(token) = &caller.token else {
+            return Client::Anonymous;
+        };
+        self.clients
+            .iter()
+            .find(|(_, known)| {
+                known.len() == token.len()
+                    && openssl::memcmp::eq(known.as_bytes(), token.as_bytes())
+            })
+            .map_or(Client::UnknownToken, |(name, _)| Client::Named(name))
+    }
+
+    pub fn allows(&self, client: Client<'_>, group: MethodGroup) -> bool {
+        self.groups
+            .get(&group)
+            .unwrap_or(&self.default)
+            .iter()
+            .any(|allowed| allowed == ANYONE || Some(allowed.as_str()) == client.name())
+    }
+
+    /// Reject empty or shared tokens and allow lists naming unknown clients
+    pub fn validate(&self) -> Result<()> {
+        let mut tokens = HashSet::new();
+        for (name, token) in &self.clients {
+            if name == ANYONE || name.trim().is_empty() {
+                bail!("invalid client name: {name:?}");
+            }
+            if token.is_empty() {
+                bail!("client {name} has an empty token");
+            }
+            if !tokens.insert(token) {
+                bail!("client {name} shares its token with another client");
+            }
+        }
+        let lists = self.groups.values().chain(std::iter::once(&self.default));
+        for allowed in lists.flatten() {
+            if allowed != ANYONE && !self.clients.contains_key(allowed) {
+                bail!("access list names unknown client {allowed}");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy() {
+        let policy: AccessPolicy = serde_json::from_str(
+            r#"{
+                "clients": { "vault": "s3cret-token", "web": "other-token" },
+                "groups": { "encryption": ["vault"], "meta": ["*"] },
+                "default": ["vault", "web"]
+            }"#,
+        )
+        .unwrap();
+        policy.validate().unwrap();
+
+        let caller = |token: Option<&str>| Caller {
+            identity: None,
+            token: token.map(str::to_string),
+        };
+        let vault = caller(Some("s3cret-token"));
+        let web = caller(Some("other-token"));
+        let wrong = caller(Some("s3cret-tokem"));
+        let anonymous = caller(None);
+        assert_eq!(policy.client(&vault), Client::Named("vault"));
+        assert_eq!(policy.client(&wrong), Client::UnknownToken);
+        assert_eq!(policy.client(&anonymous), Client::Anonymous);
+
+        let allows = |caller: &Caller, group| policy.allows(policy.client(caller), group);
+        assert!(allows(&vault, MethodGroup::Encryption));
+        assert!(!allows(&web, MethodGroup::Encryption));
+        assert!(allows(&web, MethodGroup::Hash));
+        assert!(!allows(&wrong, MethodGroup::Hash));
+        assert!(allows(&anonymous, MethodGroup::Meta));
+        assert!(!allows(&anonymous, MethodGroup::Encoding));
+
+        let identified = Caller {
+            identity: Some("vault".into()),
+            token: None,
+        };
+        assert!(allows(&identified, MethodGroup::Encryption));
+        assert!(AccessPolicy::default().allows(Client::Anonymous, MethodGroup::Encryption));
+
+        let serialized = serde_json::to_value(&policy).unwrap();
+        assert_eq!(
+            serialized["clients"],
+            serde_json::json!({ "vault": REDACTED, "web": REDACTED })
+        );
+        assert_eq!(
+            serialized["groups"]["encryption"],
+            serde_json::json!(["vault"])
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        let invalid = [
+            r#"{ "clients": { "a": "" } }"#,
+            r#"{ "clients": { "a": "t", "b": "t" } }"#,
+            r#"{ "clients": { "*": "t" } }"#,
+            r#"{ "groups": { "hash": ["nobody"] } }"#,
+            r#"{ "default": ["nobody"] }"#,
+        ];
+        for json in invalid {
+            let policy: AccessPolicy = serde_json::from_str(json).unwrap();
+            assert!(policy.validate().is_err(), "{json}");
+        }
+        assert!(serde_json::from_str::<AccessPolicy>(r#"{ "groups": { "admin": [] } }"#).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use openssl::symm::{Cipher, Crypter, Mode};
+
+/// Magic bytes of the header recording the AEAD cipher of a ciphertext.
+/// Data without it is encrypted with AES-256-GCM (files) or AES-256-CBC (strings).
+pub const HEADER_MAGIC: &[u8; 4] = b"ENZC";
+/// magic(4) || cipher(1) || params length(1), the same layout as the KDF header
+pub const HEADER_LEN: usize = HEADER_MAGIC.len() + 2;
+pub const KEY_LEN: usize = 32;
+pub const TAG_LEN: usize = 16;
+
+const CHACHA_CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+/// Identifier of an AEAD cipher, stored in ciphertext headers.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AeadCipher {
+    Aes256Gcm = 1,
+    ChaCha20Poly1305 = 2,
+    XChaCha20Poly1305 = 3,
+}
+
+impl AeadCipher {
+    pub const ALL: [AeadCipher; 3] = [
+        AeadCipher::Aes256Gcm,
+        AeadCipher::ChaCha20Poly1305,
+        AeadCipher::XChaCha20Poly1305,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AeadCipher::Aes256Gcm => "aes-256-gcm",
+            AeadCipher::ChaCha20Poly1305 => "chacha20-poly1305",
+            AeadCipher::XChaCha20Poly1305 => "xchacha20-poly1305",
+        }
+    }
+
+    /// Length of the random nonce stored next to the ciphertext
+    pub fn nonce_len(&self) -> usize {
+        match self {
+            AeadCipher::Aes256Gcm | AeadCipher::ChaCha20Poly1305 => 12,
+            AeadCipher::XChaCha20Poly1305 => 24,
+        }
+    }
+
+    /// Streaming crypter for a 32-byte `key` and a nonce of [`AeadCipher::nonce_len`] bytes.
+    /// The tag is read with `get_tag` after encryption and set with `set_tag` before
+    /// finalizing decryption.
+    pub fn crypter(&self, mode: Mode, key: &[u8], nonce: &[u8]) -> Result<Crypter> {
+        if key.len() != KEY_LEN || nonce.len() != self.nonce_len() {
+            return Err(anyhow!("invalid key or nonce length for {self}"));
+        }
+        let mut crypter = match self {
+            AeadCipher::Aes256Gcm => Crypter::new(Cipher::aes_256_gcm(), mode, key, Some(nonce))?,
+            AeadCipher::ChaCha20Poly1305 => {
+                Crypter::new(Cipher::chacha20_poly1305(), mode, key, Some(nonce))?
+            }
+            AeadCipher::XChaCha20Poly1305 => {
+                // XChaCha20: derive a subkey from the first 16 nonce bytes and use
+                // ChaCha20-Poly1305 with four zero bytes and the remaining 8 bytes
+                let mut subkey = hchacha20(key, &nonce[..16]);
+                let mut chacha_nonce = [0u8; 12];
+                chacha_nonce[4..].copy_from_slice(&nonce[16..]);
+                let crypter = Crypter::new(
+                    Cipher::chacha20_poly1305(),
+                    mode,
+                    &subkey,
+                    Some(&chacha_nonce),
+                );
+                subkey.fill(0);
+                crypter?
+            }
+        };
+        crypter.pad(false);
+        Ok(crypter)
+    }
+}
+
+impl fmt::Display for AeadCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AeadCipher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        AeadCipher::ALL
+            .into_iter()
+            .find(|cipher| cipher.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("unknown cipher: {s}"))
+    }
+}
+
+impl TryFrom<u8> for AeadCipher {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        AeadCipher::ALL
+            .into_iter()
+            .find(|cipher| *cipher as u8 == value)
+            .ok_or_else(|| anyhow!("unknown cipher identifier: {value}"))
+    }
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// HChaCha20 (draft-irtf-cfrg-xchacha section 2.2) of a 32-byte key and 16-byte nonce
+fn hchacha20(key: &[u8], nonce: &[u8]) -> [u8; 32] {
+    let word = |bytes: &[u8]| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CHACHA_CONSTANTS);
+    for i in 0..8 {
+        state[4 + i] = word(&key[i * 4..]);
+    }
+    for i in 0..4 {
+        state[12 + i] = word(&nonce[i * 4..]);
+    }
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    let mut subkey = [0u8; 32];
+    for (i, index) in [0, 1, 2, 3, 12, 13, 14, 15].into_iter().enumerate() {
+        subkey[i * 4..(i + 1) * 4].copy_from_slice(&state[index].to_le_bytes());
+    }
+    state.fill(0);
+    subkey
+}
+
+/// Serialize a header: magic || cipher || params length (always 0)
+pub fn encode_header(cipher: AeadCipher) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(HEADER_MAGIC);
+    header.push(cipher as u8);
+    header.push(0);
+    header
+}
+
+/// Parse a cipher header at the start of `data`.
+/// Returns the cipher and the header length, or `None` when the data has no cipher header.
+pub fn decode_header(data: &[u8]) -> Result<Option<(AeadCipher, usize)>> {
+    if data.len() < HEADER_LEN || !data.starts_with(HEADER_MAGIC) {
+        return Ok(None);
+    }
+    if data[HEADER_LEN - 1] != 0 {
+        return Err(anyhow!("unsupported cipher parameters"));
+    }
+    let cipher = AeadCipher::try_from(data[HEADER_MAGIC.len()])?;
+    Ok(Some((cipher, HEADER_LEN)))
+}
+
+/// One-shot encryption -> ciphertext || tag
+pub fn seal(cipher: AeadCipher, key: &[u8], nonce: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut crypter = cipher.crypter(Mode::Encrypt, key, nonce)?;
+    let mut out = vec![0u8; plaintext.len() + 16 + TAG_LEN];
+    let mut count = crypter.update(plaintext, &mut out)?;
+    count += crypter.finalize(&mut out[count..])?;
+    crypter.get_tag(&mut out[count..count + TAG_LEN])?;
+    out.truncate(count + TAG_LEN);
+    Ok(out)
+}
+
+/// One-shot decryption of ciphertext || tag, failing when authentication fails
+pub fn open(cipher: AeadCipher, key: &[u8], nonce: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    let ciphertext_len = sealed
+        .len()
+        .checked_sub(TAG_LEN)
+        .ok_or_else(|| anyhow!("input too short"))?;
+    let mut crypter = cipher.crypter(Mode::Decrypt, key, nonce)?;
+    let mut out = vec![0u8; ciphertext_len + 16];
+    let mut count = crypter.update(&sealed[..ciphertext_len], &mut out)?;
+    crypter.set_tag(&sealed[ciphertext_len..])?;
+    count += crypter
+        .finalize(&mut out[count..])
+        .map_err(|_| anyhow!("Decryption failed: authentication tag mismatch"))?;
+    out.truncate(count);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hchacha20_vector() {
+        // draft-irtf-cfrg-xchacha-03 section 2.2.1
+        let key: Vec<u8> = (0u8..32).collect();
+        let nonce = hex::decode("000000090000004a0000000031415927").unwrap();
+        assert_eq!(
+            hex::encode(hchacha20(&key, &nonce)),
+            "82413b4227b27bfed30e42508a877d73a0f9e4d58a74a853c12ec41326d3ecdc"
+        );
+    }
+
+    #[test]
+    fn test_chacha20_poly1305_rfc8439_vector() {
+        // RFC 8439 section 2.8.2; the expected tag covers additional data, so only the
+        // start of the ciphertext is compared
+        let key: Vec<u8> = (0x80u8..0xa0).collect();
+        let nonce = hex::decode("070000004041424344454647").unwrap();
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+                          only one tip for the future, sunscreen would be it.";
+        let sealed = seal(AeadCipher::ChaCha20Poly1305, &key, &nonce, plaintext).unwrap();
+        assert_eq!(
+            hex::encode(&sealed[..16]),
+            "d31a8d34648e60db7b86afbc53ef7ec2"
+        );
+        let opened = open(AeadCipher::ChaCha20Poly1305, &key, &nonce, &sealed).unwrap();
+        assert_eq!(opened, plaintext);
+    }
+
+    #[test]
+    fn test_xchacha20_poly1305_vector() {
+        // Output of the RustCrypto chacha20poly1305 crate for the same inputs
+        let sealed = seal(
+            AeadCipher::XChaCha20Poly1305,
+            &[7u8; KEY_LEN],
+            &[3u8; 24],
+            b"attack at dawn",
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(sealed),
+            "e5723f7a164ae6910330279a30bf0a9ffe7b5de759493f7244fbdbdb0250"
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_tampering_and_header() {
+        let key = [7u8; KEY_LEN];
+        for cipher in AeadCipher::ALL {
+            let nonce = vec![3u8; cipher.nonce_len()];
+            let mut sealed = seal(cipher, &key, &nonce, b"attack at dawn").unwrap();
+            assert_eq!(
+                open(cipher, &key, &nonce, &sealed).unwrap(),
+                b"attack at dawn"
+            );
+            sealed[0] ^= 1;
+            assert!(open(cipher, &key, &nonce, &sealed).is_err());
+
+            let header = encode_header(cipher);
+            assert_eq!(decode_header(&header).unwrap(), Some((cipher, HEADER_LEN)));
+            assert_eq!(cipher.name().parse::<AeadCipher>().unwrap(), cipher);
+        }
+        assert!(seal(AeadCipher::XChaCha20Poly1305, &key, &[0u8; 12], b"x").is_err());
+        assert_eq!(decode_header(b"ENZK\x01\x10").unwrap(), None);
+        assert!(decode_header(b"ENZC\x09\x00").is_err());
+    }
+}
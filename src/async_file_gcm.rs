@@ -1,37 +1,118 @@
 use std::path::Path;
 
 use anyhow::{Result, anyhow};
-use openssl::pkcs5;
 use openssl::rand::rand_bytes;
-use openssl::symm::{Cipher, Crypter, Mode};
+use openssl::symm::{Crypter, Mode};
 use tokio::fs::File;
+use tokio::io::AsyncRead;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
 use tokio::sync::mpsc::Sender;
 use tokio::time::{Duration, Instant};
 
+use crate::aead::{self, AeadCipher};
+use crate::kdf::{self, Kdf, KdfLimits, Scrypt};
+
 const SALT_LEN: usize = 16;
-const NONCE_LEN: usize = 12; // Recommended nonce size for GCM
 const KEY_LEN: usize = 32;
 const TAG_LEN: usize = 16;
+const BLOCK_SIZE: usize = 16;
+/// Bytes read and encrypted or decrypted at a time
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Key derived on a blocking thread, as a password KDF is slow by design
+async fn derive_key(kdf: Box<dyn Kdf>, password: &str, salt: &[u8]) -> Result<Vec<u8>> {
+    kdf::ensure_password_kdf(kdf.algorithm())?;
+    let (password, salt) = (password.to_owned(), salt.to_vec());
+    tokio::task::spawn_blocking(move || {
+        let mut key = vec![0u8; KEY_LEN];
+        kdf.derive(password.as_bytes(), &salt, &mut key)?;
+        Ok(key)
+    })
+    .await?
+}
 
-// scrypt parameters (adjust based on your security requirements)
-const SCRYPT_N: u64 = 16384;
-const SCRYPT_R: u64 = 8;
-const SCRYPT_P: u64 = 1;
-const SCRYPT_MAXMEM: u64 = 512 * 1024 * 1024;
+/// Crypter of a file with its input and output buffers
+struct Chunks {
+    crypter: Crypter,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
 
-fn derive_key_scrypt(password: &str, salt: &[u8]) -> Result<Vec<u8>> {
-    let mut key = vec![0u8; KEY_LEN];
-    pkcs5::scrypt(
-        password.as_bytes(),
-        salt,
-        SCRYPT_N,
-        SCRYPT_R,
-        SCRYPT_P,
-        SCRYPT_MAXMEM,
-        &mut key,
-    )?;
-    Ok(key)
+impl Chunks {
+    fn new(crypter: Crypter) -> Self {
+        Self {
+            crypter,
+            input: vec![0u8; CHUNK_LEN],
+            output: vec![0u8; CHUNK_LEN + BLOCK_SIZE],
+        }
+    }
+
+    /// Encrypt or decrypt the first `len` input bytes into the output on a blocking
+    /// thread; returns the chunks back with the number of output bytes
+    async fn update(mut self, len: usize) -> Result<(Self, usize)> {
+        tokio::task::spawn_blocking(move || {
+            let written = self.crypter.update(&self.input[..len], &mut self.output)?;
+            Ok((self, written))
+        })
+        .await?
+    }
+}
+
+/// Cipher, KDF, total header length and salt at the start of a file
+struct Header {
+    cipher: AeadCipher,
+    kdf: Box<dyn Kdf>,
+    len: usize,
+    salt: [u8; SALT_LEN],
+}
+
+/// Read the optional cipher and KDF headers and the salt from the start of the file.
+/// KDF headers costing more than `limits` are refused.
+async fn read_header<R: AsyncRead + Unpin>(reader: &mut R, limits: &KdfLimits) -> Result<Header> {
+    let mut prefix = [0u8; kdf::HEADER_PREFIX_LEN];
+    reader.read_exact(&mut prefix).await?;
+    let mut salt = [0u8; SALT_LEN];
+
+    // The cipher header has the same length as a KDF header prefix
+    let (cipher, cipher_header_len) = match aead::decode_header(&prefix)? {
+        Some(header) => {
+            reader.read_exact(&mut prefix).await?;
+            header
+        }
+        None => (AeadCipher::Aes256Gcm, 0),
+    };
+
+    match kdf::params_len(&prefix) {
+        Some(params_len) => {
+            let mut header = prefix.to_vec();
+            header.resize(kdf::HEADER_PREFIX_LEN + params_len, 0);
+            reader
+                .read_exact(&mut header[kdf::HEADER_PREFIX_LEN..])
+                .await?;
+            let (kdf, header_len) = kdf::decode_header_with_limits(&header, limits)?
+                .ok_or_else(|| anyhow!("Invalid KDF header"))?;
+            reader.read_exact(&mut salt).await?;
+            Ok(Header {
+                cipher,
+                kdf,
+                len: cipher_header_len + header_len,
+                salt,
+            })
+        }
+        None => {
+            // Legacy file: the prefix is the beginning of the salt
+            salt[..kdf::HEADER_PREFIX_LEN].copy_from_slice(&prefix);
+            reader
+                .read_exact(&mut salt[kdf::HEADER_PREFIX_LEN..])
+                .await?;
+            Ok(Header {
+                cipher,
+                kdf: Box::new(Scrypt::default()),
+                len: cipher_header_len,
+                salt,
+            })
+        }
+    }
 }
 
 pub struct Progress(usize, usize); // bytes processed, total bytes
@@ -62,41 +143,137 @@ pub async fn encrypt_file(
     progress_tx: Sender<Progress>,
     interval: Duration,
 ) -> Result<()> {
+    encrypt_file_inner(
+        input_path,
+        output_path,
+        password,
+        &Scrypt::default(),
+        Layout::Legacy,
+        progress_tx,
+        interval,
+    )
+    .await
+}
+
+/// Encrypt large file with password using the given KDF and write output file with format:
+/// [kdf header] [salt(16 bytes)] [nonce(12 bytes)] [ciphertext stream ...] [tag(16 bytes)]
+pub async fn encrypt_file_with_kdf(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    kdf: &dyn Kdf,
+    progress_tx: Sender<Progress>,
+    interval: Duration,
+) -> Result<()> {
+    encrypt_file_inner(
+        input_path,
+        output_path,
+        password,
+        kdf,
+        Layout::Kdf,
+        progress_tx,
+        interval,
+    )
+    .await
+}
+
+/// Encrypt large file with password using the given KDF and cipher and write output file with format:
+/// [cipher header] [kdf header] [salt(16 bytes)] [nonce(12 or 24 bytes)] [ciphertext stream ...] [tag(16 bytes)]
+pub async fn encrypt_file_with_cipher(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    kdf: &dyn Kdf,
+    cipher: AeadCipher,
+    progress_tx: Sender<Progress>,
+    interval: Duration,
+) -> Result<()> {
+    encrypt_file_inner(
+        input_path,
+        output_path,
+        password,
+        kdf,
+        Layout::Cipher(cipher),
+        progress_tx,
+        interval,
+    )
+    .await
+}
+
+/// Headers written in front of the salt
+#[derive(Clone, Copy)]
+enum Layout {
+    /// No header: default scrypt parameters and AES-256-GCM
+    Legacy,
+    Kdf,
+    Cipher(AeadCipher),
+}
+
+impl Layout {
+    fn cipher(self) -> AeadCipher {
+        match self {
+            Layout::Cipher(cipher) => cipher,
+            Layout::Legacy | Layout::Kdf => AeadCipher::Aes256Gcm,
+        }
+    }
+
+    fn header(self, kdf: &dyn Kdf) -> Result<Vec<u8>> {
+        Ok(match self {
+            Layout::Legacy => Vec::new(),
+            Layout::Kdf => kdf::encode_header(kdf)?,
+            Layout::Cipher(cipher) => {
+                let mut header = aead::encode_header(cipher);
+                header.extend_from_slice(&kdf::encode_header(kdf)?);
+                header
+            }
+        })
+    }
+}
+
+async fn encrypt_file_inner(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    kdf: &dyn Kdf,
+    layout: Layout,
+    progress_tx: Sender<Progress>,
+    interval: Duration,
+) -> Result<()> {
+    let cipher = layout.cipher();
+    let header = layout.header(kdf)?;
     let mut salt = [0u8; SALT_LEN];
-    let mut nonce = [0u8; NONCE_LEN];
+    let mut nonce = vec![0u8; cipher.nonce_len()];
     rand_bytes(&mut salt)?;
     rand_bytes(&mut nonce)?;
 
-    let key = derive_key_scrypt(password, &salt)?;
-
-    let cipher = Cipher::aes_256_gcm();
+    // An owned copy of the KDF moves to the blocking thread
+    let owned_kdf = kdf::from_params(kdf.algorithm(), &kdf.params()?)?;
+    let key = derive_key(owned_kdf, password, &salt).await?;
 
-    let mut crypter = Crypter::new(cipher, Mode::Encrypt, &key, Some(&nonce))?;
-    crypter.pad(false); // GCM does not use padding
+    let mut chunks = Chunks::new(cipher.crypter(Mode::Encrypt, &key, &nonce)?);
 
     let mut infile = BufReader::new(File::open(input_path).await?);
     let mut outfile = BufWriter::new(File::create(output_path).await?);
 
-    // Write salt and nonce at the start of output
+    // Write header, salt and nonce at the start of output
+    outfile.write_all(&header).await?;
     outfile.write_all(&salt).await?;
     outfile.write_all(&nonce).await?;
 
-    let mut buffer = [0u8; 4096];
-    let mut ciphertext_chunk = vec![0u8; 4096 + cipher.block_size()];
-
     let metadata = tokio::fs::metadata(input_path).await?;
     let total_bytes = metadata.len() as usize;
     let mut total_bytes_read = 0u64 as usize;
     // --- PROGRESS THROTTLE ---
     let mut last_sent = Instant::now();
     loop {
-        let count = infile.read(&mut buffer).await?;
+        let count = infile.read(&mut chunks.input).await?;
         if count == 0 {
             break;
         }
         total_bytes_read += count;
-        let len = crypter.update(&buffer[..count], &mut ciphertext_chunk)?;
-        outfile.write_all(&ciphertext_chunk[..len]).await?;
+        let len;
+        (chunks, len) = chunks.update(count).await?;
+        outfile.write_all(&chunks.output[..len]).await?;
 
         // Send progress only every specified interval
         if last_sent.elapsed() >= interval {
@@ -107,13 +284,13 @@ pub async fn encrypt_file(
         }
     }
     // Finalize encryption (writes any remaining data)
-    let len = crypter.finalize(&mut ciphertext_chunk)?;
-    outfile.write_all(&ciphertext_chunk[..len]).await?;
+    let len = chunks.crypter.finalize(&mut chunks.output)?;
+    outfile.write_all(&chunks.output[..len]).await?;
 
     // Get and write authentication tag at the end
     // Read the tag at the end of file
     let mut tag = [0u8; TAG_LEN];
-    crypter.get_tag(&mut tag)?;
+    chunks.crypter.get_tag(&mut tag)?;
     outfile.write_all(&tag).await?;
 
     outfile.flush().await?;
@@ -125,58 +302,78 @@ pub async fn encrypt_file(
 }
 
 /// Decrypt large file with password from file format:
-/// [salt(16 bytes)] [nonce(12 bytes)] [ciphertext stream ...] [tag(16 bytes)]
+/// [cipher header (optional)] [kdf header (optional)] [salt(16 bytes)] [nonce(12 or 24 bytes)]
+/// [ciphertext stream ...] [tag(16 bytes)]
 pub async fn decrypt_file(
     input_path: &Path,
     output_path: &Path,
     password: &str,
     progress_tx: Sender<Progress>,
     interval: Duration,
+) -> Result<()> {
+    decrypt_file_with_limits(
+        input_path,
+        output_path,
+        password,
+        &KdfLimits::MAX,
+        progress_tx,
+        interval,
+    )
+    .await
+}
+
+/// [`decrypt_file`] refusing KDF headers that cost more than `limits`
+pub async fn decrypt_file_with_limits(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    limits: &KdfLimits,
+    progress_tx: Sender<Progress>,
+    interval: Duration,
 ) -> Result<()> {
     let mut infile = BufReader::new(File::open(input_path).await?);
 
-    // Read salt and nonce from file start
-    let mut salt = [0u8; SALT_LEN];
-    infile.read_exact(&mut salt).await?;
-    let mut nonce = [0u8; NONCE_LEN];
+    // Read header, salt and nonce from file start
+    let Header {
+        cipher,
+        kdf,
+        len: header_len,
+        salt,
+    } = read_header(&mut infile, limits).await?;
+    let mut nonce = vec![0u8; cipher.nonce_len()];
     infile.read_exact(&mut nonce).await?;
 
     // Determine file length to find where tag is located
     let metadata = infile.get_ref().metadata().await?;
     let file_size = metadata.len();
 
-    // Calculate ciphertext size: total - salt - nonce - tag
-    let ciphertext_len = file_size as usize - SALT_LEN - NONCE_LEN - TAG_LEN;
+    // Calculate ciphertext size: total - header - salt - nonce - tag
+    let ciphertext_len = (file_size as usize)
+        .checked_sub(header_len + SALT_LEN + nonce.len() + TAG_LEN)
+        .ok_or_else(|| anyhow!("Input file too short"))?;
     let total_bytes = ciphertext_len;
 
-    let key = derive_key_scrypt(password, &salt)?;
-
-    let cipher = Cipher::aes_256_gcm();
+    let key = derive_key(kdf, password, &salt).await?;
 
-    let mut crypter = Crypter::new(cipher, Mode::Decrypt, &key, Some(&nonce))?;
-    crypter.pad(false);
+    let mut chunks = Chunks::new(cipher.crypter(Mode::Decrypt, &key, &nonce)?);
 
     let mut outfile = BufWriter::new(File::create(output_path).await?);
 
-    // Buffer for reading ciphertext chunks
-    let mut buffer = vec![0u8; 4096];
-    // Buffer for decrypted plaintext output
-    let mut plaintext_chunk = vec![0u8; 4096 + cipher.block_size()];
-
     // Read ciphertext chunks until just before tag
     let mut total_read = 0usize;
     // --- PROGRESS THROTTLE ---
     let mut last_sent = Instant::now();
     while total_read < ciphertext_len {
-        let to_read = std::cmp::min(4096, ciphertext_len - total_read);
-        let read_bytes = infile.read(&mut buffer[..to_read]).await?;
+        let to_read = std::cmp::min(CHUNK_LEN, ciphertext_len - total_read);
+        let read_bytes = infile.read(&mut chunks.input[..to_read]).await?;
         if read_bytes == 0 {
             return Err(anyhow!("Unexpected end of file while reading ciphertext"));
         }
         total_read += read_bytes;
 
-        let len = crypter.update(&buffer[..read_bytes], &mut plaintext_chunk)?;
-        outfile.write_all(&plaintext_chunk[..len]).await?;
+        let len;
+        (chunks, len) = chunks.update(read_bytes).await?;
+        outfile.write_all(&chunks.output[..len]).await?;
 
         // Send progress only every specified interval
         if last_sent.elapsed() >= interval {
@@ -187,13 +384,14 @@ pub async fn decrypt_file(
     // Read the tag at the end of file
     let mut tag = [0u8; TAG_LEN];
     infile.read_exact(&mut tag).await?;
-    crypter.set_tag(&tag)?;
+    chunks.crypter.set_tag(&tag)?;
 
     // Finalize decryption (checks authentication)
-    let len = crypter
-        .finalize(&mut plaintext_chunk)
+    let len = chunks
+        .crypter
+        .finalize(&mut chunks.output)
         .map_err(|_| anyhow!("Decryption failed: authentication tag mismatch"))?;
-    outfile.write_all(&plaintext_chunk[..len]).await?;
+    outfile.write_all(&chunks.output[..len]).await?;
 
     outfile.flush().await?;
 
@@ -434,4 +632,95 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_encrypt_decrypt_file_with_kdf() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let plain_path = temp_dir.path().join("plaintext.txt");
+        let encrypted_path = temp_dir.path().join("encrypted.enc");
+        let decrypted_path = temp_dir.path().join("decrypted.txt");
+
+        let test_data = b"Argon2id protected data";
+        tokio::fs::write(&plain_path, test_data).await?;
+
+        let kdf = crate::kdf::Argon2id {
+            iterations: 1,
+            memory_kib: 1024,
+            lanes: 1,
+        };
+        let (enc_tx, _enc_rx) = mpsc::channel::<Progress>(10);
+        encrypt_file_with_kdf(
+            &plain_path,
+            &encrypted_path,
+            "testpassword",
+            &kdf,
+            enc_tx,
+            Duration::from_millis(500),
+        )
+        .await?;
+
+        let encrypted = tokio::fs::read(&encrypted_path).await?;
+        assert!(encrypted.starts_with(kdf::HEADER_MAGIC));
+
+        let (dec_tx, _dec_rx) = mpsc::channel::<Progress>(10);
+        decrypt_file(
+            &encrypted_path,
+            &decrypted_path,
+            "testpassword",
+            dec_tx,
+            Duration::from_millis(500),
+        )
+        .await?;
+
+        assert_eq!(tokio::fs::read(&decrypted_path).await?, test_data);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_decrypt_file_with_cipher() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let plain_path = temp_dir.path().join("plaintext.txt");
+        let encrypted_path = temp_dir.path().join("encrypted.enc");
+        let decrypted_path = temp_dir.path().join("decrypted.txt");
+
+        let test_data = b"ChaCha20-Poly1305 protected data";
+        tokio::fs::write(&plain_path, test_data).await?;
+
+        let kdf = crate::kdf::Pbkdf2 {
+            sha512: true,
+            iterations: 1000,
+        };
+        for cipher in [AeadCipher::ChaCha20Poly1305, AeadCipher::XChaCha20Poly1305] {
+            let (enc_tx, _enc_rx) = mpsc::channel::<Progress>(10);
+            encrypt_file_with_cipher(
+                &plain_path,
+                &encrypted_path,
+                "testpassword",
+                &kdf,
+                cipher,
+                enc_tx,
+                Duration::from_millis(500),
+            )
+            .await?;
+
+            let encrypted = tokio::fs::read(&encrypted_path).await?;
+            assert!(encrypted.starts_with(aead::HEADER_MAGIC));
+
+            let (dec_tx, _dec_rx) = mpsc::channel::<Progress>(10);
+            decrypt_file(
+                &encrypted_path,
+                &decrypted_path,
+                "testpassword",
+                dec_tx,
+                Duration::from_millis(500),
+            )
+            .await?;
+            assert_eq!(tokio::fs::read(&decrypted_path).await?, test_data);
+
+            // The synchronous implementation reads the same format
+            crate::file_gcm::decrypt_file(&encrypted_path, &decrypted_path, "testpassword")?;
+            assert_eq!(tokio::fs::read(&decrypted_path).await?, test_data);
+        }
+        Ok(())
+    }
 }
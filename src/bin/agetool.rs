@@ -0,0 +1,103 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use enzo_crypto::age::{self, DecryptWith, EncryptTo, Identity};
+use enzo_crypto::util::passphrase_arg;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} keygen [-o <identity file>]\n       \
+         {program} recipient <identity file>\n       \
+         {program} encrypt <input> <output> [options]\n       \
+         {program} decrypt <input> <output> [options]\n\n\
+         Options:\n  \
+         -r <recipient>   encrypt to an age1... recipient (repeatable)\n  \
+         -R <file>        encrypt to the recipients listed in a file (repeatable)\n  \
+         -i <file>        decrypt with the identities in a file (repeatable)\n  \
+         -p <passphrase>  encrypt or decrypt with a passphrase (- to read stdin)\n  \
+         -w <factor>      scrypt work factor for passphrase encryption (default 18)\n  \
+         -a               write an ASCII armored file"
+    );
+    std::process::exit(1);
+}
+
+fn keygen(output: Option<&String>) -> Result<(), Box<dyn Error>> {
+    let identity = Identity::generate()?;
+    let text = format!(
+        "# public key: {}\n{}\n",
+        identity.recipient()?,
+        identity.to_bech32()?
+    );
+    match output {
+        Some(path) => {
+            fs::write(path, text)?;
+            println!("[Public key] {}", identity.recipient()?);
+        }
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        usage(&args[0]);
+    }
+
+    let mut recipients = Vec::new();
+    let mut identities = Vec::new();
+    let mut passphrase: Option<String> = None;
+    let mut work_factor = age::DEFAULT_WORK_FACTOR;
+    let mut armor = false;
+    let mut output: Option<String> = None;
+    let mut positional = Vec::new();
+
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().unwrap_or_else(|| usage(&args[0]));
+        match arg.as_str() {
+            "-r" => recipients.push(value().parse()?),
+            "-R" => recipients.extend(age::parse_recipients(&fs::read_to_string(value())?)?),
+            "-i" => identities.extend(age::parse_identities(&fs::read_to_string(value())?)?),
+            "-p" => passphrase = Some(passphrase_arg(value(), "Passphrase")?),
+            "-w" => work_factor = value().parse()?,
+            "-o" => output = Some(value().clone()),
+            "-a" => armor = true,
+            _ => positional.push(arg.as_str()),
+        }
+    }
+
+    match (args[1].as_str(), positional.as_slice()) {
+        ("keygen", []) => keygen(output.as_ref())?,
+        ("recipient", [path]) => {
+            for identity in age::parse_identities(&fs::read_to_string(path)?)? {
+                println!("[Recipient] {}", identity.recipient()?);
+            }
+        }
+        ("encrypt", [input, output]) => {
+            let to = match passphrase {
+                Some(passphrase) if recipients.is_empty() => EncryptTo::Passphrase {
+                    passphrase,
+                    work_factor,
+                },
+                None if !recipients.is_empty() => EncryptTo::Recipients(recipients),
+                _ => usage(&args[0]),
+            };
+            age::encrypt_file(Path::new(input), Path::new(output), &to, armor)?;
+            println!("[Encrypted] {output}");
+        }
+        ("decrypt", [input, output]) => {
+            let with = match passphrase {
+                Some(passphrase) if identities.is_empty() => DecryptWith::Passphrase(passphrase),
+                None if !identities.is_empty() => DecryptWith::Identities(identities),
+                _ => usage(&args[0]),
+            };
+            age::decrypt_file(Path::new(input), Path::new(output), &with)?;
+            println!("[Decrypted] {output}");
+        }
+        _ => usage(&args[0]),
+    }
+
+    Ok(())
+}
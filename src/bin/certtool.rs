@@ -0,0 +1,239 @@
+use std::error::Error;
+use std::fs;
+
+use enzo_crypto::keys::{self, KeyFormat, KeySpec};
+use enzo_crypto::pkcs12;
+use enzo_crypto::util::passphrase_arg;
+use enzo_crypto::x509::{self, CertInfo, CertParams, DEFAULT_CA_DAYS};
+use openssl::pkey::{PKey, Private};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} self-signed <common name> [options]\n       \
+         {program} ca <common name> [options]\n       \
+         {program} issue <ca cert> <ca key> <common name> [options]\n       \
+         {program} csr <common name> [options]\n       \
+         {program} sign <ca cert> <ca key> <csr file> [options]\n       \
+         {program} show <cert or csr file>\n       \
+         {program} pkcs12 <cert> <key> [ca cert...] -o <file.p12> [options]\n       \
+         {program} extract <file.p12> [options]\n\n\
+         Options:\n  \
+         -s <san>         subject alternative name, DNS:, IP:, email: or URI: (repeatable)\n  \
+         -O <org>         organization name\n  \
+         -d <days>        validity in days (default 365, 3650 for a CA)\n  \
+         -k <key file>    use an existing private key instead of generating one\n  \
+         -a <key type>    type of the generated key (default ec-p256)\n  \
+         -n <name>        friendly name stored in the PKCS#12 file\n  \
+         -p <passphrase>  passphrase of the input key, CA key or PKCS#12 file (- to read stdin)\n  \
+         -P <passphrase>  passphrase protecting the generated or extracted key,\n                   \
+         or the PKCS#12 file (- to read stdin, prompted for PKCS#12 when omitted)\n  \
+         -o <file>        write the certificate, CSR or PKCS#12 file to a file instead of stdout\n  \
+         -K <file>        write the generated or extracted key to a file instead of stdout"
+    );
+    std::process::exit(1);
+}
+
+#[derive(Default)]
+struct Options {
+    sans: Vec<String>,
+    organization: Option<String>,
+    name: Option<String>,
+    days: Option<u32>,
+    key: Option<String>,
+    key_spec: Option<KeySpec>,
+    passphrase: Option<String>,
+    new_passphrase: Option<String>,
+    output: Option<String>,
+    key_output: Option<String>,
+}
+
+impl Options {
+    fn params(&self, common_name: &str, default_days: u32) -> Result<CertParams, Box<dyn Error>> {
+        let mut params = CertParams::new(common_name);
+        params.organization = self.organization.clone();
+        params.days = self.days.unwrap_or(default_days);
+        for san in &self.sans {
+            params.sans.push(san.parse()?);
+        }
+        Ok(params)
+    }
+
+    /// The `-k` key, or a freshly generated one that is written out afterwards
+    fn private_key(&self) -> Result<(PKey<Private>, bool), Box<dyn Error>> {
+        match &self.key {
+            Some(path) => Ok((
+                keys::load_private(&fs::read(path)?, self.passphrase.as_deref())?,
+                false,
+            )),
+            None => {
+                let spec = self.key_spec.unwrap_or(KeySpec::Ec(keys::Curve::P256));
+                Ok((keys::generate(spec)?, true))
+            }
+        }
+    }
+
+    fn write(&self, label: &str, pem: &[u8]) -> Result<(), Box<dyn Error>> {
+        match &self.output {
+            Some(path) => {
+                fs::write(path, pem)?;
+                println!("[{label}] written to {path}");
+            }
+            None => print!("{}", String::from_utf8_lossy(pem)),
+        }
+        Ok(())
+    }
+
+    fn write_key(&self, key: &PKey<Private>) -> Result<(), Box<dyn Error>> {
+        let pem = keys::export_private(key, KeyFormat::Pem, self.new_passphrase.as_deref())?;
+        match &self.key_output {
+            Some(path) => {
+                fs::write(path, pem)?;
+                println!("[Key] written to {path}");
+            }
+            None => print!("{}", String::from_utf8_lossy(&pem)),
+        }
+        Ok(())
+    }
+
+    fn load_ca(
+        &self,
+        cert_path: &str,
+        key_path: &str,
+    ) -> Result<(openssl::x509::X509, PKey<Private>), Box<dyn Error>> {
+        let cert = x509::load_cert(&fs::read(cert_path)?)?;
+        let key = keys::load_private(&fs::read(key_path)?, self.passphrase.as_deref())?;
+        Ok((cert, key))
+    }
+}
+
+fn show(path: &str) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(path)?;
+    if let Ok(cert) = x509::load_cert(&data) {
+        println!("{}", CertInfo::from_cert(&cert)?);
+        return Ok(());
+    }
+    let csr = x509::load_csr(&data)?;
+    let key = csr.public_key()?;
+    println!("Subject:    {}", x509::format_name(csr.subject_name()));
+    println!("Key:        {}", keys::describe(&key)?);
+    println!(
+        "Signature:  {}",
+        if csr.verify(&key)? {
+            "valid"
+        } else {
+            "INVALID"
+        }
+    );
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 3 {
+        usage(&args[0]);
+    }
+
+    let mut options = Options::default();
+    let mut positional = Vec::new();
+
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().unwrap_or_else(|| usage(&args[0])).clone();
+        match arg.as_str() {
+            "-s" => options.sans.push(value()),
+            "-O" => options.organization = Some(value()),
+            "-n" => options.name = Some(value()),
+            "-d" => options.days = Some(value().parse()?),
+            "-k" => options.key = Some(value()),
+            "-a" => options.key_spec = Some(value().parse()?),
+            "-p" => options.passphrase = Some(passphrase_arg(&value(), "Passphrase")?),
+            "-P" => options.new_passphrase = Some(passphrase_arg(&value(), "New passphrase")?),
+            "-o" => options.output = Some(value()),
+            "-K" => options.key_output = Some(value()),
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    match (args[1].as_str(), positional.as_slice()) {
+        ("self-signed", [common_name]) => {
+            let (key, generated) = options.private_key()?;
+            let cert = x509::self_signed(&key, &options.params(common_name, x509::DEFAULT_DAYS)?)?;
+            options.write("Certificate", &cert.to_pem()?)?;
+            if generated {
+                options.write_key(&key)?;
+            }
+        }
+        ("ca", [common_name]) => {
+            let (key, generated) = options.private_key()?;
+            let cert = x509::create_ca(&key, &options.params(common_name, DEFAULT_CA_DAYS)?)?;
+            options.write("CA certificate", &cert.to_pem()?)?;
+            if generated {
+                options.write_key(&key)?;
+            }
+        }
+        ("issue", [ca_cert, ca_key, common_name]) => {
+            let (ca, ca_key) = options.load_ca(ca_cert, ca_key)?;
+            let (key, generated) = match &options.key {
+                // The passphrase belongs to the CA key, the leaf key must be unencrypted
+                Some(path) => (keys::load_private(&fs::read(path)?, None)?, false),
+                None => options.private_key()?,
+            };
+            let params = options.params(common_name, x509::DEFAULT_DAYS)?;
+            let cert = x509::issue(&ca, &ca_key, &key, &params)?;
+            options.write("Certificate", &cert.to_pem()?)?;
+            if generated {
+                options.write_key(&key)?;
+            }
+        }
+        ("csr", [common_name]) => {
+            let (key, generated) = options.private_key()?;
+            let csr = x509::create_csr(&key, &options.params(common_name, x509::DEFAULT_DAYS)?)?;
+            options.write("CSR", &csr.to_pem()?)?;
+            if generated {
+                options.write_key(&key)?;
+            }
+        }
+        ("sign", [ca_cert, ca_key, csr_path]) => {
+            let (ca, ca_key) = options.load_ca(ca_cert, ca_key)?;
+            let csr = x509::load_csr(&fs::read(csr_path)?)?;
+            let requested = options.params("", x509::DEFAULT_DAYS)?;
+            let cert = x509::sign_csr(&ca, &ca_key, &csr, &requested.sans, requested.days)?;
+            options.write("Certificate", &cert.to_pem()?)?;
+        }
+        ("show", [path]) => show(path)?,
+        ("pkcs12", [cert_path, key_path, chain @ ..]) => {
+            let Some(output) = &options.output else {
+                usage(&args[0]);
+            };
+            let cert = x509::load_cert(&fs::read(cert_path)?)?;
+            let key = keys::load_private(&fs::read(key_path)?, options.passphrase.as_deref())?;
+            let mut ca = Vec::new();
+            for path in chain {
+                ca.extend(x509::load_chain(&fs::read(path)?)?);
+            }
+            let passphrase = match &options.new_passphrase {
+                Some(passphrase) => passphrase.clone(),
+                None => passphrase_arg("-", "PKCS#12 passphrase")?,
+            };
+            let name = match &options.name {
+                Some(name) => name.clone(),
+                None => CertInfo::from_cert(&cert)?.subject,
+            };
+            let der = pkcs12::create(&name, &key, &cert, &ca, &passphrase)?;
+            fs::write(output, der)?;
+            println!("[PKCS#12] written to {output}");
+        }
+        ("extract", [path]) => {
+            let passphrase = match &options.passphrase {
+                Some(passphrase) => passphrase.clone(),
+                None => passphrase_arg("-", "PKCS#12 passphrase")?,
+            };
+            let bundle = pkcs12::parse(&fs::read(path)?, &passphrase)?;
+            options.write("Certificates", &bundle.certs_pem()?)?;
+            options.write_key(&bundle.key)?;
+        }
+        _ => usage(&args[0]),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::error::Error;
+
+use enzo_crypto::encoding::Encoding;
+use enzo_crypto::shamir::{self, Share};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    let (encoding, shares) = match args.get(1).map(String::as_str) {
+        Some("-e") if args.len() > 3 => (args[2].parse()?, &args[3..]),
+        Some(_) if args[1] != "-e" => (Encoding::Base52, &args[1..]),
+        _ => {
+            eprintln!("Usage: {} [-e base52|base64] <share> <share>...", args[0]);
+            std::process::exit(1);
+        }
+    };
+
+    let shares = shares
+        .iter()
+        .map(|text| Share::decode(text, encoding))
+        .collect::<Result<Vec<_>, _>>()?;
+    let secret = shamir::combine(&shares)?;
+
+    match String::from_utf8(secret) {
+        Ok(text) => println!("[Secret] {text}"),
+        Err(e) => println!("[Secret (hex)] {}", hex::encode(e.into_bytes())),
+    }
+
+    Ok(())
+}
@@ -1,5 +1,13 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
 use chrono::Local;
+use enzo_crypto::access::AUDIT_TARGET;
+use enzo_crypto::config::{DaemonConfig, LogConfig, LogDestination};
 use enzo_crypto::crypto::Crypto;
+use enzo_crypto::methods::MethodGroup;
 use fern::Dispatch;
 use ipc_broker::worker::WorkerBuilder;
 use log::LevelFilter;
@@ -8,7 +16,111 @@ use tokio::{
     time::Instant,
 };
 
-const TIMEOUT: u64 = 60;
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} [options]\n\n\
+         Options override the settings of the --config file, which also holds the\n\
+         access policy (client tokens and the clients allowed per method group)\n\
+         and the named master keys of the envelope methods:\n  \
+         --config <file>              JSON or TOML (.toml) configuration file\n  \
+         --object-name <name>         broker object name [applications.crypto]\n  \
+         --idle-timeout <secs|never>  shut down after this long without calls [60]\n  \
+         --log-level <level>          off, error, warn, info, debug or trace\n  \
+         --log-destination <dest>     stdout, stderr or a file path [stdout]\n  \
+         --audit-log <file>           also append denied calls to this file\n  \
+         --methods <groups>           comma-separated method groups to serve [all]:\n                               \
+         {}\n  \
+         --max-batch-size <n>         operations accepted by one batch call\n  \
+         --max-batch-bytes <n>        bytes of strings accepted in one batch call\n  \
+         --max-input-bytes <n>        bytes accepted in the input of one call\n  \
+         --threads <n>                threads for CPU-heavy methods, 0 for one per CPU\n  \
+         --queue <n>                  CPU-heavy calls allowed to wait for a thread\n  \
+         --max-jobs <n>               file jobs allowed to run at once\n  \
+         --max-kdf-memory <KiB>       KDF memory a decrypted ciphertext may ask for\n  \
+         --max-kdf-iterations <n>     PBKDF2 iterations a decrypted ciphertext may ask for\n  \
+         --max-kdf-passes <n>         Argon2id passes or scrypt p a decrypted ciphertext\n                               \
+         may ask for\n  \
+         --kdf <name>                 default KDF of scrypt-encrypt, encrypt and encrypt-file\n  \
+         --kdf-iterations <n>         default PBKDF2 iterations or Argon2id passes\n  \
+         --kdf-memory <KiB>           default Argon2id memory\n  \
+         --kdf-parallelism <n>        default Argon2id lanes or scrypt p\n  \
+         --kdf-cost <n>               default scrypt N\n  \
+         --base-dir <dir>             directory file paths of calls are relative to;\n                               \
+         file methods are refused without one\n  \
+         --print-config               print the resulting configuration as JSON, client\n                               \
+         tokens redacted, and exit",
+        MethodGroup::ALL.map(|group| group.name()).join(", ")
+    );
+    std::process::exit(1);
+}
+
+/// Value of `option`, exiting when it is missing or does not parse
+fn value<T>(option: &str, value: Option<String>) -> T
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    let Some(value) = value else {
+        eprintln!("Missing value for {option}");
+        std::process::exit(1);
+    };
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("Invalid value for {option}: {e}");
+        std::process::exit(1);
+    })
+}
+
+/// The --config file, if any, with the other flags applied on top
+fn parse_config(program: &str, args: Vec<String>) -> Result<(DaemonConfig, bool), Box<dyn Error>> {
+    let mut config = DaemonConfig::default();
+    let mut overrides = Vec::new();
+    let mut print = false;
+    let mut options = args.into_iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--config" => config = DaemonConfig::load(&value::<PathBuf>(&option, options.next()))?,
+            "--print-config" => print = true,
+            "-h" | "--help" => usage(program),
+            _ => overrides.push((option, options.next())),
+        }
+    }
+    for (option, arg) in overrides {
+        match option.as_str() {
+            "--object-name" => config.object_name = value(&option, arg),
+            "--idle-timeout" => config.idle_timeout = value(&option, arg),
+            "--log-level" => config.log.level = Some(value(&option, arg)),
+            "--log-destination" => config.log.destination = value(&option, arg),
+            "--audit-log" => config.log.audit = Some(value(&option, arg)),
+            "--methods" => {
+                config.methods = value::<String>(&option, arg)
+                    .split(',')
+                    .map(|group| group.trim().parse())
+                    .collect::<Result<_, _>>()?
+            }
+            "--max-batch-size" => config.limits.max_batch_size = value(&option, arg),
+            "--max-batch-bytes" => config.limits.max_batch_bytes = value(&option, arg),
+            "--max-input-bytes" => config.limits.max_input_bytes = value(&option, arg),
+            "--threads" => config.limits.threads = value(&option, arg),
+            "--queue" => config.limits.queue = value(&option, arg),
+            "--max-jobs" => config.limits.max_jobs = value(&option, arg),
+            "--max-kdf-memory" => config.limits.max_kdf_memory = value(&option, arg),
+            "--max-kdf-iterations" => config.limits.max_kdf_iterations = value(&option, arg),
+            "--max-kdf-passes" => config.limits.max_kdf_passes = value(&option, arg),
+            "--kdf" => config.kdf.algorithm = Some(value(&option, arg)),
+            "--kdf-iterations" => config.kdf.iterations = Some(value(&option, arg)),
+            "--kdf-memory" => config.kdf.memory = Some(value(&option, arg)),
+            "--kdf-parallelism" => config.kdf.parallelism = Some(value(&option, arg)),
+            "--kdf-cost" => config.kdf.cost = Some(value(&option, arg)),
+            "--base-dir" => config.files.base_dir = Some(value(&option, arg)),
+            _ => {
+                eprintln!("Unknown option: {option}");
+                usage(program);
+            }
+        }
+    }
+    config.validate()?;
+    Ok((config, print))
+}
 
 fn logging_level() -> LevelFilter {
     // 1. Check for debug files near executable
@@ -35,10 +147,10 @@ fn logging_level() -> LevelFilter {
 struct LogHandler;
 
 impl LogHandler {
-    fn start() -> Self {
-        let level_filter = logging_level();
+    fn start(config: &LogConfig) -> Result<Self, Box<dyn Error>> {
+        let level_filter = config.level.unwrap_or_else(logging_level);
 
-        if let Err(e) = Dispatch::new()
+        let dispatch = Dispatch::new()
             .format(move |out, message, record| {
                 let file = record.file().unwrap_or("unknown_file");
                 let line = record.line().map_or(0, |l| l);
@@ -69,17 +181,39 @@ impl LogHandler {
                     }
                 }
             })
-            .level(level_filter)
-            .chain(std::io::stdout())
-            .apply()
-        {
+            .level(level_filter);
+        let dispatch = match &config.destination {
+            LogDestination::Stdout => dispatch.chain(std::io::stdout()),
+            LogDestination::Stderr => dispatch.chain(std::io::stderr()),
+            LogDestination::File(path) => dispatch.chain(fern::log_file(path)?),
+        };
+        // The audit log sits next to the main output rather than under it, so a
+        // quieter --log-level does not drop denials
+        let dispatch = match &config.audit {
+            Some(path) => Dispatch::new().chain(dispatch).chain(
+                Dispatch::new()
+                    .format(|out, message, record| {
+                        out.finish(format_args!(
+                            "[{}][{}]: {}",
+                            Local::now().format("%b-%d-%Y %H:%M:%S.%f"),
+                            record.level(),
+                            message,
+                        ))
+                    })
+                    .level(LevelFilter::Info)
+                    .filter(|metadata| metadata.target() == AUDIT_TARGET)
+                    .chain(fern::log_file(path)?),
+            ),
+            None => dispatch,
+        };
+        if let Err(e) = dispatch.apply() {
             log::error!("Logger initialization failed: {e:?}");
         }
         let name = env!("CARGO_PKG_NAME");
         let version = env!("CARGO_PKG_VERSION");
         log::info!("{name} {version} has started...");
         log::debug!("Enabled log {level_filter}.");
-        Self
+        Ok(Self)
     }
 }
 impl Drop for LogHandler {
@@ -92,18 +226,39 @@ impl Drop for LogHandler {
 }
 // replace broken tail with a proper async main
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let logger = LogHandler::start();
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args();
+    let program = args.next().unwrap_or_else(|| "crypto".to_string());
+    let (config, print) = parse_config(&program, args.collect())?;
+    if print {
+        println!("{}", serde_json::to_string_pretty(&config)?);
+        return Ok(());
+    }
+
+    let logger = LogHandler::start(&config.log)?;
+    log::info!(
+        "Serving {} as {} (idle timeout: {}).",
+        config
+            .methods
+            .iter()
+            .map(|group| group.name())
+            .collect::<Vec<_>>()
+            .join(", "),
+        config.object_name,
+        config.idle_timeout
+    );
     let (activity_tx, activity_rx) = unbounded_channel();
+    let object_name = config.object_name.clone();
+    let timeout = config.idle_timeout.duration();
 
     let (builder, shutdown) = WorkerBuilder::new()
-        .add("applications.crypto", Crypto::new(activity_tx))
+        .add(&object_name, Crypto::with_config(activity_tx, config))
         .with_graceful_shutdown();
 
     let handle = tokio::spawn(async move { builder.spawn().await });
 
     tokio::spawn(async move {
-        run_with_inactivity_timeout(activity_rx).await;
+        run_with_inactivity_timeout(activity_rx, timeout).await;
         let _ = shutdown.send(true);
     });
 
@@ -112,8 +267,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-async fn run_with_inactivity_timeout(mut activity_rx: mpsc::UnboundedReceiver<()>) {
-    let timeout = std::time::Duration::from_secs(TIMEOUT);
+/// Wait for Ctrl-C, or for `timeout` to pass without activity unless it is `None`
+async fn run_with_inactivity_timeout(
+    mut activity_rx: mpsc::UnboundedReceiver<()>,
+    timeout: Option<Duration>,
+) {
     let mut last_activity = Instant::now();
 
     loop {
@@ -123,8 +281,8 @@ async fn run_with_inactivity_timeout(mut activity_rx: mpsc::UnboundedReceiver<()
                 break;
             }
 
-            _ = tokio::time::sleep_until(last_activity + timeout) => {
-                log::warn!("No activity for {TIMEOUT} seconds, shutting down");
+            Some(timeout) = idle(last_activity, timeout) => {
+                log::warn!("No activity for {} seconds, shutting down", timeout.as_secs());
                 break;
             }
 
@@ -142,3 +300,15 @@ async fn run_with_inactivity_timeout(mut activity_rx: mpsc::UnboundedReceiver<()
         }
     }
 }
+
+/// Completes with `timeout` once it passed since `last_activity`; never completes
+/// without a timeout
+async fn idle(last_activity: Instant, timeout: Option<Duration>) -> Option<Duration> {
+    match timeout {
+        Some(timeout) => {
+            tokio::time::sleep_until(last_activity + timeout).await;
+            Some(timeout)
+        }
+        None => std::future::pending().await,
+    }
+}
@@ -1,4 +1,5 @@
-use enzo_crypto::decrypt;
+use base64::{Engine as _, engine::general_purpose};
+use enzo_crypto::{aead, decrypt, scrypt};
 use std::{borrow::Cow, error::Error}; // assuming your crate name is enzo_crypto
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -12,7 +13,15 @@ fn main() -> Result<(), Box<dyn Error>> {
     let ciphertext_b64 = &args[1];
     let password = &args[2];
 
-    let decrypted = decrypt(Cow::Borrowed(ciphertext_b64), Cow::Borrowed(password))?;
+    // The output of encrypt starts with a cipher header, the legacy format does not
+    let sealed = general_purpose::STANDARD.decode(ciphertext_b64)?;
+    let decrypted = if aead::decode_header(&sealed)?.is_some() {
+        let plaintext =
+            scrypt::decrypt_base64(Cow::Borrowed(ciphertext_b64), Cow::Borrowed(password))?;
+        Cow::Owned(String::from_utf8(plaintext)?)
+    } else {
+        decrypt(Cow::Borrowed(ciphertext_b64), Cow::Borrowed(password))?
+    };
     println!("[Decrypted Text] {decrypted}");
 
     Ok(())
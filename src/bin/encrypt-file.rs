@@ -2,7 +2,8 @@ use std::io::{Write, stdout};
 use std::time::Duration;
 use std::{error::Error, path::PathBuf}; // assuming your crate name is enzo_crypto
 
-use enzo_crypto::{async_file_gcm, file_gcm::encrypt_file};
+use enzo_crypto::aead::AeadCipher;
+use enzo_crypto::{async_file_gcm, file_gcm, kdf};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -10,7 +11,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     if args.len() < 4 {
         eprintln!(
-            "Usage: {} <input path> <output path> <password> [--progress]",
+            "Usage: {} <input path> <output path> <password> [--progress] [--kdf <name>] [--cipher aes-256-gcm|chacha20-poly1305|xchacha20-poly1305]",
             args[0]
         );
         std::process::exit(1);
@@ -19,16 +20,33 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let input_path = PathBuf::from(&args[1]);
     let output_path = PathBuf::from(&args[2]);
     let password = args[3].to_string();
-    let progress = if args.len() > 4 {
-        if &args[4] == "--progress" {
-            true
-        } else {
-            eprintln!("Unknown option: {}", args[4]);
-            std::process::exit(1);
+    let mut progress = false;
+    let mut kdf = None;
+    let mut cipher: Option<AeadCipher> = None;
+    let mut options = args[4..].iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--progress" => progress = true,
+            "--kdf" => match options.next() {
+                Some(name) => kdf = Some(kdf::password_kdf(name)?),
+                None => {
+                    eprintln!("Missing value for --kdf");
+                    std::process::exit(1);
+                }
+            },
+            "--cipher" => match options.next() {
+                Some(name) => cipher = Some(name.parse()?),
+                None => {
+                    eprintln!("Missing value for --cipher");
+                    std::process::exit(1);
+                }
+            },
+            _ => {
+                eprintln!("Unknown option: {option}");
+                std::process::exit(1);
+            }
         }
-    } else {
-        false
-    };
+    }
 
     // Check if input path exists and is a file
     if !input_path.exists() {
@@ -54,14 +72,42 @@ async fn main() -> Result<(), Box<dyn Error>> {
             let plain = input_path.clone();
             let enc = output_path.clone();
             async move {
-                async_file_gcm::encrypt_file(
-                    &plain,
-                    &enc,
-                    &password,
-                    tx_progress,
-                    Duration::from_millis(500),
-                )
-                .await
+                match (kdf, cipher) {
+                    (kdf, Some(cipher)) => {
+                        let kdf = kdf.unwrap_or_else(|| Box::new(kdf::Scrypt::default()));
+                        async_file_gcm::encrypt_file_with_cipher(
+                            &plain,
+                            &enc,
+                            &password,
+                            kdf.as_ref(),
+                            cipher,
+                            tx_progress,
+                            Duration::from_millis(500),
+                        )
+                        .await
+                    }
+                    (Some(kdf), None) => {
+                        async_file_gcm::encrypt_file_with_kdf(
+                            &plain,
+                            &enc,
+                            &password,
+                            kdf.as_ref(),
+                            tx_progress,
+                            Duration::from_millis(500),
+                        )
+                        .await
+                    }
+                    (None, None) => {
+                        async_file_gcm::encrypt_file(
+                            &plain,
+                            &enc,
+                            &password,
+                            tx_progress,
+                            Duration::from_millis(500),
+                        )
+                        .await
+                    }
+                }
             }
         });
         while let Some(p) = rx_progress.recv().await {
@@ -76,7 +122,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
         enc_task.await??;
         println!(); // move to new line at the end
     } else {
-        encrypt_file(input_path.as_path(), output_path.as_path(), &password)?;
+        match (kdf, cipher) {
+            (kdf, Some(cipher)) => file_gcm::encrypt_file_with_cipher(
+                input_path.as_path(),
+                output_path.as_path(),
+                &password,
+                kdf.unwrap_or_else(|| Box::new(kdf::Scrypt::default()))
+                    .as_ref(),
+                cipher,
+            )?,
+            (Some(kdf), None) => file_gcm::encrypt_file_with_kdf(
+                input_path.as_path(),
+                output_path.as_path(),
+                &password,
+                kdf.as_ref(),
+            )?,
+            (None, None) => {
+                file_gcm::encrypt_file(input_path.as_path(), output_path.as_path(), &password)?
+            }
+        }
     }
     println!(
         "Encryption successful. Encrypted file saved to: {}",
@@ -1,4 +1,6 @@
-use enzo_crypto::encrypt;
+use enzo_crypto::aead::AeadCipher;
+use enzo_crypto::kdf::Scrypt;
+use enzo_crypto::scrypt::encrypt_base64_with_cipher;
 use std::{borrow::Cow, error::Error}; // assuming your crate name is enzo_crypto
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -12,7 +14,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     let plaintext = &args[1];
     let password = &args[2];
 
-    let encrypted = encrypt(Cow::Borrowed(plaintext), Cow::Borrowed(password))?;
+    let encrypted = encrypt_base64_with_cipher(
+        plaintext.as_bytes(),
+        Cow::Borrowed(password),
+        &Scrypt::default(),
+        AeadCipher::Aes256Gcm,
+    )?;
     println!("[Encrypted Text] {encrypted}");
 
     Ok(())
@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::path::Path;
+
+use enzo_crypto::encoding::Encoding;
+use enzo_crypto::hash::{self, HashAlgorithm};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} [-a <algorithm>] [-k <hmac key>] [-e hex|base64|base52] <file or string>...\n       \
+         {program} [-a <algorithm>] -c <manifest>\n\n\
+         Algorithms: {}",
+        HashAlgorithm::ALL.map(|alg| alg.name()).join(", ")
+    );
+    std::process::exit(1);
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut algorithm = HashAlgorithm::Sha256;
+    let mut encoding = Encoding::Hex;
+    let mut key: Option<String> = None;
+    let mut manifest: Option<String> = None;
+    let mut inputs = Vec::new();
+
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-a" => algorithm = iter.next().unwrap_or_else(|| usage(&args[0])).parse()?,
+            "-e" => encoding = iter.next().unwrap_or_else(|| usage(&args[0])).parse()?,
+            "-k" => key = Some(iter.next().unwrap_or_else(|| usage(&args[0])).clone()),
+            "-c" => manifest = Some(iter.next().unwrap_or_else(|| usage(&args[0])).clone()),
+            _ => inputs.push(arg.clone()),
+        }
+    }
+
+    if let Some(manifest) = manifest {
+        let manifest = Path::new(&manifest);
+        let base_dir = manifest.parent().unwrap_or(Path::new("."));
+        let entries = hash::verify_manifest(algorithm, manifest, base_dir)?;
+        let failed = entries.iter().filter(|entry| !entry.ok).count();
+        for entry in &entries {
+            let status = if entry.ok { "OK" } else { "FAILED" };
+            println!("{}: {status}", entry.path.display());
+        }
+        if failed > 0 {
+            eprintln!(
+                "WARNING: {failed} of {} computed checksums did NOT match",
+                entries.len()
+            );
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if inputs.is_empty() {
+        usage(&args[0]);
+    }
+
+    for input in &inputs {
+        let path = Path::new(input);
+        // Same rule as util::data_source: real files are streamed, anything else is hashed literally
+        let digest = match (&key, path.is_file()) {
+            (Some(key), true) => hash::hmac_file(algorithm, key.as_bytes(), path)?,
+            (Some(key), false) => hash::hmac(algorithm, key.as_bytes(), input.as_bytes())?,
+            (None, true) => hash::hash_file(algorithm, path)?,
+            (None, false) => hash::hash(algorithm, input.as_bytes())?,
+        };
+        println!("{}  {input}", encoding.encode(&digest));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::time::Duration;
+
+use enzo_crypto::calibrate::{self, Measurement};
+
+const DEFAULT_TARGET_MS: u64 = 250;
+const DEFAULT_MAX_MEMORY_MB: u64 = 64;
+
+fn print_row<K>(name: &str, params: String, m: &Measurement<K>) {
+    println!(
+        "{:<15} {:<32} {:>10} {:>10}",
+        name,
+        params,
+        format!("{} KiB", m.memory / 1024),
+        format!("{} ms", m.duration.as_millis())
+    );
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() > 3 {
+        eprintln!("Usage: {} [target ms] [max memory MB]", args[0]);
+        std::process::exit(1);
+    }
+
+    let target_ms = match args.get(1) {
+        Some(arg) => arg.parse::<u64>()?,
+        None => DEFAULT_TARGET_MS,
+    };
+    let max_memory_mb = match args.get(2) {
+        Some(arg) => arg.parse::<u64>()?,
+        None => DEFAULT_MAX_MEMORY_MB,
+    };
+
+    println!("Calibrating for {target_ms} ms per derivation, at most {max_memory_mb} MB memory...");
+    let c = calibrate::calibrate(
+        Duration::from_millis(target_ms),
+        max_memory_mb * 1024 * 1024,
+    )?;
+
+    println!();
+    println!(
+        "{:<15} {:<32} {:>10} {:>10}",
+        "KDF", "Parameters", "Memory", "Time"
+    );
+    print_row(
+        "scrypt",
+        format!(
+            "N={} r={} p={}",
+            c.scrypt.kdf.n, c.scrypt.kdf.r, c.scrypt.kdf.p
+        ),
+        &c.scrypt,
+    );
+    print_row(
+        "pbkdf2-sha256",
+        format!("iterations={}", c.pbkdf2_sha256.kdf.iterations),
+        &c.pbkdf2_sha256,
+    );
+    print_row(
+        "pbkdf2-sha512",
+        format!("iterations={}", c.pbkdf2_sha512.kdf.iterations),
+        &c.pbkdf2_sha512,
+    );
+    print_row(
+        "argon2id",
+        format!(
+            "t={} m={}KiB p={}",
+            c.argon2id.kdf.iterations, c.argon2id.kdf.memory_kib, c.argon2id.kdf.lanes
+        ),
+        &c.argon2id,
+    );
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::fs;
+
+use enzo_crypto::keys::{self, KeyFormat, KeySpec};
+use enzo_crypto::util::passphrase_arg;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} generate [rsa-2048|rsa-3072|rsa-4096|ec-p256|ec-p384] [options]\n       \
+         {program} convert <key file> [--public] [options]\n       \
+         {program} fingerprint <key file> [-p <passphrase>]\n\n\
+         Options:\n  \
+         -f <format>      pem (PKCS#8/SPKI), der, pkcs1, pkcs1-der or jwk\n  \
+         -p <passphrase>  passphrase of the input key, or of the generated key (- to read stdin)\n  \
+         -P <passphrase>  passphrase protecting the converted private key (- to read stdin)\n  \
+         -o <file>        write the key to a file instead of stdout"
+    );
+    std::process::exit(1);
+}
+
+fn write_key(
+    data: &[u8],
+    format: KeyFormat,
+    output: Option<&String>,
+) -> Result<(), Box<dyn Error>> {
+    match output {
+        Some(path) => {
+            fs::write(path, data)?;
+            println!("[Key] written to {path}");
+        }
+        None => print!("{}", keys::encode_text(data, format)?),
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 {
+        usage(&args[0]);
+    }
+
+    let mut format = KeyFormat::Pem;
+    let mut passphrase: Option<String> = None;
+    let mut new_passphrase: Option<String> = None;
+    let mut output: Option<String> = None;
+    let mut public = false;
+    let mut positional = Vec::new();
+
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-f" => format = iter.next().unwrap_or_else(|| usage(&args[0])).parse()?,
+            "-p" => {
+                let value = iter.next().unwrap_or_else(|| usage(&args[0]));
+                passphrase = Some(passphrase_arg(value, "Passphrase")?);
+            }
+            "-P" => {
+                let value = iter.next().unwrap_or_else(|| usage(&args[0]));
+                new_passphrase = Some(passphrase_arg(value, "New passphrase")?);
+            }
+            "-o" => output = Some(iter.next().unwrap_or_else(|| usage(&args[0])).clone()),
+            "--public" => public = true,
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    match (args[1].as_str(), positional.as_slice()) {
+        ("generate", [] | [_]) => {
+            let spec = match positional.first() {
+                Some(spec) => spec.parse()?,
+                None => KeySpec::default(),
+            };
+            let key = keys::generate(spec)?;
+            eprintln!("[Fingerprint] {}", keys::fingerprint(&key)?);
+            let data = keys::export_private(&key, format, passphrase.as_deref())?;
+            write_key(&data, format, output.as_ref())?;
+        }
+        ("convert", [path]) => {
+            let data = fs::read(path)?;
+            let converted = if public {
+                let key = keys::load_public(&data, passphrase.as_deref())?;
+                keys::export_public(&key, format)?
+            } else {
+                let key = keys::load_private(&data, passphrase.as_deref())?;
+                keys::export_private(&key, format, new_passphrase.as_deref())?
+            };
+            write_key(&converted, format, output.as_ref())?;
+        }
+        ("fingerprint", [path]) => {
+            let key = keys::load_public(&fs::read(path)?, passphrase.as_deref())?;
+            let jwk = keys::Jwk::public_from(&key)?;
+            println!("[Key] {}", keys::describe(&key)?);
+            println!("[SHA256] {}", keys::fingerprint(&key)?);
+            println!("[JWK Thumbprint] {}", jwk.thumbprint()?);
+        }
+        _ => usage(&args[0]),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::path::Path;
+
+use enzo_crypto::encoding::Encoding;
+use enzo_crypto::keywrap;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} wrap <kek> <key> [--pad] [-e hex|base64|base52]\n       \
+         {program} unwrap <kek> <wrapped key> [--pad] [-e hex|base64|base52]\n\n\
+         Keys are given in the chosen encoding (hex by default), or as files with raw bytes.\n\
+         --pad selects RFC 5649 wrapping with padding for keys of any length."
+    );
+    std::process::exit(1);
+}
+
+/// Raw bytes of an existing file, otherwise the argument decoded as text
+fn key_source(arg: &str, encoding: Encoding) -> Result<Vec<u8>, Box<dyn Error>> {
+    let path = Path::new(arg);
+    if path.is_file() {
+        Ok(std::fs::read(path)?)
+    } else {
+        Ok(encoding.decode(arg)?)
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 4 {
+        usage(&args[0]);
+    }
+
+    let mut encoding = Encoding::Hex;
+    let mut padding = false;
+    let mut positional = Vec::new();
+
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--pad" => padding = true,
+            "-e" => encoding = iter.next().unwrap_or_else(|| usage(&args[0])).parse()?,
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let [kek, input] = positional.as_slice() else {
+        usage(&args[0]);
+    };
+    let kek = key_source(kek, encoding)?;
+    let input = key_source(input, encoding)?;
+
+    match (args[1].as_str(), padding) {
+        ("wrap", false) => println!(
+            "[Wrapped] {}",
+            encoding.encode(&keywrap::wrap(&kek, &input)?)
+        ),
+        ("wrap", true) => println!(
+            "[Wrapped] {}",
+            encoding.encode(&keywrap::wrap_with_padding(&kek, &input)?)
+        ),
+        ("unwrap", false) => println!("[Key] {}", encoding.encode(&keywrap::unwrap(&kek, &input)?)),
+        ("unwrap", true) => println!(
+            "[Key] {}",
+            encoding.encode(&keywrap::unwrap_with_padding(&kek, &input)?)
+        ),
+        _ => usage(&args[0]),
+    }
+
+    Ok(())
+}
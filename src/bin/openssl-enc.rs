@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use enzo_crypto::kdf::{self, Kdf, Scrypt};
+use enzo_crypto::openssl_enc::{self, DEFAULT_PBKDF2_ITERATIONS, EncDigest, EncKdf, EncOptions};
+use enzo_crypto::util::passphrase_arg;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} encrypt <input> <output> <password> [options]\n       \
+         {program} decrypt <input> <output> <password> [options]\n       \
+         {program} migrate <input> <output> <password> [options] [--kdf <name>]\n\n\
+         Reads and writes files of `openssl enc -salt`; migrate re-encrypts them\n\
+         into the GCM file format with the same password (- reads it from stdin).\n\n\
+         Options:\n  \
+         -c <cipher>   aes-128-cbc, aes-192-cbc or aes-256-cbc (default)\n  \
+         -md <digest>  md5, sha1, sha256 (default) or sha512\n  \
+         -iter <n>     PBKDF2 iterations (default 10000)\n  \
+         -legacy       EVP_BytesToKey instead of PBKDF2 (digest defaults to md5)\n  \
+         -a            base64 text as written by `openssl enc -a`\n  \
+         --kdf <name>  KDF of the migrated GCM file (default scrypt)"
+    );
+    std::process::exit(1);
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 5 {
+        usage(&args[0]);
+    }
+
+    let mut options = EncOptions::default();
+    let mut digest: Option<EncDigest> = None;
+    let mut iterations = DEFAULT_PBKDF2_ITERATIONS;
+    let mut legacy = false;
+    let mut base64 = false;
+    let mut gcm_kdf: Box<dyn Kdf> = Box::new(Scrypt::default());
+    let mut positional = Vec::new();
+
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().unwrap_or_else(|| usage(&args[0]));
+        match arg.as_str() {
+            "-c" => options.cipher = value().parse()?,
+            "-md" => digest = Some(value().parse()?),
+            "-iter" => iterations = value().parse()?,
+            "-legacy" => legacy = true,
+            "-a" => base64 = true,
+            "--kdf" => gcm_kdf = kdf::password_kdf(value())?,
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let [input, output, password] = positional.as_slice() else {
+        usage(&args[0]);
+    };
+    let password = passphrase_arg(password, "Password")?;
+    options.kdf = if legacy {
+        EncKdf::BytesToKey(digest.unwrap_or(EncDigest::Md5))
+    } else {
+        EncKdf::Pbkdf2 {
+            digest: digest.unwrap_or(EncDigest::Sha256),
+            iterations,
+        }
+    };
+    let (input, output) = (Path::new(input), Path::new(output));
+
+    match (args[1].as_str(), base64) {
+        ("encrypt", false) => openssl_enc::encrypt_file(input, output, &password, &options)?,
+        ("encrypt", true) => {
+            let encoded = openssl_enc::encrypt_base64(
+                &fs::read(input)?,
+                Cow::Borrowed(password.as_str()),
+                &options,
+            )?;
+            fs::write(output, encoded.as_bytes())?;
+        }
+        ("decrypt", false) => openssl_enc::decrypt_file(input, output, &password, &options)?,
+        ("decrypt", true) => {
+            let plaintext = openssl_enc::decrypt_base64(
+                Cow::Owned(fs::read_to_string(input)?),
+                Cow::Borrowed(password.as_str()),
+                &options,
+            )?;
+            fs::write(output, plaintext)?;
+        }
+        ("migrate", false) => {
+            openssl_enc::migrate_to_gcm(input, output, &password, &options, gcm_kdf.as_ref())?
+        }
+        _ => usage(&args[0]),
+    }
+    let label = match args[1].as_str() {
+        "encrypt" => "Encrypted",
+        "decrypt" => "Decrypted",
+        _ => "Migrated",
+    };
+    println!("[{label}] {}", output.display());
+
+    Ok(())
+}
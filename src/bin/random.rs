@@ -0,0 +1,65 @@
+use std::error::Error;
+use std::path::Path;
+
+use enzo_crypto::encoding::Encoding;
+use enzo_crypto::random::{self, CharClasses};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {program} bytes [length] [hex|base64|base52]\n       \
+         {program} token [length]\n       \
+         {program} uuid\n       \
+         {program} password [length] [lower,upper,digits,symbols,no-ambiguous]\n       \
+         {program} passphrase <wordlist file> [words] [separator]"
+    );
+    std::process::exit(1);
+}
+
+fn parse_or<T: std::str::FromStr>(arg: Option<&String>, default: T) -> Result<T, T::Err> {
+    match arg {
+        Some(arg) => arg.parse(),
+        None => Ok(default),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() < 2 || args.len() > 5 {
+        usage(&args[0]);
+    }
+
+    match args[1].as_str() {
+        "bytes" => {
+            let length = parse_or(args.get(2), 32usize)?;
+            let encoding = parse_or(args.get(3), Encoding::Hex)?;
+            println!("[Random] {}", random::random_encoded(length, encoding)?);
+        }
+        "token" => {
+            let length = parse_or(args.get(2), 32usize)?;
+            println!("[Token] {}", random::token(length)?);
+        }
+        "uuid" => println!("[UUID] {}", random::uuid_v4()?),
+        "password" => {
+            let length = parse_or(args.get(2), 20usize)?;
+            let classes = parse_or(args.get(3), CharClasses::default())?;
+            let secret = random::password(length, &classes)?;
+            println!("[Password] {}", secret.value);
+            println!("[Entropy] {:.1} bits", secret.entropy_bits);
+        }
+        "passphrase" => {
+            let Some(wordlist) = args.get(2) else {
+                usage(&args[0]);
+            };
+            let wordlist = random::load_wordlist(Path::new(wordlist))?;
+            let words = parse_or(args.get(3), 6usize)?;
+            let separator = args.get(4).map_or("-", String::as_str);
+            let secret = random::passphrase(words, &wordlist, separator)?;
+            println!("[Passphrase] {}", secret.value);
+            println!("[Entropy] {:.1} bits", secret.entropy_bits);
+        }
+        _ => usage(&args[0]),
+    }
+
+    Ok(())
+}
@@ -1,18 +1,41 @@
-use enzo_crypto::{self, scrypt};
+use enzo_crypto::{self, aead::AeadCipher, kdf, scrypt};
 use std::{borrow::Cow, error::Error};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = std::env::args().collect();
 
-    if args.len() != 3 {
-        eprintln!("Usage: {} <plaintext> <password>", args[0]);
+    if !(3..=5).contains(&args.len()) {
+        eprintln!(
+            "Usage: {} <plaintext> <password> [scrypt|pbkdf2-sha256|pbkdf2-sha512|argon2id] \
+             [aes-256-gcm|chacha20-poly1305|xchacha20-poly1305]",
+            args[0]
+        );
         std::process::exit(1);
     }
 
     let plaintext = &args[1];
     let password = &args[2];
 
-    let base64_cipher_text = scrypt::encrypt_base64(plaintext.as_bytes(), Cow::Borrowed(password))?;
+    let base64_cipher_text = match (args.get(3), args.get(4)) {
+        (Some(name), Some(cipher)) => {
+            let kdf = kdf::password_kdf(name)?;
+            scrypt::encrypt_base64_with_cipher(
+                plaintext.as_bytes(),
+                Cow::Borrowed(password),
+                kdf.as_ref(),
+                cipher.parse::<AeadCipher>()?,
+            )?
+        }
+        (Some(name), None) => {
+            let kdf = kdf::password_kdf(name)?;
+            scrypt::encrypt_base64_with_kdf(
+                plaintext.as_bytes(),
+                Cow::Borrowed(password),
+                kdf.as_ref(),
+            )?
+        }
+        _ => scrypt::encrypt_base64(plaintext.as_bytes(), Cow::Borrowed(password))?,
+    };
     println!("[Encrypted Text] {base64_cipher_text}");
 
     Ok(())
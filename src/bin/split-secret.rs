@@ -0,0 +1,31 @@
+use std::error::Error;
+
+use enzo_crypto::encoding::Encoding;
+use enzo_crypto::shamir;
+use enzo_crypto::util::data_source;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.len() != 4 && args.len() != 5 {
+        eprintln!(
+            "Usage: {} <threshold> <shares> <secret or file> [base52|base64]",
+            args[0]
+        );
+        std::process::exit(1);
+    }
+
+    let threshold: u8 = args[1].parse()?;
+    let count: u8 = args[2].parse()?;
+    let secret = data_source(&args[3])?;
+    let encoding = match args.get(4) {
+        Some(name) => name.parse()?,
+        None => Encoding::Base52,
+    };
+
+    for share in shamir::split(&secret, threshold, count)? {
+        println!("[Share {}] {}", share.index, share.encode(encoding)?);
+    }
+
+    Ok(())
+}
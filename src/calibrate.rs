@@ -0,0 +1,212 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+
+use crate::kdf::{self, Argon2id, Kdf, Pbkdf2, Scrypt};
+
+const BENCH_PASSWORD: &[u8] = b"enzo-crypto calibration";
+const BENCH_SALT: &[u8] = b"calibration salt";
+const BENCH_KEY_LEN: usize = 32;
+
+// scrypt search range (N = 2^log_n), up to the largest N headers accept
+const SCRYPT_MIN_LOG_N: u32 = 10;
+const SCRYPT_MAX_LOG_N: u32 = kdf::SCRYPT_MAX_N.ilog2();
+const SCRYPT_R: u64 = 8;
+const SCRYPT_P: u64 = 1;
+
+// PBKDF2 probe size and floor
+const PBKDF2_PROBE_ITERATIONS: u32 = 10_000;
+const PBKDF2_MIN_ITERATIONS: u32 = 1_000;
+
+// Argon2id memory floor (8 MiB); the ceiling is the one headers accept
+const ARGON2_MIN_MEMORY_KIB: u32 = 8 * 1024;
+const ARGON2_LANES: u32 = 1;
+
+/// Parameters picked for one KDF together with the measured cost on this machine
+pub struct Measurement<K> {
+    pub kdf: K,
+    pub duration: Duration,
+    pub memory: u64,
+}
+
+/// Recommended parameters for every password KDF
+pub struct Calibration {
+    pub scrypt: Measurement<Scrypt>,
+    pub pbkdf2_sha256: Measurement<Pbkdf2>,
+    pub pbkdf2_sha512: Measurement<Pbkdf2>,
+    pub argon2id: Measurement<Argon2id>,
+}
+
+/// Time a single key derivation
+pub fn measure(kdf: &dyn Kdf) -> Result<Duration> {
+    let mut key = [0u8; BENCH_KEY_LEN];
+    let start = Instant::now();
+    kdf.derive(BENCH_PASSWORD, BENCH_SALT, &mut key)?;
+    Ok(start.elapsed())
+}
+
+/// Memory needed by OpenSSL scrypt: 128 * r * (N + 2 + p) bytes
+pub fn scrypt_memory(kdf: &Scrypt) -> u64 {
+    128 * kdf.r * (kdf.n + 2 + kdf.p)
+}
+
+/// Largest scrypt N (r = 8, p = 1) that fits in `max_memory` bytes and
+/// derives a key in at most `target_duration`. Falls back to N = 2^10 when
+/// even that exceeds the target.
+pub fn calibrate_scrypt(target_duration: Duration, max_memory: u64) -> Result<Measurement<Scrypt>> {
+    let scrypt = |log_n: u32| Scrypt {
+        n: 1 << log_n,
+        r: SCRYPT_R,
+        p: SCRYPT_P,
+        max_mem: max_memory,
+    };
+
+    let mut best = scrypt(SCRYPT_MIN_LOG_N);
+    if scrypt_memory(&best) > max_memory {
+        return Err(anyhow!(
+            "max_memory is below the scrypt minimum of {} bytes",
+            scrypt_memory(&best)
+        ));
+    }
+    let mut best_duration = measure(&best)?;
+
+    let mut log_n = SCRYPT_MIN_LOG_N + 1;
+    // Time roughly doubles with N, stop before a step would overshoot
+    while log_n <= SCRYPT_MAX_LOG_N && best_duration * 2 <= target_duration {
+        let candidate = scrypt(log_n);
+        if scrypt_memory(&candidate) > max_memory {
+            break;
+        }
+        let duration = measure(&candidate)?;
+        if duration > target_duration {
+            break;
+        }
+        best = candidate;
+        best_duration = duration;
+        log_n += 1;
+    }
+
+    Ok(Measurement {
+        memory: scrypt_memory(&best),
+        kdf: best,
+        duration: best_duration,
+    })
+}
+
+/// PBKDF2 iteration count reaching `target_duration`, extrapolated from a probe run
+pub fn calibrate_pbkdf2(sha512: bool, target_duration: Duration) -> Result<Measurement<Pbkdf2>> {
+    let probe = Pbkdf2 {
+        sha512,
+        iterations: PBKDF2_PROBE_ITERATIONS,
+    };
+    let probe_duration = measure(&probe)?.max(Duration::from_micros(1));
+
+    let scale = target_duration.as_secs_f64() / probe_duration.as_secs_f64();
+    let iterations = (PBKDF2_PROBE_ITERATIONS as f64 * scale).clamp(
+        PBKDF2_MIN_ITERATIONS as f64,
+        kdf::PBKDF2_MAX_ITERATIONS as f64,
+    ) as u32;
+
+    let kdf = Pbkdf2 { sha512, iterations };
+    let duration = measure(&kdf)?;
+    Ok(Measurement {
+        kdf,
+        duration,
+        memory: 0,
+    })
+}
+
+/// Argon2id parameters following RFC 9106 guidance: use as much memory as
+/// allowed, then add passes until `target_duration` is reached.
+pub fn calibrate_argon2id(
+    target_duration: Duration,
+    max_memory: u64,
+) -> Result<Measurement<Argon2id>> {
+    let max_kib = (max_memory / 1024).min(kdf::ARGON2_MAX_MEMORY_KIB as u64) as u32;
+    if max_kib < ARGON2_MIN_MEMORY_KIB {
+        return Err(anyhow!(
+            "max_memory is below the Argon2id minimum of {} bytes",
+            ARGON2_MIN_MEMORY_KIB as u64 * 1024
+        ));
+    }
+
+    let mut kdf = Argon2id {
+        iterations: 1,
+        memory_kib: max_kib,
+        lanes: ARGON2_LANES,
+    };
+    let mut duration = measure(&kdf)?;
+    while duration > target_duration && kdf.memory_kib / 2 >= ARGON2_MIN_MEMORY_KIB {
+        kdf.memory_kib /= 2;
+        duration = measure(&kdf)?;
+    }
+
+    let passes = ((target_duration.as_secs_f64() / duration.as_secs_f64().max(1e-6)) as u32)
+        .min(kdf::ARGON2_MAX_ITERATIONS);
+    if passes > 1 {
+        kdf.iterations = passes;
+        duration = measure(&kdf)?;
+    }
+
+    Ok(Measurement {
+        memory: kdf.memory_kib as u64 * 1024,
+        kdf,
+        duration,
+    })
+}
+
+/// Benchmark this machine and pick parameters for every password KDF so a
+/// single derivation takes about `target_duration` and at most `max_memory` bytes.
+pub fn calibrate(target_duration: Duration, max_memory: u64) -> Result<Calibration> {
+    Ok(Calibration {
+        scrypt: calibrate_scrypt(target_duration, max_memory)?,
+        pbkdf2_sha256: calibrate_pbkdf2(false, target_duration)?,
+        pbkdf2_sha512: calibrate_pbkdf2(true, target_duration)?,
+        argon2id: calibrate_argon2id(target_duration, max_memory)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_scrypt_respects_memory_limit() {
+        let m = calibrate_scrypt(Duration::from_secs(10), 4 * MIB).unwrap();
+        assert!(m.memory <= 4 * MIB);
+        assert!(m.kdf.n >= 1 << SCRYPT_MIN_LOG_N);
+        assert!(m.kdf.n.is_power_of_two());
+    }
+
+    #[test]
+    fn test_scrypt_memory_too_small() {
+        assert!(calibrate_scrypt(Duration::from_millis(50), 1024).is_err());
+    }
+
+    #[test]
+    fn test_scrypt_tiny_target_falls_back_to_minimum() {
+        let m = calibrate_scrypt(Duration::from_nanos(1), 64 * MIB).unwrap();
+        assert_eq!(m.kdf.n, 1 << SCRYPT_MIN_LOG_N);
+    }
+
+    #[test]
+    fn test_pbkdf2_scales_with_target() {
+        // Timing based, so only compare targets far enough apart to survive a busy machine
+        let short = calibrate_pbkdf2(false, Duration::from_nanos(1)).unwrap();
+        let long = calibrate_pbkdf2(false, Duration::from_millis(100)).unwrap();
+        assert_eq!(short.kdf.iterations, PBKDF2_MIN_ITERATIONS);
+        assert!(long.kdf.iterations > short.kdf.iterations);
+    }
+
+    #[test]
+    fn test_argon2id_respects_memory_limit() {
+        let m = calibrate_argon2id(Duration::from_millis(20), 16 * MIB).unwrap();
+        assert!(m.memory <= 16 * MIB);
+        assert!(m.kdf.memory_kib >= ARGON2_MIN_MEMORY_KIB);
+        assert!(m.kdf.iterations >= 1);
+
+        assert!(calibrate_argon2id(Duration::from_millis(20), MIB).is_err());
+    }
+}
@@ -0,0 +1,612 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::LevelFilter;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::access::{AccessPolicy, REDACTED};
+use crate::crypto::MAX_BATCH_SIZE;
+use crate::envelope::{self, ProviderConfig};
+use crate::jobs::DEFAULT_MAX_JOBS;
+use crate::kdf::{self, KdfAlgorithm, KdfLimits, KdfOptions};
+use crate::methods::MethodGroup;
+use crate::pool::{self, BlockingPool, DEFAULT_QUEUE_LIMIT};
+
+/// Broker object the worker registers when none is configured
+pub const DEFAULT_OBJECT_NAME: &str = "applications.crypto";
+/// Seconds without a call before the worker shuts down
+pub const DEFAULT_IDLE_TIMEOUT: u64 = 60;
+/// Largest `input` accepted by a single call
+pub const DEFAULT_MAX_INPUT_BYTES: usize = 16 * 1024 * 1024;
+/// Largest total of the strings in the operations of a single batch
+pub const DEFAULT_MAX_BATCH_BYTES: usize = 64 * 1024 * 1024;
+
+/// How long the worker waits for a call before shutting down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleTimeout {
+    Never,
+    Seconds(u64),
+}
+
+impl Default for IdleTimeout {
+    fn default() -> Self {
+        IdleTimeout::Seconds(DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+impl IdleTimeout {
+    /// `None` when the worker never times out
+    pub fn duration(&self) -> Option<Duration> {
+        match self {
+            IdleTimeout::Never => None,
+            IdleTimeout::Seconds(seconds) => Some(Duration::from_secs(*seconds)),
+        }
+    }
+}
+
+impl fmt::Display for IdleTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdleTimeout::Never => f.write_str("never"),
+            IdleTimeout::Seconds(seconds) => write!(f, "{seconds}"),
+        }
+    }
+}
+
+impl FromStr for IdleTimeout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("never") {
+            return Ok(IdleTimeout::Never);
+        }
+        s.parse()
+            .map(IdleTimeout::Seconds)
+            .map_err(|_| anyhow!("idle timeout must be a number of seconds or never: {s}"))
+    }
+}
+
+impl Serialize for IdleTimeout {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            IdleTimeout::Never => serializer.serialize_str("never"),
+            IdleTimeout::Seconds(seconds) => serializer.serialize_u64(*seconds),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for IdleTimeout {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Seconds(u64),
+            Name(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Seconds(seconds) => Ok(IdleTimeout::Seconds(seconds)),
+            Repr::Name(name) => name.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Where log records are written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LogDestination {
+    #[default]
+    Stdout,
+    Stderr,
+    /// Appended to this file
+    File(PathBuf),
+}
+
+impl fmt::Display for LogDestination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogDestination::Stdout => f.write_str("stdout"),
+            LogDestination::Stderr => f.write_str("stderr"),
+            LogDestination::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for LogDestination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "" => bail!("log destination must be stdout, stderr or a file path"),
+            "stdout" => LogDestination::Stdout,
+            "stderr" => LogDestination::Stderr,
+            path => LogDestination::File(PathBuf::from(path)),
+        })
+    }
+}
+
+impl Serialize for LogDestination {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogDestination {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// `LevelFilter` as its lowercase name
+mod level {
+    use log::LevelFilter;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        level: &Option<LevelFilter>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match level {
+            Some(level) => serializer.serialize_str(&level.as_str().to_lowercase()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<LevelFilter>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|name| name.parse().map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `None` picks the level from `trace`/`debug` files next to the executable or
+    /// the `BROKER_DEBUG` environment variable
+    #[serde(with = "level")]
+    pub level: Option<LevelFilter>,
+    pub destination: LogDestination,
+    /// File denied calls are also appended to
+    pub audit: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Operations accepted by one `batch` call
+    pub max_batch_size: usize,
+    /// Bytes of strings accepted in the operations of one `batch` call
+    pub max_batch_bytes: usize,
+    /// Bytes accepted in the `input` of one call
+    pub max_input_bytes: usize,
+    /// Threads running CPU-heavy methods, 0 for one per CPU
+    pub threads: usize,
+    /// CPU-heavy calls allowed to wait for a thread
+    pub queue: usize,
+    /// File jobs allowed to run at once
+    pub max_jobs: usize,
+    /// KiB of scrypt or Argon2id memory the KDF header of a decrypted ciphertext
+    /// may ask for
+    pub max_kdf_memory: u64,
+    /// PBKDF2 iterations the KDF header of a decrypted ciphertext may ask for
+    pub max_kdf_iterations: u32,
+    /// Argon2id passes or scrypt p the KDF header of a decrypted ciphertext may ask for
+    pub max_kdf_passes: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        let kdf = KdfLimits::default();
+        Self {
+            max_batch_size: MAX_BATCH_SIZE,
+            max_batch_bytes: DEFAULT_MAX_BATCH_BYTES,
+            max_input_bytes: DEFAULT_MAX_INPUT_BYTES,
+            threads: 0,
+            queue: DEFAULT_QUEUE_LIMIT,
+            max_jobs: DEFAULT_MAX_JOBS,
+            max_kdf_memory: kdf.memory_kib,
+            max_kdf_iterations: kdf.iterations,
+            max_kdf_passes: kdf.passes,
+        }
+    }
+}
+
+impl Limits {
+    /// Ceiling on KDF parameters read from the headers of decrypted data
+    pub fn kdf_limits(&self) -> KdfLimits {
+        KdfLimits {
+            memory_kib: self.max_kdf_memory,
+            iterations: self.max_kdf_iterations,
+            passes: self.max_kdf_passes,
+        }
+    }
+
+    pub fn pool(&self) -> BlockingPool {
+        let threads = match self.threads {
+            0 => pool::default_threads(),
+            threads => threads,
+        };
+        BlockingPool::new(threads, self.queue)
+    }
+}
+
+/// Where methods taking file paths may read and write
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileAccess {
+    /// Directory file paths are relative to; file paths are refused without one
+    pub base_dir: Option<PathBuf>,
+}
+
+impl FileAccess {
+    /// `path` under the base directory. Rejects absolute paths, `..` and symlinks
+    /// leading out of the base directory.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let Some(base_dir) = &self.base_dir else {
+            bail!("file access is disabled, no base directory is configured");
+        };
+        let relative = Path::new(path);
+        if path.is_empty()
+            || !relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            bail!("'{path}' is not a path inside the base directory");
+        }
+        let base_dir = base_dir
+            .canonicalize()
+            .with_context(|| format!("resolving {}", base_dir.display()))?;
+        let resolved = base_dir.join(relative);
+        // The deepest existing part decides where a symlink actually leads
+        let existing = resolved
+            .ancestors()
+            .find(|ancestor| ancestor.symlink_metadata().is_ok())
+            .unwrap_or(&base_dir);
+        let real = existing
+            .canonicalize()
+            .with_context(|| format!("resolving '{path}'"))?;
+        if !real.starts_with(&base_dir) {
+            bail!("'{path}' leads outside the base directory");
+        }
+        Ok(resolved)
+    }
+}
+
+/// KDF used by scrypt-encrypt, AEAD encrypt and encrypt-file when a call names
+/// neither a KDF nor its parameters
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KdfDefaults {
+    /// scrypt when only parameters are given
+    pub algorithm: Option<String>,
+    pub iterations: Option<u32>,
+    /// Argon2id memory in KiB
+    pub memory: Option<u32>,
+    pub parallelism: Option<u32>,
+    /// scrypt N
+    pub cost: Option<u64>,
+}
+
+impl KdfDefaults {
+    pub fn options(&self) -> KdfOptions {
+        KdfOptions {
+            iterations: self.iterations,
+            memory_kib: self.memory,
+            parallelism: self.parallelism,
+            cost: self.cost,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.algorithm.is_none() && self.options().is_empty()
+    }
+}
+
+fn redacted<S: Serializer>(_: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
+}
+
+/// Master key of the envelope methods and where its provider finds it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase", deny_unknown_fields)]
+pub enum EnvelopeKey {
+    /// Raw, hex or base64 key in a file
+    File { path: PathBuf },
+    /// Hex or base64 key in an environment variable of the worker
+    Env { variable: String },
+    /// Hex or base64 key of the in-process mock provider; redacted when serialized
+    Mock {
+        #[serde(serialize_with = "redacted")]
+        key: String,
+    },
+}
+
+impl EnvelopeKey {
+    pub fn provider_config(&self) -> Result<ProviderConfig> {
+        Ok(match self {
+            EnvelopeKey::File { path } => ProviderConfig::File(path.clone()),
+            EnvelopeKey::Env { variable } => ProviderConfig::Env(variable.clone()),
+            EnvelopeKey::Mock { key } => ProviderConfig::Mock(envelope::parse_master_key(key)?),
+        })
+    }
+}
+
+/// Master keys of the envelope methods; callers select one by name and never see
+/// where it is kept
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvelopeKeys {
+    pub keys: BTreeMap<String, EnvelopeKey>,
+}
+
+/// Settings of the `crypto` worker, read from a JSON or TOML file and overridden by flags
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    pub object_name: String,
+    pub idle_timeout: IdleTimeout,
+    pub log: LogConfig,
+    /// Method groups served; methods of other groups are reported as unknown
+    pub methods: Vec<MethodGroup>,
+    pub limits: Limits,
+    pub kdf: KdfDefaults,
+    pub access: AccessPolicy,
+    pub files: FileAccess,
+    pub envelope: EnvelopeKeys,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            object_name: DEFAULT_OBJECT_NAME.to_string(),
+            idle_timeout: IdleTimeout::default(),
+            log: LogConfig::default(),
+            methods: MethodGroup::ALL.to_vec(),
+            limits: Limits::default(),
+            kdf: KdfDefaults::default(),
+            access: AccessPolicy::default(),
+            files: FileAccess::default(),
+            envelope: EnvelopeKeys::default(),
+        }
+    }
+}
+
+impl DaemonConfig {
+    /// Read a JSON file, or a TOML file when the extension is `.toml`
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let is_toml = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"));
+        if is_toml {
+            toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+        } else {
+            serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+        }
+    }
+
+    /// Reject settings the worker cannot run with
+    pub fn validate(&self) -> Result<()> {
+        if self.object_name.trim().is_empty() {
+            bail!("object name must not be empty");
+        }
+        if self.idle_timeout == IdleTimeout::Seconds(0) {
+            bail!("idle timeout must be positive, or never");
+        }
+        if self.methods.is_empty() {
+            bail!("at least one method group must be enabled");
+        }
+        let limits = &self.limits;
+        if limits.max_batch_size == 0 || limits.max_batch_bytes == 0 || limits.max_input_bytes == 0
+        {
+            bail!("max batch size, max batch bytes and max input bytes must be positive");
+        }
+        if limits.max_jobs == 0 {
+            bail!("max jobs must be positive");
+        }
+        let algorithm: KdfAlgorithm = self
+            .kdf
+            .algorithm
+            .as_deref()
+            .unwrap_or(KdfAlgorithm::Scrypt.name())
+            .parse()?;
+        let default_kdf = kdf::configure(algorithm, &self.kdf.options())
+            .context("invalid default KDF parameters")?;
+        // Data encrypted with the defaults must decrypt again
+        kdf::from_params_with_limits(algorithm, &default_kdf.params()?, &self.limits.kdf_limits())
+            .context("the default KDF parameters exceed the KDF limits of decryption")?;
+        self.access.validate().context("invalid access policy")?;
+        if let Some(base_dir) = &self.files.base_dir
+            && !base_dir.is_dir()
+        {
+            bail!("base directory {} does not exist", base_dir.display());
+        }
+        for (name, key) in &self.envelope.keys {
+            key.provider_config()
+                .and_then(|config| config.provider())
+                .with_context(|| format!("invalid envelope master key {name}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn enables(&self, group: MethodGroup) -> bool {
+        self.methods.contains(&group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_defaults() {
+        let config: DaemonConfig = serde_json::from_str(
+            r#"{
+                "object_name": "applications.vault",
+                "idle_timeout": "never",
+                "log": { "level": "debug", "destination": "/tmp/crypto.log" },
+                "methods": ["encoding", "hash"],
+                "limits": { "max_batch_size": 10 },
+                "kdf": { "algorithm": "argon2id", "memory": 8192 },
+                "access": { "clients": { "web": "token" }, "groups": { "hash": ["web"] } }
+            }"#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.object_name, "applications.vault");
+        assert_eq!(config.idle_timeout.duration(), None);
+        assert_eq!(config.log.level, Some(LevelFilter::Debug));
+        assert_eq!(
+            config.log.destination,
+            LogDestination::File(PathBuf::from("/tmp/crypto.log"))
+        );
+        assert!(config.enables(MethodGroup::Hash));
+        assert!(!config.enables(MethodGroup::Encryption));
+        assert_eq!(config.limits.max_batch_size, 10);
+        assert_eq!(config.limits.queue, DEFAULT_QUEUE_LIMIT);
+        assert_eq!(config.kdf.options().memory_kib, Some(8192));
+        assert_eq!(config.access.groups[&MethodGroup::Hash], ["web"]);
+
+        // What --print-config writes reads back unchanged, except for the redacted tokens
+        let printed = serde_json::to_string(&config).unwrap();
+        assert!(!printed.contains("\"token\""));
+        let mut reread = serde_json::from_str::<DaemonConfig>(&printed).unwrap();
+        assert_eq!(reread.access.clients["web"], REDACTED);
+        reread.access.clients = config.access.clients.clone();
+        assert_eq!(reread, config);
+        let defaults: DaemonConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(defaults, DaemonConfig::default());
+        assert_eq!(
+            defaults.idle_timeout.duration(),
+            Some(Duration::from_secs(DEFAULT_IDLE_TIMEOUT))
+        );
+    }
+
+    #[test]
+    fn test_load_toml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crypto.toml");
+        let master_key = dir.path().join("master.key");
+        std::fs::write(&master_key, [7u8; 32]).unwrap();
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+            object_name = "applications.vault"
+            idle_timeout = 300
+            methods = ["encoding", "hash"]
+
+            [log]
+            level = "warn"
+            audit = "/tmp/audit.log"
+
+            [kdf]
+            algorithm = "argon2id"
+            memory = 8192
+
+            [access.clients]
+            web = "token"
+
+            [access.groups]
+            hash = ["web"]
+
+            [envelope.keys.records]
+            provider = "file"
+            path = "{}"
+
+            [envelope.keys.local]
+            provider = "mock"
+            key = "{}"
+            "#,
+                master_key.display(),
+                "11".repeat(32),
+            ),
+        )
+        .unwrap();
+        let config = DaemonConfig::load(&path).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.object_name, "applications.vault");
+        assert_eq!(config.idle_timeout, IdleTimeout::Seconds(300));
+        assert_eq!(config.log.level, Some(LevelFilter::Warn));
+        assert_eq!(config.methods, [MethodGroup::Encoding, MethodGroup::Hash]);
+        assert_eq!(config.kdf.options().memory_kib, Some(8192));
+        assert_eq!(config.access.groups[&MethodGroup::Hash], ["web"]);
+        assert_eq!(
+            config.envelope.keys["records"],
+            EnvelopeKey::File { path: master_key }
+        );
+        // The mock master key is printed redacted like the client tokens
+        let printed = serde_json::to_string(&config).unwrap();
+        assert!(!printed.contains(&"11".repeat(32)));
+        assert!(printed.contains(REDACTED));
+
+        // Other extensions are read as JSON
+        let path = dir.path().join("crypto.conf");
+        std::fs::write(&path, r#"{ "idle_timeout": "never" }"#).unwrap();
+        assert_eq!(
+            DaemonConfig::load(&path).unwrap().idle_timeout,
+            IdleTimeout::Never
+        );
+        std::fs::write(&path, "idle_timeout = 5").unwrap();
+        assert!(DaemonConfig::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_file_access() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        let files = FileAccess {
+            base_dir: Some(dir.path().to_path_buf()),
+        };
+        let base = dir.path().canonicalize().unwrap();
+        assert_eq!(files.resolve("a.txt").unwrap(), base.join("a.txt"));
+        assert_eq!(files.resolve("./sub/b").unwrap(), base.join("sub/b"));
+        for escape in ["", "/etc/passwd", "../a.txt", "sub/../../a.txt", "sub/.."] {
+            assert!(files.resolve(escape).is_err(), "{escape}");
+        }
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", dir.path().join("etc")).unwrap();
+            assert!(files.resolve("etc/passwd").is_err());
+            std::os::unix::fs::symlink("/nonexistent", dir.path().join("dangling")).unwrap();
+            assert!(files.resolve("dangling").is_err());
+        }
+        assert!(FileAccess::default().resolve("a.txt").is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(serde_json::from_str::<DaemonConfig>(r#"{ "timeout": 5 }"#).is_err());
+        assert!(serde_json::from_str::<DaemonConfig>(r#"{ "idle_timeout": "soon" }"#).is_err());
+        assert!(serde_json::from_str::<DaemonConfig>(r#"{ "methods": ["admin"] }"#).is_err());
+        assert!(serde_json::from_str::<DaemonConfig>(r#"{ "log": { "level": "loud" } }"#).is_err());
+
+        let invalid = [
+            r#"{ "object_name": " " }"#,
+            r#"{ "idle_timeout": 0 }"#,
+            r#"{ "methods": [] }"#,
+            r#"{ "limits": { "max_input_bytes": 0 } }"#,
+            r#"{ "kdf": { "algorithm": "md5" } }"#,
+            r#"{ "kdf": { "cost": 1000 } }"#,
+            r#"{ "kdf": { "algorithm": "pbkdf2-sha256", "memory": 8192 } }"#,
+            r#"{ "limits": { "max_kdf_memory": 1024 } }"#,
+            r#"{ "kdf": { "algorithm": "argon2id" }, "limits": { "max_kdf_passes": 2 } }"#,
+            r#"{ "access": { "default": ["nobody"] } }"#,
+            r#"{ "files": { "base_dir": "/nonexistent/enzo-crypto" } }"#,
+            r#"{ "envelope": { "keys": { "a": { "provider": "mock", "key": "0102" } } } }"#,
+            r#"{ "envelope": { "keys": { "a": { "provider": "file", "path": "/nonexistent/kek" } } } }"#,
+        ];
+        for json in invalid {
+            let config: DaemonConfig = serde_json::from_str(json).unwrap();
+            assert!(config.validate().is_err(), "{json}");
+        }
+    }
+}
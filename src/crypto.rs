@@ -1,18 +1,44 @@
-use std::{borrow::Cow, string::FromUtf8Error};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    string::FromUtf8Error,
+    sync::{Arc, LazyLock},
+};
 
 use async_trait::async_trait;
 use base64::{DecodeError, Engine, engine::general_purpose};
 
 use ipc_broker::worker::SharedObject;
 use json_result::r#struct::JsonResult;
-use serde_json::Value;
+use serde_json::{Value, json};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinSet;
 
-use crate::{base52, decrypt, encrypt, scrypt};
+use crate::access::{AUDIT_TARGET, Caller};
+use crate::aead::{self, AeadCipher};
+use crate::config::{DaemonConfig, EnvelopeKey};
+use crate::encoding::{Encoding, PayloadEncoding};
+use crate::envelope::{self, Envelope, KeyProvider};
+use crate::fpe::{self, Alphabet, FpeMode};
+use crate::hash::{self, HashAlgorithm};
+use crate::jobs::{self, JobId, JobManager};
+use crate::jose::{self, JoseKey, JweKey, JwsAlgorithm, Validation};
+use crate::kdf::{Kdf, KdfAlgorithm, KdfLimits, KdfOptions};
+use crate::keys::{self, Jwk, KeyFormat, KeySpec};
+use crate::keywrap;
+use crate::methods::{MethodGroup, MethodSpec, ParamDefault, ParamSpec};
+use crate::otp::{self, OtpAlgorithm, OtpAuth, OtpKind, Totp};
+use crate::pool::{BlockingPool, PoolError};
+use crate::random::{self, CharClasses};
+use crate::siv;
+use crate::{async_file_gcm, base52, decrypt_bytes, kdf, scrypt};
 
 #[repr(i32)]
-#[derive(Serialize_repr, Deserialize_repr, Debug, Default)]
+#[derive(Serialize_repr, Deserialize_repr, Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Code {
     #[default]
     Success = 0,
@@ -23,12 +49,20 @@ pub enum Code {
     UnknownMethodError = -5,
     InvalidArgumentsError = -6,
     ParseError = -7,
+    HashError = -8,
+    VerifyError = -9,
+    KeyError = -10,
+    QueueFull = -11,
+    InternalError = -12,
+    PermissionDenied = -13,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct CryptoOK<'a> {
     code: Code,
     pub result: Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entropy_bits: Option<f64>,
 }
 
 impl<'a> CryptoOK<'a> {
@@ -36,6 +70,15 @@ impl<'a> CryptoOK<'a> {
         CryptoOK {
             code: Code::Success,
             result,
+            entropy_bits: None,
+        }
+    }
+
+    fn with_entropy(result: Cow<'a, str>, entropy_bits: f64) -> CryptoOK<'a> {
+        CryptoOK {
+            code: Code::Success,
+            result,
+            entropy_bits: Some(entropy_bits),
         }
     }
 }
@@ -54,6 +97,15 @@ impl<'a> CryptoError<'a> {
 
 type CryptoResult<'a> = JsonResult<CryptoOK<'a>, CryptoError<'a>>;
 
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// `algorithm` of the encrypt/decrypt format that predates the AEAD ciphers
+const LEGACY_CIPHER: &str = "aes-256-cbc";
+/// Shift of rot-encode/rot-decode when none is given (ROT13)
+const DEFAULT_SHIFT: u8 = 13;
+/// Default for the most operations accepted in one batch call
+pub const MAX_BATCH_SIZE: usize = 1000;
+
 impl<'a> From<CryptoOK<'a>> for JsonResult<CryptoOK<'a>, CryptoError<'a>> {
     fn from(t: CryptoOK<'a>) -> Self {
         JsonResult(Ok(t))
@@ -84,16 +136,357 @@ struct Param<'a> {
     input: Cow<'a, str>,
     #[serde(default)]
     passphrase: Cow<'a, str>,
+    #[serde(default)]
+    kdf: Option<Cow<'a, str>>,
+    #[serde(default)]
+    cipher: Option<Cow<'a, str>>,
+    #[serde(default)]
+    aad: Option<Cow<'a, str>>,
+    #[serde(default)]
+    tweak: Option<Cow<'a, str>>,
+    #[serde(default)]
+    alphabet: Option<Cow<'a, str>>,
+    /// Name of a configured envelope master key
+    #[serde(default)]
+    master_key: Option<Cow<'a, str>>,
+    #[serde(default)]
+    output: Option<Cow<'a, str>>,
+    #[serde(default)]
+    overwrite: bool,
+    #[serde(default)]
+    job: Option<u64>,
+    #[serde(default)]
+    key: Cow<'a, str>,
+    #[serde(default)]
+    path: Option<Cow<'a, str>>,
+    /// Encoding of the data a method reads and returns, UTF-8 by default
+    #[serde(default)]
+    encoding: Option<Cow<'a, str>>,
+    /// Encoding of keys, digests and other binary values, hex by default
+    #[serde(default)]
+    binary_encoding: Option<Cow<'a, str>>,
+    #[serde(default)]
+    length: Option<usize>,
+    #[serde(default)]
+    classes: Option<Cow<'a, str>>,
+    #[serde(default)]
+    words: Option<usize>,
+    #[serde(default)]
+    separator: Option<Cow<'a, str>>,
+    #[serde(default)]
+    digits: Option<u32>,
+    #[serde(default)]
+    period: Option<u64>,
+    #[serde(default)]
+    counter: Option<u64>,
+    #[serde(default)]
+    window: Option<u64>,
+    #[serde(default)]
+    time: Option<u64>,
+    #[serde(default)]
+    algorithm: Option<Cow<'a, str>>,
+    #[serde(default)]
+    account: Option<Cow<'a, str>>,
+    #[serde(default)]
+    issuer: Option<Cow<'a, str>>,
+    #[serde(default)]
+    format: Option<Cow<'a, str>>,
+    #[serde(default)]
+    public: bool,
+    #[serde(default)]
+    new_passphrase: Option<Cow<'a, str>>,
+    #[serde(default)]
+    audience: Option<Cow<'a, str>>,
+    #[serde(default)]
+    leeway: Option<u64>,
+    #[serde(default)]
+    padding: bool,
+    #[serde(default)]
+    method: Option<Cow<'a, str>>,
+    #[serde(default)]
+    shift: Option<u8>,
+    #[serde(default)]
+    iterations: Option<u32>,
+    #[serde(default)]
+    memory: Option<u32>,
+    #[serde(default)]
+    parallelism: Option<u32>,
+    #[serde(default)]
+    cost: Option<u64>,
+    #[serde(default)]
+    operations: Option<Vec<BatchOperation>>,
+    #[serde(default)]
+    parallel: bool,
+}
+
+/// One `{method, args}` item of a batch call
+#[derive(serde::Deserialize)]
+struct BatchOperation {
+    method: String,
+    #[serde(default)]
+    args: serde_json::Map<String, Value>,
+}
+
+impl Param<'_> {
+    fn kdf_options(&self) -> KdfOptions {
+        KdfOptions {
+            iterations: self.iterations,
+            memory_kib: self.memory,
+            parallelism: self.parallelism,
+            cost: self.cost,
+        }
+    }
+
+    /// `self` with the configured default KDF when the call names neither a KDF nor
+    /// its parameters
+    fn with_kdf_defaults(mut self, config: &DaemonConfig) -> Self {
+        if self.kdf.is_none() && self.kdf_options().is_empty() {
+            let defaults = &config.kdf;
+            self.kdf = defaults.algorithm.clone().map(Cow::Owned);
+            self.iterations = defaults.iterations;
+            self.memory = defaults.memory;
+            self.parallelism = defaults.parallelism;
+            self.cost = defaults.cost;
+        }
+        self
+    }
+}
+
+/// One-time password settings shared by the totp/hotp methods
+struct OtpOptions {
+    secret: Vec<u8>,
+    digits: u32,
+    period: u64,
+    algorithm: OtpAlgorithm,
+}
+
+impl OtpOptions {
+    /// The secret in `key` is base32, or scrypt-encrypted when a passphrase is given and
+    /// then refused when its KDF header costs more than `limits`
+    fn from_param<'a>(
+        param: &Param<'a>,
+        limits: &KdfLimits,
+    ) -> Result<OtpOptions, CryptoError<'a>> {
+        if param.key.is_empty() {
+            return Err(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Secret key is required"),
+            ));
+        }
+        let secret = if param.passphrase.is_empty() {
+            otp::base32_decode(&param.key)
+                .map_err(|e| CryptoError::new(Code::DecodeError, Cow::Owned(e.to_string())))?
+        } else {
+            scrypt::decrypt_base64_with_limits(
+                Cow::Borrowed(&param.key),
+                Cow::Borrowed(&param.passphrase),
+                limits,
+            )
+            .map_err(|e| CryptoError::new(Code::DecryptError, Cow::Owned(e.to_string())))?
+        };
+        let algorithm = match &param.algorithm {
+            Some(name) => name.parse().map_err(|e: anyhow::Error| {
+                CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+            })?,
+            None => OtpAlgorithm::default(),
+        };
+        Ok(OtpOptions {
+            secret,
+            digits: param.digits.unwrap_or(otp::DEFAULT_DIGITS),
+            period: param.period.unwrap_or(otp::DEFAULT_PERIOD),
+            algorithm,
+        })
+    }
+
+    fn totp(self) -> Totp {
+        Totp {
+            secret: self.secret,
+            digits: self.digits,
+            period: self.period,
+            algorithm: self.algorithm,
+        }
+    }
+}
+
+/// Signing or encryption key of a JWT
+enum TokenKey {
+    Jws(JoseKey),
+    Jwe(JweKey),
+}
+
+impl TokenKey {
+    /// HS256 uses `key` as the shared secret, RS256/ES256/EdDSA a PEM, JWK or
+    /// base64 DER key (opened with `passphrase` when encrypted), `dir` a 256-bit
+    /// key in `binary_encoding` (hex by default) and PBES2 the passphrase.
+    fn from_param<'a>(
+        algorithm: &str,
+        key: &str,
+        passphrase: &str,
+        encoding: Option<Cow<'a, str>>,
+        signing: bool,
+    ) -> Result<TokenKey, CryptoError<'a>> {
+        let key_error =
+            |e: anyhow::Error| CryptoError::new(Code::KeyError, Cow::Owned(e.to_string()));
+        if algorithm.eq_ignore_ascii_case("dir") {
+            let encoding = Crypto::binary_encoding(encoding)?;
+            return Ok(TokenKey::Jwe(JweKey::Direct(
+                encoding.decode(key).map_err(key_error)?,
+            )));
+        }
+        if algorithm.to_ascii_uppercase().starts_with("PBES2") {
+            if passphrase.is_empty() {
+                return Err(CryptoError::new(
+                    Code::InvalidArgumentsError,
+                    Cow::Borrowed("Passphrase is required"),
+                ));
+            }
+            return Ok(TokenKey::Jwe(JweKey::password(passphrase)));
+        }
+
+        let algorithm: JwsAlgorithm = algorithm.parse().map_err(|e: anyhow::Error| {
+            CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+        })?;
+        if key.is_empty() {
+            return Err(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Key is required"),
+            ));
+        }
+        let jose_key = if algorithm == JwsAlgorithm::Hs256 {
+            JoseKey::Secret(key.as_bytes().to_vec())
+        } else {
+            let passphrase = Some(passphrase).filter(|p| !p.is_empty());
+            let data = keys::decode_text(key).map_err(key_error)?;
+            if signing {
+                JoseKey::Private(keys::load_private(&data, passphrase).map_err(key_error)?)
+            } else {
+                JoseKey::Public(keys::load_public(&data, passphrase).map_err(key_error)?)
+            }
+        };
+        match jose_key.algorithm() {
+            Ok(actual) if actual == algorithm => Ok(TokenKey::Jws(jose_key)),
+            Ok(actual) => Err(CryptoError::new(
+                Code::KeyError,
+                Cow::Owned(format!("{algorithm} requested but the key is for {actual}")),
+            )),
+            Err(e) => Err(key_error(e)),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Crypto {
     activity_tx: UnboundedSender<()>,
+    jobs: JobManager,
+    pool: BlockingPool,
+    config: Arc<DaemonConfig>,
 }
 
 impl Crypto {
     pub fn new(activity_tx: UnboundedSender<()>) -> Self {
-        Self { activity_tx }
+        Self::with_config(activity_tx, DaemonConfig::default())
+    }
+
+    /// Serve the method groups, limits and KDF defaults of `config`
+    pub fn with_config(activity_tx: UnboundedSender<()>, config: DaemonConfig) -> Self {
+        Self {
+            activity_tx,
+            jobs: JobManager::new(config.limits.max_jobs),
+            pool: config.limits.pool(),
+            config: Arc::new(config),
+        }
+    }
+
+    /// Run CPU-heavy methods on `pool` instead of the default one-thread-per-CPU pool
+    pub fn with_pool(activity_tx: UnboundedSender<()>, pool: BlockingPool) -> Self {
+        Self {
+            activity_tx,
+            jobs: JobManager::default(),
+            pool,
+            config: Arc::new(DaemonConfig::default()),
+        }
+    }
+
+    /// Registry entry of `name`, unless its method group is disabled
+    fn method(&self, name: &str) -> Option<&'static Method> {
+        find_method(name).filter(|method| self.config.enables(method.spec.group))
+    }
+
+    /// Handle a call from `caller`, which the access policy checks before running it
+    pub async fn call_as(&self, caller: &Caller, method: &str, args: &Value) -> Value {
+        let _ = self.activity_tx.send(());
+        self.dispatch(caller, method, args.clone()).await
     }
+
+    /// Parse `args` and run `method` from the registry, heavy methods on the blocking pool
+    async fn dispatch(&self, caller: &Caller, method: &str, args: Value) -> Value {
+        let Some(method) = self.method(method) else {
+            let msg = format!("Unknown method called: {method}");
+            log::warn!("{msg}");
+            return CryptoResult::from(CryptoError::new(Code::UnknownMethodError, Cow::Owned(msg)))
+                .into();
+        };
+        let policy = &self.config.access;
+        let client = policy.client(caller);
+        if !policy.allows(client, method.spec.group) {
+            log::warn!(
+                target: AUDIT_TARGET,
+                "Denied {} ({} group) to {client}",
+                method.spec.name,
+                method.spec.group
+            );
+            return CryptoResult::from(CryptoError::new(
+                Code::PermissionDenied,
+                Cow::Owned(format!("Permission denied: {}", method.spec.name)),
+            ))
+            .into();
+        }
+        let param: Param = match serde_json::from_value(args) {
+            Ok(p) => p,
+            Err(e) => {
+                return CryptoResult::from(CryptoError::new(
+                    Code::InvalidArgumentsError,
+                    Cow::Owned(e.to_string()),
+                ))
+                .into();
+            }
+        };
+        let max_input_bytes = self.config.limits.max_input_bytes;
+        if param.input.len() > max_input_bytes {
+            return CryptoResult::from(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Owned(format!(
+                    "Input is {} bytes, more than the {max_input_bytes} byte limit",
+                    param.input.len()
+                )),
+            ))
+            .into();
+        }
+
+        match &method.handler {
+            Handler::Inline(handler) => handler(self, param).into(),
+            Handler::Blocking(handler) => {
+                let crypto = self.clone();
+                match self
+                    .pool
+                    .run(move || Value::from(handler(&crypto, param)))
+                    .await
+                {
+                    Ok(result) => result,
+                    Err(e) => {
+                        let code = match e {
+                            PoolError::QueueFull => Code::QueueFull,
+                            PoolError::Failed(_) => Code::InternalError,
+                        };
+                        log::warn!("{} not run: {e}", method.spec.name);
+                        CryptoResult::from(CryptoError::new(code, Cow::Owned(e.to_string()))).into()
+                    }
+                }
+            }
+            Handler::Batch => self.batch(caller, param.operations, param.parallel).await,
+        }
+    }
+
     /// Require passphrase or return error JSON with caller-provided error code
     fn require_passphrase<'a>(passphrase: Cow<'a, str>, rc: Code) -> Option<CryptoResult<'a>> {
         if passphrase.is_empty() {
@@ -103,193 +496,3190 @@ impl Crypto {
         }
     }
 
+    /// Parse the optional binary encoding, defaulting to hex
+    fn binary_encoding<'a>(encoding: Option<Cow<'a, str>>) -> Result<Encoding, CryptoError<'a>> {
+        match encoding {
+            Some(name) => name.parse().map_err(|e: anyhow::Error| {
+                CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+            }),
+            None => Ok(Encoding::default()),
+        }
+    }
+
+    /// Parse the optional key format, defaulting to PEM
+    fn key_format<'a>(format: Option<Cow<'a, str>>) -> Result<KeyFormat, CryptoError<'a>> {
+        match format {
+            Some(name) => name.parse().map_err(|e: anyhow::Error| {
+                CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+            }),
+            None => Ok(KeyFormat::default()),
+        }
+    }
+
+    /// KDF named `name` (scrypt when only `options` are given) with `options` applied,
+    /// or `None` when neither is given
+    fn kdf<'a>(
+        name: Option<&str>,
+        options: &KdfOptions,
+    ) -> Result<Option<Box<dyn Kdf>>, CryptoError<'a>> {
+        if name.is_none() && options.is_empty() {
+            return Ok(None);
+        }
+        name.unwrap_or(KdfAlgorithm::Scrypt.name())
+            .parse()
+            .and_then(|algorithm| kdf::configure(algorithm, options))
+            .map(Some)
+            .map_err(|e| CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string())))
+    }
+
+    /// Parse the optional payload encoding, defaulting to UTF-8 text
+    fn payload_encoding<'a>(
+        encoding: Option<Cow<'a, str>>,
+    ) -> Result<PayloadEncoding, CryptoError<'a>> {
+        match encoding {
+            Some(name) => name.parse().map_err(|e: anyhow::Error| {
+                CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+            }),
+            None => Ok(PayloadEncoding::default()),
+        }
+    }
+
+    /// Bytes of an input payload given in `encoding`
+    fn payload_input<'a>(
+        input: &str,
+        encoding: Option<Cow<'a, str>>,
+    ) -> Result<Vec<u8>, CryptoError<'a>> {
+        Crypto::payload_encoding(encoding)?
+            .decode(input)
+            .map_err(|e| CryptoError::new(Code::DecodeError, Cow::Owned(e.to_string())))
+    }
+
+    /// Output payload in `encoding`; `code` is reported when UTF-8 was requested for
+    /// bytes that are not valid UTF-8
+    fn payload_output<'a>(
+        bytes: Vec<u8>,
+        encoding: PayloadEncoding,
+        code: Code,
+    ) -> Result<CryptoOK<'a>, CryptoError<'a>> {
+        encoding
+            .encode(bytes)
+            .map(|text| CryptoOK::new(Cow::Owned(text)))
+            .map_err(|e| CryptoError::new(code, Cow::Owned(e.to_string())))
+    }
+
     /// Base64 decode helper
-    pub fn decode_base64<'a>(input: Cow<'a, str>) -> CryptoResult<'a> {
+    pub fn decode_base64<'a>(
+        input: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
         log::info!("Decoding base64 input: {input}");
+        let encoding = match Crypto::payload_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
         general_purpose::STANDARD
             .decode(input.as_bytes())
             .map_err(CryptoError::from)
-            .and_then(|bytes| {
-                Ok(CryptoOK::new(
-                    String::from_utf8(bytes)
-                        .map(Cow::Owned)
-                        .map_err(CryptoError::from)?,
-                ))
-            })
+            .and_then(|bytes| Crypto::payload_output(bytes, encoding, Code::ParseError))
             .into()
     }
 
     /// Base64 encode helper
-    pub fn encode_base64<'a>(input: Cow<'a, str>) -> CryptoResult<'a> {
+    pub fn encode_base64<'a>(
+        input: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
         log::info!("Encoding base64 input: {input}");
-        CryptoOK::new(general_purpose::STANDARD.encode(input.as_bytes()).into()).into()
+        Crypto::payload_input(&input, encoding)
+            .map(|bytes| CryptoOK::new(general_purpose::STANDARD.encode(bytes).into()))
+            .into()
     }
 
     /// Base64 decode helper
-    pub fn decode_base64_nopad<'a>(input: Cow<'a, str>) -> CryptoResult<'a> {
+    pub fn decode_base64_nopad<'a>(
+        input: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
         log::info!("Decoding base64 no padding input: {input}");
+        let encoding = match Crypto::payload_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
 
         general_purpose::STANDARD_NO_PAD
             .decode(input.as_bytes())
             .map_err(CryptoError::from)
-            .and_then(|bytes| {
-                Ok(CryptoOK::new(
-                    String::from_utf8(bytes)
-                        .map(Cow::Owned)
-                        .map_err(CryptoError::from)?,
-                ))
-            })
+            .and_then(|bytes| Crypto::payload_output(bytes, encoding, Code::ParseError))
             .into()
     }
 
     /// Base64 encode helper
-    pub fn encode_base64_nopad<'a>(input: Cow<'a, str>) -> CryptoResult<'a> {
+    pub fn encode_base64_nopad<'a>(
+        input: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
         log::info!("Encoding base64 no padding input: {input}");
-        CryptoOK::new(
-            general_purpose::STANDARD_NO_PAD
-                .encode(input.as_bytes())
-                .into(),
-        )
-        .into()
+        Crypto::payload_input(&input, encoding)
+            .map(|bytes| CryptoOK::new(general_purpose::STANDARD_NO_PAD.encode(bytes).into()))
+            .into()
     }
 
     /// Base52 decode helper
-    pub fn decode_base52<'a>(input: Cow<'a, str>) -> CryptoResult<'a> {
+    pub fn decode_base52<'a>(
+        input: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
         log::info!("Decoding base52 input: {input}");
+        let encoding = match Crypto::payload_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
 
         base52::decode(input.as_bytes())
             .map_err(CryptoError::from)
-            .and_then(|bytes| {
-                Ok(CryptoOK::new(
-                    String::from_utf8(bytes)
-                        .map(Cow::Owned)
-                        .map_err(CryptoError::from)?,
-                ))
-            })
+            .and_then(|bytes| Crypto::payload_output(bytes, encoding, Code::ParseError))
             .into()
     }
 
     /// Base52 encode helper
-    pub fn encode_base52<'a>(input: Cow<'a, str>) -> CryptoResult<'a> {
+    pub fn encode_base52<'a>(
+        input: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
         log::info!("Encoding base52 input: {input}");
-        CryptoOK::new(base52::encode(input.as_bytes()).into()).into()
+        Crypto::payload_input(&input, encoding)
+            .map(|bytes| CryptoOK::new(base52::encode(bytes).into()))
+            .into()
+    }
+
+    /// Decrypt the legacy aes-256-cbc format, which can no longer be encrypted to
+    pub fn decrypt<'a>(
+        input: Cow<'a, str>,
+        passphrase: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        log::info!("Decrypting legacy input with passphrase.");
+        if let Some(err) = Crypto::require_passphrase(passphrase.clone(), Code::DecryptError) {
+            return err;
+        }
+        let encoding = match Crypto::payload_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        decrypt_bytes(&input, passphrase)
+            .map_err(|e| CryptoError::new(Code::DecryptError, Cow::Owned(e.to_string())))
+            .and_then(|bytes| Crypto::payload_output(bytes, encoding, Code::DecryptError))
+            .into()
     }
 
-    pub fn encrypt<'a>(input: Cow<'a, str>, passphrase: Cow<'a, str>) -> CryptoResult<'a> {
-        log::info!("Encrypting input with passphrase.");
+    pub fn scrypt_encrypt<'a>(
+        input: Cow<'a, str>,
+        passphrase: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        log::info!("Encrypting input with scrypt and passphrase.");
         if let Some(err) = Crypto::require_passphrase(passphrase.clone(), Code::EncryptError) {
             return err;
         }
-        encrypt(input, passphrase.clone())
-            .map(|res| Ok(CryptoOK::new(res)))
+        let plaintext = match Crypto::payload_input(&input, encoding) {
+            Ok(plaintext) => plaintext,
+            Err(err) => return err.into(),
+        };
+
+        scrypt::encrypt_base64(&plaintext, passphrase)
+            .map(|s| Ok(CryptoOK::new(s)))
+            .map_err(|e| Cow::Owned::<String>(e.to_string()))
             .unwrap_or_else(|e| {
                 Err(CryptoError::new(
-                    Code::EncryptError,
+                    Code::DecodeError,
                     Cow::Owned(e.to_string()),
                 ))
             })
             .into()
     }
 
-    pub fn decrypt<'a>(input: Cow<'a, str>, passphrase: Cow<'a, str>) -> CryptoResult<'a> {
-        log::info!("Decrypting input with passphrase.");
-        if let Some(err) = Crypto::require_passphrase(passphrase.clone(), Code::DecryptError) {
+    pub fn scrypt_encrypt_with_kdf<'a>(
+        input: Cow<'a, str>,
+        passphrase: Cow<'a, str>,
+        kdf_name: &str,
+        options: &KdfOptions,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        log::info!("Encrypting input with {kdf_name} and passphrase.");
+        if let Some(err) = Crypto::require_passphrase(passphrase.clone(), Code::EncryptError) {
             return err;
         }
-        decrypt(input, passphrase)
-            .map(|res| Ok(CryptoOK::new(res)))
+        let kdf = match Crypto::kdf(Some(kdf_name), options) {
+            Ok(kdf) => kdf.unwrap_or_else(|| Box::new(kdf::Scrypt::default())),
+            Err(err) => return err.into(),
+        };
+
+        let plaintext = match Crypto::payload_input(&input, encoding) {
+            Ok(plaintext) => plaintext,
+            Err(err) => return err.into(),
+        };
+
+        scrypt::encrypt_base64_with_kdf(&plaintext, passphrase, kdf.as_ref())
+            .map(|s| Ok(CryptoOK::new(s)))
             .unwrap_or_else(|e| {
                 Err(CryptoError::new(
-                    Code::DecryptError,
+                    Code::EncryptError,
                     Cow::Owned(e.to_string()),
                 ))
             })
             .into()
     }
 
-    pub fn scrypt_encrypt<'a>(input: Cow<'a, str>, passphrase: Cow<'a, str>) -> CryptoResult<'a> {
-        log::info!("Encrypting input with scrypt and passphrase.");
+    pub fn scrypt_encrypt_with_cipher<'a>(
+        input: Cow<'a, str>,
+        passphrase: Cow<'a, str>,
+        kdf_name: Option<&str>,
+        options: &KdfOptions,
+        cipher_name: &str,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        log::info!("Encrypting input with {cipher_name} and passphrase.");
         if let Some(err) = Crypto::require_passphrase(passphrase.clone(), Code::EncryptError) {
             return err;
         }
+        let kdf = match Crypto::kdf(kdf_name, options) {
+            Ok(kdf) => kdf.unwrap_or_else(|| Box::new(kdf::Scrypt::default())),
+            Err(err) => return err.into(),
+        };
+        let cipher = match cipher_name.parse::<AeadCipher>() {
+            Ok(cipher) => cipher,
+            Err(e) => {
+                return CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+                    .into();
+            }
+        };
+
+        let plaintext = match Crypto::payload_input(&input, encoding) {
+            Ok(plaintext) => plaintext,
+            Err(err) => return err.into(),
+        };
 
-        scrypt::encrypt_base64(input.as_bytes(), passphrase)
+        scrypt::encrypt_base64_with_cipher(&plaintext, passphrase, kdf.as_ref(), cipher)
             .map(|s| Ok(CryptoOK::new(s)))
-            .map_err(|e| Cow::Owned::<String>(e.to_string()))
             .unwrap_or_else(|e| {
                 Err(CryptoError::new(
-                    Code::DecodeError,
+                    Code::EncryptError,
                     Cow::Owned(e.to_string()),
                 ))
             })
             .into()
     }
 
-    pub fn scrypt_decrypt<'a>(input: Cow<'a, str>, passphrase: Cow<'a, str>) -> CryptoResult<'a> {
+    /// Decrypt the scrypt-encrypt format, refusing KDF headers beyond the configured limits
+    pub fn scrypt_decrypt<'a>(
+        &self,
+        input: Cow<'a, str>,
+        passphrase: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
         log::info!("Decrypting input with scrypt and passphrase.");
         if let Some(err) = Crypto::require_passphrase(passphrase.clone(), Code::DecryptError) {
             return err;
         }
+        let encoding = match Crypto::payload_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
 
-        scrypt::decrypt_base64(input, passphrase)
+        scrypt::decrypt_base64_with_limits(input, passphrase, &self.config.limits.kdf_limits())
             .map_err(|e| CryptoError::new(Code::DecryptError, Cow::Owned(e.to_string())))
-            .and_then(|bytes| {
-                Ok(CryptoOK::new(
-                    String::from_utf8(bytes).map(Cow::Owned).map_err(|e| {
-                        CryptoError::new(Code::DecryptError, Cow::Owned(e.to_string()))
-                    })?,
+            .and_then(|bytes| Crypto::payload_output(bytes, encoding, Code::DecryptError))
+            .into()
+    }
+
+    /// Digest of `input`, or of the file at `path` in the base directory when given
+    pub fn hash<'a>(
+        &self,
+        algorithm: HashAlgorithm,
+        input: Cow<'a, str>,
+        path: Option<Cow<'a, str>>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        log::info!("Hashing input with {algorithm}.");
+        let encoding = match Crypto::binary_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        let path = match path.map(|path| self.file_path(&path)).transpose() {
+            Ok(path) => path,
+            Err(err) => return err.into(),
+        };
+        match path {
+            Some(path) => hash::hash_file(algorithm, &path),
+            None => hash::hash(algorithm, input.as_bytes()),
+        }
+        .map(|digest| Ok(CryptoOK::new(Cow::Owned(encoding.encode(&digest)))))
+        .unwrap_or_else(|e| Err(CryptoError::new(Code::HashError, Cow::Owned(e.to_string()))))
+        .into()
+    }
+
+    /// HMAC of `input`, or of the file at `path` in the base directory when given
+    pub fn hmac<'a>(
+        &self,
+        algorithm: HashAlgorithm,
+        input: Cow<'a, str>,
+        key: Cow<'a, str>,
+        path: Option<Cow<'a, str>>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        log::info!("Computing HMAC of input with {algorithm}.");
+        if key.is_empty() {
+            return CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Key is required"),
+            )
+            .into();
+        }
+        let encoding = match Crypto::binary_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        let path = match path.map(|path| self.file_path(&path)).transpose() {
+            Ok(path) => path,
+            Err(err) => return err.into(),
+        };
+        match path {
+            Some(path) => hash::hmac_file(algorithm, key.as_bytes(), &path),
+            None => hash::hmac(algorithm, key.as_bytes(), input.as_bytes()),
+        }
+        .map(|mac| Ok(CryptoOK::new(Cow::Owned(encoding.encode(&mac)))))
+        .unwrap_or_else(|e| Err(CryptoError::new(Code::HashError, Cow::Owned(e.to_string()))))
+        .into()
+    }
+
+    /// Random bytes in the requested encoding (hex by default)
+    pub fn random_bytes<'a>(length: usize, encoding: Option<Cow<'a, str>>) -> CryptoResult<'a> {
+        log::info!("Generating {length} random bytes.");
+        let encoding = match Crypto::binary_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        random::random_encoded(length, encoding)
+            .map(|s| Ok(CryptoOK::new(Cow::Owned(s))))
+            .unwrap_or_else(|e| {
+                Err(CryptoError::new(
+                    Code::InvalidArgumentsError,
+                    Cow::Owned(e.to_string()),
                 ))
             })
             .into()
     }
 
-    pub fn rot_n_encode<'a>(input: Cow<'a, str>, shift: u8) -> CryptoResult<'a> {
-        log::info!("Encoding ROT-N input with shift {shift}: {input}");
-        let encoded = crate::rotn::rot_n_encode(&input, shift);
-        CryptoOK::new(Cow::Owned(encoded)).into()
+    /// URL-safe random token
+    pub fn random_token<'a>(length: usize) -> CryptoResult<'a> {
+        log::info!("Generating random token of {length} bytes.");
+        random::token(length)
+            .map(|s| Ok(CryptoOK::new(Cow::Owned(s))))
+            .unwrap_or_else(|e| {
+                Err(CryptoError::new(
+                    Code::InvalidArgumentsError,
+                    Cow::Owned(e.to_string()),
+                ))
+            })
+            .into()
     }
 
-    pub fn rot_n_decode<'a>(input: Cow<'a, str>, shift: u8) -> CryptoResult<'a> {
-        log::info!("Decoding ROT-N input with shift {shift}: {input}");
-        let decoded = crate::rotn::rot_n_decode(&input, shift);
-        CryptoOK::new(Cow::Owned(decoded)).into()
+    pub fn uuid<'a>() -> CryptoResult<'a> {
+        log::info!("Generating UUIDv4.");
+        random::uuid_v4()
+            .map(|s| Ok(CryptoOK::new(Cow::Owned(s))))
+            .unwrap_or_else(|e| {
+                Err(CryptoError::new(
+                    Code::InvalidArgumentsError,
+                    Cow::Owned(e.to_string()),
+                ))
+            })
+            .into()
     }
-}
 
-#[async_trait]
-impl SharedObject for Crypto {
-    async fn call(&self, method: &str, args: &Value) -> Value {
-        let _ = self.activity_tx.send(());
-        let param: Param = match serde_json::from_value(args.clone()) {
-            Ok(p) => p,
-            Err(e) => {
-                return CryptoResult::from(CryptoError::new(
+    /// Random password from comma-separated character classes (all classes by default)
+    pub fn password<'a>(length: usize, classes: Option<Cow<'a, str>>) -> CryptoResult<'a> {
+        log::info!("Generating password of length {length}.");
+        let classes = match classes {
+            Some(classes) => classes.parse::<CharClasses>(),
+            None => Ok(CharClasses::default()),
+        };
+        classes
+            .and_then(|classes| random::password(length, &classes))
+            .map(|secret| {
+                Ok(CryptoOK::with_entropy(
+                    Cow::Owned(secret.value),
+                    secret.entropy_bits,
+                ))
+            })
+            .unwrap_or_else(|e| {
+                Err(CryptoError::new(
                     Code::InvalidArgumentsError,
                     Cow::Owned(e.to_string()),
                 ))
-                .into();
-            }
+            })
+            .into()
+    }
+
+    /// Random passphrase using the wordlist file at `path` in the base directory
+    pub fn passphrase<'a>(
+        &self,
+        words: usize,
+        path: Option<Cow<'a, str>>,
+        separator: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        log::info!("Generating passphrase of {words} words.");
+        let Some(path) = path else {
+            return CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Wordlist path is required"),
+            )
+            .into();
+        };
+        let path = match self.file_path(&path) {
+            Ok(path) => path,
+            Err(err) => return err.into(),
         };
+        let separator = separator.unwrap_or(Cow::Borrowed("-"));
+        random::load_wordlist(&path)
+            .and_then(|wordlist| random::passphrase(words, &wordlist, &separator))
+            .map(|secret| {
+                Ok(CryptoOK::with_entropy(
+                    Cow::Owned(secret.value),
+                    secret.entropy_bits,
+                ))
+            })
+            .unwrap_or_else(|e| {
+                Err(CryptoError::new(
+                    Code::InvalidArgumentsError,
+                    Cow::Owned(e.to_string()),
+                ))
+            })
+            .into()
+    }
 
-        match method {
-            "decode64" => Crypto::decode_base64(param.input).into(),
-            "encode64" => Crypto::encode_base64(param.input).into(),
-            "decode64-nopad" => Crypto::decode_base64_nopad(param.input).into(),
-            "encode64-nopad" => Crypto::encode_base64_nopad(param.input).into(),
-            "encrypt" => Crypto::encrypt(param.input, param.passphrase).into(),
-            "decrypt" => Crypto::decrypt(param.input, param.passphrase).into(),
-            "decode52" => Crypto::decode_base52(param.input).into(),
-            "encode52" => Crypto::encode_base52(param.input).into(),
-            "scrypt-encrypt" => Crypto::scrypt_encrypt(param.input, param.passphrase).into(),
-            "scrypt-decrypt" => Crypto::scrypt_decrypt(param.input, param.passphrase).into(),
-            "rot8-encode" => Crypto::rot_n_encode(param.input, 8).into(),
-            "rot8-decode" => Crypto::rot_n_decode(param.input, 8).into(),
-            _ => {
-                let msg = format!("Unknown method called: {method}");
-                log::warn!("{msg}");
-                CryptoResult::from(CryptoError::new(Code::UnknownMethodError, Cow::Owned(msg)))
-                    .into()
-            }
-        }
+    /// New random OTP secret, base32 or scrypt-encrypted when a passphrase is given
+    pub fn otp_secret<'a>(passphrase: Cow<'a, str>) -> CryptoResult<'a> {
+        log::info!("Generating OTP secret.");
+        otp::generate_secret()
+            .and_then(|secret| {
+                if passphrase.is_empty() {
+                    Ok(otp::base32_encode(&secret))
+                } else {
+                    otp::encrypt_secret(&secret, &passphrase)
+                }
+            })
+            .map(|s| Ok(CryptoOK::new(Cow::Owned(s))))
+            .unwrap_or_else(|e| {
+                Err(CryptoError::new(
+                    Code::EncryptError,
+                    Cow::Owned(e.to_string()),
+                ))
+            })
+            .into()
+    }
+
+    /// TOTP code at `time` (now by default)
+    fn totp<'a>(options: OtpOptions, time: Option<u64>) -> CryptoResult<'a> {
+        log::info!("Generating TOTP code.");
+        let totp = options.totp();
+        match time {
+            Some(time) => totp.generate_at(time),
+            None => totp.generate(),
+        }
+        .map(|code| Ok(CryptoOK::new(Cow::Owned(code))))
+        .unwrap_or_else(|e| {
+            Err(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Owned(e.to_string()),
+            ))
+        })
+        .into()
+    }
+
+    /// Verify a TOTP code, the result is the clock drift in time steps
+    fn totp_verify<'a>(
+        options: OtpOptions,
+        code: Cow<'a, str>,
+        time: Option<u64>,
+        window: u64,
+    ) -> CryptoResult<'a> {
+        log::info!("Verifying TOTP code with window {window}.");
+        let totp = options.totp();
+        let verified = match time {
+            Some(time) => totp.verify_at(&code, time, window),
+            None => totp.verify(&code, window),
+        };
+        match verified {
+            Ok(Some(drift)) => CryptoOK::new(Cow::Owned(drift.to_string())).into(),
+            Ok(None) => {
+                CryptoError::new(Code::VerifyError, Cow::Borrowed("Invalid TOTP code")).into()
+            }
+            Err(e) => {
+                CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string())).into()
+            }
+        }
+    }
+
+    fn hotp<'a>(options: OtpOptions, counter: u64) -> CryptoResult<'a> {
+        log::info!("Generating HOTP code for counter {counter}.");
+        otp::hotp(&options.secret, counter, options.digits, options.algorithm)
+            .map(|code| Ok(CryptoOK::new(Cow::Owned(code))))
+            .unwrap_or_else(|e| {
+                Err(CryptoError::new(
+                    Code::InvalidArgumentsError,
+                    Cow::Owned(e.to_string()),
+                ))
+            })
+            .into()
+    }
+
+    /// Verify an HOTP code, the result is the counter to use next
+    fn hotp_verify<'a>(
+        options: OtpOptions,
+        code: Cow<'a, str>,
+        counter: u64,
+        window: u64,
+    ) -> CryptoResult<'a> {
+        log::info!("Verifying HOTP code from counter {counter} with look-ahead {window}.");
+        match otp::verify_hotp(
+            &options.secret,
+            &code,
+            counter,
+            window,
+            options.digits,
+            options.algorithm,
+        ) {
+            Ok(Some(next)) => CryptoOK::new(Cow::Owned(next.to_string())).into(),
+            Ok(None) => {
+                CryptoError::new(Code::VerifyError, Cow::Borrowed("Invalid HOTP code")).into()
+            }
+            Err(e) => {
+                CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string())).into()
+            }
+        }
+    }
+
+    /// `otpauth://` provisioning URI, HOTP when a counter is given
+    fn otpauth_uri<'a>(
+        options: OtpOptions,
+        account: Option<Cow<'a, str>>,
+        issuer: Option<Cow<'a, str>>,
+        counter: Option<u64>,
+    ) -> CryptoResult<'a> {
+        log::info!("Generating otpauth URI.");
+        let Some(account) = account else {
+            return CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Account is required"),
+            )
+            .into();
+        };
+        let kind = match counter {
+            Some(counter) => OtpKind::Hotp { counter },
+            None => OtpKind::Totp {
+                period: options.period,
+            },
+        };
+        let auth = OtpAuth {
+            kind,
+            account: account.into_owned(),
+            issuer: issuer.map(Cow::into_owned),
+            secret: options.secret,
+            algorithm: options.algorithm,
+            digits: options.digits,
+        };
+        CryptoOK::new(Cow::Owned(auth.to_uri())).into()
+    }
+
+    /// Parse an `otpauth://` URI into a JSON object with a base32 secret
+    pub fn otpauth_parse<'a>(input: Cow<'a, str>) -> CryptoResult<'a> {
+        log::info!("Parsing otpauth URI.");
+        OtpAuth::parse(&input)
+            .map(|auth| {
+                let mut value = serde_json::json!({
+                    "account": auth.account,
+                    "issuer": auth.issuer,
+                    "secret": otp::base32_encode(&auth.secret),
+                    "algorithm": auth.algorithm.name(),
+                    "digits": auth.digits,
+                });
+                match auth.kind {
+                    OtpKind::Totp { period } => {
+                        value["type"] = "totp".into();
+                        value["period"] = period.into();
+                    }
+                    OtpKind::Hotp { counter } => {
+                        value["type"] = "hotp".into();
+                        value["counter"] = counter.into();
+                    }
+                }
+                Ok(CryptoOK::new(Cow::Owned(value.to_string())))
+            })
+            .unwrap_or_else(|e| {
+                Err(CryptoError::new(
+                    Code::ParseError,
+                    Cow::Owned(e.to_string()),
+                ))
+            })
+            .into()
+    }
+
+    /// New RSA or EC private key, optionally passphrase protected
+    pub fn key_generate<'a>(
+        algorithm: Option<Cow<'a, str>>,
+        format: Option<Cow<'a, str>>,
+        passphrase: Cow<'a, str>,
+    ) -> CryptoResult<'a> {
+        let spec = match algorithm.map(|name| name.parse::<KeySpec>()) {
+            Some(Ok(spec)) => spec,
+            Some(Err(e)) => {
+                return CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+                    .into();
+            }
+            None => KeySpec::default(),
+        };
+        let format = match Crypto::key_format(format) {
+            Ok(format) => format,
+            Err(err) => return err.into(),
+        };
+        log::info!("Generating {spec} key in {format} format.");
+        let passphrase = Some(passphrase.as_ref()).filter(|p| !p.is_empty());
+        keys::generate(spec)
+            .and_then(|key| keys::export_private(&key, format, passphrase))
+            .and_then(|data| keys::encode_text(&data, format))
+            .map(|s| Ok(CryptoOK::new(Cow::Owned(s))))
+            .unwrap_or_else(|e| Err(CryptoError::new(Code::KeyError, Cow::Owned(e.to_string()))))
+            .into()
+    }
+
+    /// Convert a key to another format. `passphrase` opens the input,
+    /// `new_passphrase` protects a private key output.
+    pub fn key_convert<'a>(
+        input: Cow<'a, str>,
+        passphrase: Cow<'a, str>,
+        format: Option<Cow<'a, str>>,
+        public: bool,
+        new_passphrase: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        let format = match Crypto::key_format(format) {
+            Ok(format) => format,
+            Err(err) => return err.into(),
+        };
+        log::info!("Converting key to {format} format.");
+        let passphrase = Some(passphrase.as_ref()).filter(|p| !p.is_empty());
+        let new_passphrase = new_passphrase.as_deref().filter(|p| !p.is_empty());
+        keys::decode_text(&input)
+            .and_then(|data| {
+                if public {
+                    let key = keys::load_public(&data, passphrase)?;
+                    keys::export_public(&key, format)
+                } else {
+                    let key = keys::load_private(&data, passphrase)?;
+                    keys::export_private(&key, format, new_passphrase)
+                }
+            })
+            .and_then(|data| keys::encode_text(&data, format))
+            .map(|s| Ok(CryptoOK::new(Cow::Owned(s))))
+            .unwrap_or_else(|e| Err(CryptoError::new(Code::KeyError, Cow::Owned(e.to_string()))))
+            .into()
+    }
+
+    /// SHA-256 fingerprint of the public key, or its RFC 7638 thumbprint
+    pub fn key_fingerprint<'a>(
+        input: Cow<'a, str>,
+        passphrase: Cow<'a, str>,
+        thumbprint: bool,
+    ) -> CryptoResult<'a> {
+        log::info!("Computing key fingerprint.");
+        let passphrase = Some(passphrase.as_ref()).filter(|p| !p.is_empty());
+        keys::decode_text(&input)
+            .and_then(|data| keys::load_public(&data, passphrase))
+            .and_then(|key| {
+                if thumbprint {
+                    Jwk::public_from(&key)?.thumbprint()
+                } else {
+                    keys::fingerprint(&key)
+                }
+            })
+            .map(|s| Ok(CryptoOK::new(Cow::Owned(s))))
+            .unwrap_or_else(|e| Err(CryptoError::new(Code::KeyError, Cow::Owned(e.to_string()))))
+            .into()
+    }
+
+    /// Sign (JWS) or encrypt (JWE) the JSON claims in `input`
+    fn jwt_sign<'a>(input: Cow<'a, str>, key: TokenKey) -> CryptoResult<'a> {
+        log::info!("Creating JWT.");
+        let claims: serde_json::Value = match serde_json::from_str(&input) {
+            Ok(claims) => claims,
+            Err(e) => return CryptoError::new(Code::ParseError, Cow::Owned(e.to_string())).into(),
+        };
+        match key {
+            TokenKey::Jws(key) => jose::encode_jwt(&claims, &key),
+            TokenKey::Jwe(key) => jose::encrypt_jwt(&claims, &key),
+        }
+        .map(|token| Ok(CryptoOK::new(Cow::Owned(token))))
+        .unwrap_or_else(|e| {
+            Err(CryptoError::new(
+                Code::EncryptError,
+                Cow::Owned(e.to_string()),
+            ))
+        })
+        .into()
+    }
+
+    /// Verify or decrypt a JWT and validate its claims, the result is the claims JSON
+    fn jwt_verify<'a>(
+        input: Cow<'a, str>,
+        key: TokenKey,
+        validation: &Validation,
+    ) -> CryptoResult<'a> {
+        log::info!("Verifying JWT.");
+        match key {
+            TokenKey::Jws(key) => jose::decode_jwt(&input, &key, validation),
+            TokenKey::Jwe(key) => jose::decrypt_jwt(&input, &key, validation),
+        }
+        .map(|claims| Ok(CryptoOK::new(Cow::Owned(claims.to_string()))))
+        .unwrap_or_else(|e| {
+            Err(CryptoError::new(
+                Code::VerifyError,
+                Cow::Owned(e.to_string()),
+            ))
+        })
+        .into()
+    }
+
+    /// Wrap (RFC 3394, or RFC 5649 with `padding`) or unwrap the key in `input` under
+    /// the KEK in `key`. Both and the result use `binary_encoding`, hex by default.
+    pub fn key_wrap<'a>(
+        input: Cow<'a, str>,
+        key: Cow<'a, str>,
+        encoding: Option<Cow<'a, str>>,
+        padding: bool,
+        unwrap: bool,
+    ) -> CryptoResult<'a> {
+        log::info!(
+            "{} key with AES-KW{}.",
+            if unwrap { "Unwrapping" } else { "Wrapping" },
+            if padding { "P" } else { "" }
+        );
+        let encoding = match Crypto::binary_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        let (kek, data) = match (encoding.decode(&key), encoding.decode(&input)) {
+            (Ok(kek), Ok(data)) => (kek, data),
+            (Err(e), _) | (_, Err(e)) => {
+                return CryptoError::new(Code::DecodeError, Cow::Owned(e.to_string())).into();
+            }
+        };
+        let (result, code) = match (unwrap, padding) {
+            (false, false) => (keywrap::wrap(&kek, &data), Code::EncryptError),
+            (false, true) => (keywrap::wrap_with_padding(&kek, &data), Code::EncryptError),
+            (true, false) => (keywrap::unwrap(&kek, &data), Code::DecryptError),
+            (true, true) => (
+                keywrap::unwrap_with_padding(&kek, &data),
+                Code::DecryptError,
+            ),
+        };
+        result
+            .map(|bytes| Ok(CryptoOK::new(Cow::Owned(encoding.encode(&bytes)))))
+            .unwrap_or_else(|e| Err(CryptoError::new(code, Cow::Owned(e.to_string()))))
+            .into()
+    }
+
+    /// Deterministic AES-SIV: equal plaintexts under the same key and `aad` give equal
+    /// ciphertexts. Key and ciphertext use `binary_encoding`, plaintext and `aad` the
+    /// payload `encoding`.
+    pub fn deterministic_crypt<'a>(
+        input: Cow<'a, str>,
+        key: Cow<'a, str>,
+        aad: Option<Cow<'a, str>>,
+        encoding: Option<Cow<'a, str>>,
+        binary_encoding: Option<Cow<'a, str>>,
+        decrypt: bool,
+    ) -> CryptoResult<'a> {
+        log::info!(
+            "{} input with deterministic AES-SIV.",
+            if decrypt { "Decrypting" } else { "Encrypting" }
+        );
+        let binary_encoding = match Crypto::binary_encoding(binary_encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        let key = match binary_encoding.decode(&key) {
+            Ok(key) => key,
+            Err(e) => {
+                return CryptoError::new(Code::DecodeError, Cow::Owned(e.to_string())).into();
+            }
+        };
+        let aad = match aad
+            .map(|aad| Crypto::payload_input(&aad, encoding.clone()))
+            .transpose()
+        {
+            Ok(aad) => aad,
+            Err(err) => return err.into(),
+        };
+        let associated_data: Vec<&[u8]> = aad.iter().map(Vec::as_slice).collect();
+
+        if !decrypt {
+            let plaintext = match Crypto::payload_input(&input, encoding) {
+                Ok(plaintext) => plaintext,
+                Err(err) => return err.into(),
+            };
+            return siv::encrypt_deterministic(&key, &plaintext, &associated_data)
+                .map(|sealed| Ok(CryptoOK::new(Cow::Owned(binary_encoding.encode(&sealed)))))
+                .unwrap_or_else(|e| {
+                    Err(CryptoError::new(
+                        Code::EncryptError,
+                        Cow::Owned(e.to_string()),
+                    ))
+                })
+                .into();
+        }
+        let encoding = match Crypto::payload_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        let sealed = match binary_encoding.decode(&input) {
+            Ok(sealed) => sealed,
+            Err(e) => {
+                return CryptoError::new(Code::DecodeError, Cow::Owned(e.to_string())).into();
+            }
+        };
+        match siv::decrypt_deterministic(&key, &sealed, &associated_data) {
+            Ok(plaintext) => Crypto::payload_output(plaintext, encoding, Code::DecodeError).into(),
+            Err(e) => CryptoError::new(Code::DecryptError, Cow::Owned(e.to_string())).into(),
+        }
+    }
+
+    /// FF1 or FF3-1 over `alphabet` (digits by default); key and tweak use the binary encoding
+    pub fn fpe_crypt<'a>(
+        input: Cow<'a, str>,
+        key: Cow<'a, str>,
+        tweak: Option<Cow<'a, str>>,
+        alphabet: Option<Cow<'a, str>>,
+        mode: Option<Cow<'a, str>>,
+        encoding: Option<Cow<'a, str>>,
+        decrypt: bool,
+    ) -> CryptoResult<'a> {
+        let mode = match mode.as_deref().unwrap_or("ff1").parse::<FpeMode>() {
+            Ok(mode) => mode,
+            Err(e) => {
+                return CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+                    .into();
+            }
+        };
+        log::info!(
+            "{} input with {mode} format-preserving encryption.",
+            if decrypt { "Decrypting" } else { "Encrypting" }
+        );
+        let alphabet = match alphabet.as_deref().map(str::parse::<Alphabet>) {
+            Some(Ok(alphabet)) => alphabet,
+            Some(Err(e)) => {
+                return CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+                    .into();
+            }
+            None => Alphabet::digits(),
+        };
+        let encoding = match Crypto::binary_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        let (key, tweak) = match (
+            encoding.decode(&key),
+            encoding.decode(tweak.as_deref().unwrap_or_default()),
+        ) {
+            (Ok(key), Ok(tweak)) => (key, tweak),
+            (Err(e), _) | (_, Err(e)) => {
+                return CryptoError::new(Code::DecodeError, Cow::Owned(e.to_string())).into();
+            }
+        };
+
+        let (result, code) = if decrypt {
+            (
+                fpe::decrypt(mode, &key, &tweak, &alphabet, &input),
+                Code::DecryptError,
+            )
+        } else {
+            (
+                fpe::encrypt(mode, &key, &tweak, &alphabet, &input),
+                Code::EncryptError,
+            )
+        };
+        match result {
+            Ok(output) => CryptoOK::new(Cow::Owned(output)).into(),
+            Err(e) => CryptoError::new(code, Cow::Owned(e.to_string())).into(),
+        }
+    }
+
+    /// Provider of the configured master key `name`. Where the key is kept and why it
+    /// cannot be loaded only go to the log.
+    fn envelope_provider<'a>(
+        name: &str,
+        key: &EnvelopeKey,
+    ) -> Result<Box<dyn KeyProvider>, CryptoError<'a>> {
+        key.provider_config()
+            .and_then(|config| config.provider())
+            .map_err(|e| {
+                log::error!("Envelope master key {name} is unavailable: {e}");
+                CryptoError::new(
+                    Code::KeyError,
+                    Cow::Owned(format!("Master key {name} is unavailable")),
+                )
+            })
+    }
+
+    /// Provider of the configured master key `name`, else of the key `sealed` was
+    /// sealed with, else of the only configured key
+    fn envelope_key<'a>(
+        &self,
+        name: Option<&str>,
+        sealed: Option<&Envelope>,
+    ) -> Result<Box<dyn KeyProvider>, CryptoError<'a>> {
+        let keys = &self.config.envelope.keys;
+        let invalid = |msg: Cow<'a, str>| CryptoError::new(Code::InvalidArgumentsError, msg);
+        match (name, sealed) {
+            (Some(name), _) => {
+                let (name, key) = keys
+                    .get_key_value(name)
+                    .ok_or_else(|| invalid(Cow::Owned(format!("Unknown master key: {name}"))))?;
+                Crypto::envelope_provider(name, key)
+            }
+            (None, Some(envelope)) => keys
+                .iter()
+                .filter_map(|(name, key)| Crypto::envelope_provider(name, key).ok())
+                .find(|provider| {
+                    provider.name() == envelope.provider && provider.key_id() == envelope.key_id
+                })
+                .ok_or_else(|| {
+                    CryptoError::new(
+                        Code::KeyError,
+                        Cow::Borrowed("No configured master key opens the envelope"),
+                    )
+                }),
+            (None, None) => match keys.iter().next() {
+                Some((name, key)) if keys.len() == 1 => Crypto::envelope_provider(name, key),
+                Some(_) => Err(invalid(Cow::Borrowed("Master key name is required"))),
+                None => Err(invalid(Cow::Borrowed(
+                    "No envelope master keys are configured",
+                ))),
+            },
+        }
+    }
+
+    /// Envelope JSON of `input` under a fresh data key wrapped by the master key `name`
+    fn envelope_encrypt<'a>(
+        &self,
+        input: Cow<'a, str>,
+        name: Option<&str>,
+        cipher: Option<Cow<'a, str>>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        let plaintext = match Crypto::payload_input(&input, encoding) {
+            Ok(plaintext) => plaintext,
+            Err(err) => return err.into(),
+        };
+        let cipher = match cipher.as_deref().map(str::parse::<AeadCipher>) {
+            Some(Ok(cipher)) => cipher,
+            Some(Err(e)) => {
+                return CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+                    .into();
+            }
+            None => AeadCipher::Aes256Gcm,
+        };
+        let provider = match self.envelope_key(name, None) {
+            Ok(provider) => provider,
+            Err(err) => return err.into(),
+        };
+        log::info!(
+            "Sealing input in an envelope with the {} key provider.",
+            provider.name()
+        );
+        match envelope::seal(provider.as_ref(), &plaintext, cipher)
+            .and_then(|envelope| envelope.to_json())
+        {
+            Ok(json) => CryptoOK::new(Cow::Owned(json)).into(),
+            Err(e) => CryptoError::new(Code::EncryptError, Cow::Owned(e.to_string())).into(),
+        }
+    }
+
+    /// Plaintext of an envelope; the master key defaults to the configured one it was
+    /// sealed with
+    fn envelope_decrypt<'a>(
+        &self,
+        input: Cow<'a, str>,
+        name: Option<&str>,
+        encoding: Option<Cow<'a, str>>,
+    ) -> CryptoResult<'a> {
+        let encoding = match Crypto::payload_encoding(encoding) {
+            Ok(encoding) => encoding,
+            Err(err) => return err.into(),
+        };
+        let envelope = match Envelope::from_json(&input) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                return CryptoError::new(Code::DecodeError, Cow::Owned(e.to_string())).into();
+            }
+        };
+        let provider = match self.envelope_key(name, Some(&envelope)) {
+            Ok(provider) => provider,
+            Err(err) => return err.into(),
+        };
+        log::info!(
+            "Opening envelope with the {} key provider.",
+            provider.name()
+        );
+        match envelope::open(provider.as_ref(), &envelope) {
+            Ok(plaintext) => Crypto::payload_output(plaintext, encoding, Code::DecodeError).into(),
+            Err(e) => CryptoError::new(Code::DecryptError, Cow::Owned(e.to_string())).into(),
+        }
+    }
+
+    /// `path` inside the configured base directory
+    fn file_path<'a>(&self, path: &str) -> Result<PathBuf, CryptoError<'a>> {
+        self.config
+            .files
+            .resolve(path)
+            .map_err(|e| CryptoError::new(Code::PermissionDenied, Cow::Owned(e.to_string())))
+    }
+
+    /// Input and output paths of a file job; an existing output is only replaced
+    /// with `overwrite`
+    fn job_paths<'a>(
+        &self,
+        path: Option<Cow<'a, str>>,
+        output: Option<Cow<'a, str>>,
+        overwrite: bool,
+    ) -> Result<(PathBuf, PathBuf), CryptoError<'a>> {
+        let (Some(path), Some(output)) = (path, output) else {
+            return Err(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Input path and output path are required"),
+            ));
+        };
+        let (input, output_path) = (self.file_path(&path)?, self.file_path(&output)?);
+        if !input.is_file() {
+            return Err(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Owned(format!("Input file '{path}' does not exist")),
+            ));
+        }
+        if !overwrite && output_path.symlink_metadata().is_ok() {
+            return Err(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Owned(format!("Output file '{output}' already exists")),
+            ));
+        }
+        Ok((input, output_path))
+    }
+
+    /// Start encrypting the file at `path` into `output` with the KDF and cipher in
+    /// `param`; returns the job id
+    fn encrypt_file_job<'a>(&self, param: Param<'a>) -> CryptoResult<'a> {
+        let kdf_options = param.kdf_options();
+        let passphrase = param.passphrase;
+        if let Some(err) = Crypto::require_passphrase(passphrase.clone(), Code::EncryptError) {
+            return err;
+        }
+        let (input, output) = match self.job_paths(param.path, param.output, param.overwrite) {
+            Ok(paths) => paths,
+            Err(err) => return err.into(),
+        };
+        let kdf = match Crypto::kdf(param.kdf.as_deref(), &kdf_options) {
+            Ok(kdf) => kdf,
+            Err(err) => return err.into(),
+        };
+        let cipher = match param
+            .cipher
+            .as_deref()
+            .map(str::parse::<AeadCipher>)
+            .transpose()
+        {
+            Ok(cipher) => cipher,
+            Err(e) => {
+                return CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string()))
+                    .into();
+            }
+        };
+
+        let passphrase = passphrase.into_owned();
+        let started = self.jobs.spawn(
+            "encrypt-file",
+            output,
+            Some(self.activity_tx.clone()),
+            move |output, progress_tx| async move {
+                let interval = jobs::PROGRESS_INTERVAL;
+                match (kdf, cipher) {
+                    (kdf, Some(cipher)) => {
+                        let kdf = kdf.unwrap_or_else(|| Box::new(kdf::Scrypt::default()));
+                        async_file_gcm::encrypt_file_with_cipher(
+                            &input,
+                            &output,
+                            &passphrase,
+                            kdf.as_ref(),
+                            cipher,
+                            progress_tx,
+                            interval,
+                        )
+                        .await
+                    }
+                    (Some(kdf), None) => {
+                        async_file_gcm::encrypt_file_with_kdf(
+                            &input,
+                            &output,
+                            &passphrase,
+                            kdf.as_ref(),
+                            progress_tx,
+                            interval,
+                        )
+                        .await
+                    }
+                    (None, None) => {
+                        async_file_gcm::encrypt_file(
+                            &input,
+                            &output,
+                            &passphrase,
+                            progress_tx,
+                            interval,
+                        )
+                        .await
+                    }
+                }
+            },
+        );
+        Crypto::job_started(started, "encryption")
+    }
+
+    /// Start decrypting the file at `path` into `output`; returns the job id
+    fn decrypt_file_job<'a>(
+        &self,
+        path: Option<Cow<'a, str>>,
+        output: Option<Cow<'a, str>>,
+        overwrite: bool,
+        passphrase: Cow<'a, str>,
+    ) -> CryptoResult<'a> {
+        if let Some(err) = Crypto::require_passphrase(passphrase.clone(), Code::DecryptError) {
+            return err;
+        }
+        let (input, output) = match self.job_paths(path, output, overwrite) {
+            Ok(paths) => paths,
+            Err(err) => return err.into(),
+        };
+
+        let passphrase = passphrase.into_owned();
+        let limits = self.config.limits.kdf_limits();
+        let started = self.jobs.spawn(
+            "decrypt-file",
+            output,
+            Some(self.activity_tx.clone()),
+            move |output, progress_tx| async move {
+                async_file_gcm::decrypt_file_with_limits(
+                    &input,
+                    &output,
+                    &passphrase,
+                    &limits,
+                    progress_tx,
+                    jobs::PROGRESS_INTERVAL,
+                )
+                .await
+            },
+        );
+        Crypto::job_started(started, "decryption")
+    }
+
+    /// Id of a started job, or QueueFull when the most jobs allowed are running
+    fn job_started<'a>(started: Result<JobId, PoolError>, operation: &str) -> CryptoResult<'a> {
+        match started {
+            Ok(id) => {
+                log::info!("Started file {operation} job {id}.");
+                CryptoOK::new(Cow::Owned(id.to_string())).into()
+            }
+            Err(e) => {
+                log::warn!("File {operation} job not started: {e}");
+                CryptoError::new(Code::QueueFull, Cow::Owned(e.to_string())).into()
+            }
+        }
+    }
+
+    /// JSON status of a job, or of the job whose cancellation was requested
+    fn job_status<'a>(&self, job: Option<JobId>, cancel: bool) -> CryptoResult<'a> {
+        let Some(id) = job else {
+            return CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Job is required"),
+            )
+            .into();
+        };
+        let status = if cancel {
+            log::info!("Cancelling job {id}.");
+            self.jobs.cancel(id)
+        } else {
+            self.jobs
+                .status(id)
+                .ok_or_else(|| anyhow::anyhow!("unknown job: {id}"))
+        };
+        match status.map(|status| serde_json::to_string(&status)) {
+            Ok(Ok(json)) => CryptoOK::new(Cow::Owned(json)).into(),
+            Ok(Err(e)) => CryptoError::new(Code::EncodeError, Cow::Owned(e.to_string())).into(),
+            Err(e) => {
+                CryptoError::new(Code::InvalidArgumentsError, Cow::Owned(e.to_string())).into()
+            }
+        }
+    }
+
+    pub fn rot_n_encode<'a>(input: Cow<'a, str>, shift: u8) -> CryptoResult<'a> {
+        log::info!("Encoding ROT-N input with shift {shift}: {input}");
+        let encoded = crate::rotn::rot_n_encode(&input, shift);
+        CryptoOK::new(Cow::Owned(encoded)).into()
+    }
+
+    pub fn rot_n_decode<'a>(input: Cow<'a, str>, shift: u8) -> CryptoResult<'a> {
+        log::info!("Decoding ROT-N input with shift {shift}: {input}");
+        let decoded = crate::rotn::rot_n_decode(&input, shift);
+        CryptoOK::new(Cow::Owned(decoded)).into()
+    }
+
+    /// scrypt-encrypt with the KDF, KDF options and cipher in `param`
+    fn scrypt_encrypt_param<'a>(param: Param<'a>) -> CryptoResult<'a> {
+        let options = param.kdf_options();
+        match (param.kdf, param.cipher) {
+            (kdf, Some(cipher)) => Crypto::scrypt_encrypt_with_cipher(
+                param.input,
+                param.passphrase,
+                kdf.as_deref(),
+                &options,
+                &cipher,
+                param.encoding,
+            ),
+            (None, None) if options.is_empty() => {
+                Crypto::scrypt_encrypt(param.input, param.passphrase, param.encoding)
+            }
+            (kdf, None) => Crypto::scrypt_encrypt_with_kdf(
+                param.input,
+                param.passphrase,
+                kdf.as_deref().unwrap_or(KdfAlgorithm::Scrypt.name()),
+                &options,
+                param.encoding,
+            ),
+        }
+    }
+
+    /// `encrypt` in the scrypt-encrypt format with the AEAD cipher named by `algorithm`;
+    /// the legacy format can only be decrypted
+    fn encrypt_with_algorithm<'a>(&self, mut param: Param<'a>) -> CryptoResult<'a> {
+        let cipher = param
+            .algorithm
+            .take()
+            .unwrap_or(Cow::Borrowed(AeadCipher::Aes256Gcm.name()));
+        if cipher.eq_ignore_ascii_case(LEGACY_CIPHER) {
+            return CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("aes-256-cbc ciphertexts can only be decrypted"),
+            )
+            .into();
+        }
+        param.cipher = Some(cipher);
+        Crypto::scrypt_encrypt_param(param.with_kdf_defaults(&self.config))
+    }
+
+    /// `decrypt` of the scrypt-encrypt format or of the legacy format, as named by
+    /// `algorithm` or else detected from the cipher header
+    fn decrypt_with_algorithm<'a>(&self, param: Param<'a>) -> CryptoResult<'a> {
+        // Undecodable input is reported by the decrypt method it is handed to
+        let recorded = general_purpose::STANDARD
+            .decode(param.input.as_bytes())
+            .ok()
+            .and_then(|sealed| aead::decode_header(&sealed).ok().flatten())
+            .map(|(recorded, _)| recorded);
+        match param.algorithm.as_deref() {
+            Some(name) if name.eq_ignore_ascii_case(LEGACY_CIPHER) => {
+                Crypto::decrypt(param.input, param.passphrase, param.encoding)
+            }
+            Some(name) => {
+                let cipher = match name.parse::<AeadCipher>() {
+                    Ok(cipher) => cipher,
+                    Err(e) => {
+                        return CryptoError::new(
+                            Code::InvalidArgumentsError,
+                            Cow::Owned(e.to_string()),
+                        )
+                        .into();
+                    }
+                };
+                if recorded != Some(cipher) {
+                    return CryptoError::new(
+                        Code::InvalidArgumentsError,
+                        Cow::Owned(format!("Ciphertext was not encrypted with {cipher}")),
+                    )
+                    .into();
+                }
+                self.scrypt_decrypt(param.input, param.passphrase, param.encoding)
+            }
+            None if recorded.is_some() => {
+                self.scrypt_decrypt(param.input, param.passphrase, param.encoding)
+            }
+            None => Crypto::decrypt(param.input, param.passphrase, param.encoding),
+        }
+    }
+
+    /// Sign or verify a JWT with the key and claim checks in `param`
+    fn jwt<'a>(param: Param<'a>, signing: bool) -> CryptoResult<'a> {
+        let key = match TokenKey::from_param(
+            param
+                .algorithm
+                .as_deref()
+                .unwrap_or(JwsAlgorithm::Hs256.name()),
+            &param.key,
+            &param.passphrase,
+            param.binary_encoding,
+            signing,
+        ) {
+            Ok(key) => key,
+            Err(err) => return err.into(),
+        };
+        if signing {
+            return Crypto::jwt_sign(param.input, key);
+        }
+        let validation = Validation {
+            leeway: param.leeway.unwrap_or(jose::DEFAULT_LEEWAY),
+            audience: param.audience.map(Cow::into_owned),
+            issuer: param.issuer.map(Cow::into_owned),
+            require_exp: false,
+            now: param.time,
+        };
+        Crypto::jwt_verify(param.input, key, &validation)
+    }
+
+    /// Seal or open an envelope with the configured master key selected in `param`
+    fn envelope<'a>(&self, param: Param<'a>, decrypt: bool) -> CryptoResult<'a> {
+        let name = param.master_key.as_deref();
+        if decrypt {
+            self.envelope_decrypt(param.input, name, param.encoding)
+        } else {
+            self.envelope_encrypt(param.input, name, param.cipher, param.encoding)
+        }
+    }
+
+    /// Run one of the totp/hotp methods with the OTP settings in `param`
+    fn otp<'a>(&self, method: &str, param: Param<'a>) -> CryptoResult<'a> {
+        let options = match OtpOptions::from_param(&param, &self.config.limits.kdf_limits()) {
+            Ok(options) => options,
+            Err(err) => return err.into(),
+        };
+        let window = param.window.unwrap_or(1);
+        match (method, param.counter) {
+            ("totp", _) => Crypto::totp(options, param.time),
+            ("totp-verify", _) => Crypto::totp_verify(options, param.input, param.time, window),
+            ("otpauth-uri", counter) => {
+                Crypto::otpauth_uri(options, param.account, param.issuer, counter)
+            }
+            (_, None) => CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Counter is required"),
+            )
+            .into(),
+            ("hotp", Some(counter)) => Crypto::hotp(options, counter),
+            (_, Some(counter)) => Crypto::hotp_verify(options, param.input, counter, window),
+        }
+    }
+
+    /// Run each operation as its own call would, concurrently when `parallel` is set;
+    /// the result is the JSON array of per-operation results
+    async fn batch(
+        &self,
+        caller: &Caller,
+        operations: Option<Vec<BatchOperation>>,
+        parallel: bool,
+    ) -> Value {
+        let Some(operations) = operations else {
+            return CryptoResult::from(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Operations are required"),
+            ))
+            .into();
+        };
+        let max_batch_size = self.config.limits.max_batch_size;
+        if operations.len() > max_batch_size {
+            return CryptoResult::from(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Owned(format!(
+                    "A batch holds at most {max_batch_size} operations, got {}",
+                    operations.len()
+                )),
+            ))
+            .into();
+        }
+        let max_batch_bytes = self.config.limits.max_batch_bytes;
+        let batch_bytes: usize = operations
+            .iter()
+            .map(|operation| {
+                let args: usize = operation
+                    .args
+                    .iter()
+                    .map(|(key, value)| key.len() + string_bytes(value))
+                    .sum();
+                operation.method.len() + args
+            })
+            .sum();
+        if batch_bytes > max_batch_bytes {
+            return CryptoResult::from(CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Owned(format!(
+                    "A batch holds at most {max_batch_bytes} bytes of operations, got {batch_bytes}"
+                )),
+            ))
+            .into();
+        }
+        log::info!(
+            "Running a batch of {} operations{}.",
+            operations.len(),
+            if parallel { " in parallel" } else { "" }
+        );
+
+        let results = if parallel {
+            let tasks = operations
+                .into_iter()
+                .map(|operation| {
+                    let crypto = self.clone();
+                    let caller = caller.clone();
+                    async move { crypto.batch_operation(&caller, operation).await }
+                })
+                .collect();
+            // No more operations in flight than the pool has threads, so a batch alone
+            // never fills the pool's queue
+            run_limited(tasks, self.pool.max_concurrent()).await
+        } else {
+            let mut results = Vec::with_capacity(operations.len());
+            for operation in operations {
+                results.push(self.batch_operation(caller, operation).await);
+            }
+            results
+        };
+        CryptoResult::from(CryptoOK::new(Cow::Owned(Value::from(results).to_string()))).into()
+    }
+
+    /// Boxed because a batch operation runs through `dispatch`, which runs `batch`;
+    /// each operation is checked against the access policy like a call of its own
+    fn batch_operation<'a>(
+        &'a self,
+        caller: &'a Caller,
+        operation: BatchOperation,
+    ) -> BoxFuture<'a, Value> {
+        Box::pin(async move {
+            if operation.method.eq_ignore_ascii_case("batch") {
+                return CryptoResult::from(CryptoError::new(
+                    Code::InvalidArgumentsError,
+                    Cow::Borrowed("Batches cannot be nested"),
+                ))
+                .into();
+            }
+            self.dispatch(caller, &operation.method, Value::Object(operation.args))
+                .await
+        })
+    }
+
+    /// JSON array describing every method of the enabled groups
+    pub fn list_methods<'a>(&self) -> CryptoResult<'a> {
+        let methods: Vec<Value> = METHODS
+            .iter()
+            .filter(|method| self.config.enables(method.spec.group))
+            .map(|method| method.spec.describe())
+            .collect();
+        CryptoOK::new(Cow::Owned(Value::from(methods).to_string())).into()
+    }
+
+    /// JSON description of `method`: parameters as JSON Schema and possible error codes
+    pub fn describe<'a>(&self, method: Option<Cow<'a, str>>) -> CryptoResult<'a> {
+        let Some(name) = method else {
+            return CryptoError::new(
+                Code::InvalidArgumentsError,
+                Cow::Borrowed("Method is required"),
+            )
+            .into();
+        };
+        match self.method(&name) {
+            Some(method) => CryptoOK::new(Cow::Owned(method.spec.describe().to_string())).into(),
+            None => CryptoError::new(
+                Code::UnknownMethodError,
+                Cow::Owned(format!("Unknown method: {name}")),
+            )
+            .into(),
+        }
+    }
+
+    /// Crate version and the algorithms each family of methods accepts
+    pub fn version<'a>() -> CryptoResult<'a> {
+        let version = json!({
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "algorithms": {
+                "hash": HashAlgorithm::ALL.map(|algorithm| algorithm.name()),
+                "kdf": password_kdf_names().collect::<Vec<_>>(),
+                "cipher": AeadCipher::ALL.map(|cipher| cipher.name()),
+                "fpe": FpeMode::ALL.map(|mode| mode.name()),
+                "jws": JwsAlgorithm::ALL.map(|algorithm| algorithm.name()),
+                "otp": OtpAlgorithm::ALL.map(|algorithm| algorithm.name()),
+                "encoding": std::iter::once(PayloadEncoding::Utf8.name())
+                    .chain(Encoding::ALL.map(|encoding| encoding.name()))
+                    .collect::<Vec<_>>(),
+                "binary_encoding": Encoding::ALL.map(|encoding| encoding.name()),
+            },
+        });
+        CryptoOK::new(Cow::Owned(version.to_string())).into()
+    }
+}
+
+/// Bytes of the strings in `value`, object keys included
+fn string_bytes(value: &Value) -> usize {
+    match value {
+        Value::String(string) => string.len(),
+        Value::Array(items) => items.iter().map(string_bytes).sum(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| key.len() + string_bytes(value))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Results of `tasks` in order, running at most `limit` at once. The slot of a task
+/// that panicked holds an InternalError.
+async fn run_limited<F>(tasks: Vec<F>, limit: usize) -> Vec<Value>
+where
+    F: Future<Output = Value> + Send + 'static,
+{
+    let mut results = vec![Value::Null; tasks.len()];
+    let mut indices = HashMap::new();
+    let mut pending = tasks.into_iter().enumerate();
+    let mut running = JoinSet::new();
+    loop {
+        while running.len() < limit.max(1)
+            && let Some((index, task)) = pending.next()
+        {
+            indices.insert(running.spawn(task).id(), index);
+        }
+        match running.join_next_with_id().await {
+            Some(Ok((id, result))) => results[indices[&id]] = result,
+            Some(Err(e)) => {
+                log::error!("Batch operation failed: {e}");
+                results[indices[&e.id()]] = CryptoResult::from(CryptoError::new(
+                    Code::InternalError,
+                    Cow::Owned(format!("Operation failed: {e}")),
+                ))
+                .into();
+            }
+            None => break,
+        }
+    }
+    results
+}
+
+type SyncHandler = Box<dyn for<'a> Fn(&Crypto, Param<'a>) -> CryptoResult<'a> + Send + Sync>;
+
+/// How a method runs
+enum Handler {
+    /// Cheap methods, run directly on the async worker
+    Inline(SyncHandler),
+    /// CPU-heavy or file-reading methods, run on the blocking pool
+    Blocking(SyncHandler),
+    /// `batch`, which dispatches its operations itself
+    Batch,
+}
+
+/// An IPC method: its description and the function that runs it
+struct Method {
+    spec: MethodSpec,
+    handler: Handler,
+}
+
+impl Method {
+    fn new<F>(spec: MethodSpec, handler: F) -> Method
+    where
+        F: for<'a> Fn(&Crypto, Param<'a>) -> CryptoResult<'a> + Send + Sync + 'static,
+    {
+        Method::with_handler(spec, Handler::Inline(Box::new(handler)))
+    }
+
+    fn blocking<F>(spec: MethodSpec, handler: F) -> Method
+    where
+        F: for<'a> Fn(&Crypto, Param<'a>) -> CryptoResult<'a> + Send + Sync + 'static,
+    {
+        Method::with_handler(
+            spec.errors(&[Code::QueueFull]),
+            Handler::Blocking(Box::new(handler)),
+        )
+    }
+
+    fn with_handler(spec: MethodSpec, handler: Handler) -> Method {
+        Method { spec, handler }
+    }
+}
+
+/// Every IPC method; dispatch, `list-methods`, `describe` and `version` all read this
+static METHODS: LazyLock<Vec<Method>> = LazyLock::new(registry);
+
+fn find_method(name: &str) -> Option<&'static Method> {
+    METHODS
+        .iter()
+        .find(|method| method.spec.name.eq_ignore_ascii_case(name))
+}
+
+fn input_param(description: &'static str) -> ParamSpec {
+    ParamSpec::string("input", description).required()
+}
+
+fn passphrase_param(description: &'static str) -> ParamSpec {
+    ParamSpec::string("passphrase", description)
+}
+
+/// `encoding` of a payload that may be binary
+fn payload_encoding_param(description: &'static str) -> ParamSpec {
+    ParamSpec::string("encoding", description)
+        .default(ParamDefault::String(PayloadEncoding::Utf8.name()))
+        .one_of(
+            std::iter::once(PayloadEncoding::Utf8.name())
+                .chain(Encoding::ALL.iter().map(Encoding::name)),
+        )
+}
+
+/// `binary_encoding` of keys, digests and other binary values
+fn binary_encoding_param(description: &'static str) -> ParamSpec {
+    ParamSpec::string("binary_encoding", description)
+        .default(ParamDefault::String(Encoding::default().name()))
+        .one_of(Encoding::ALL.iter().map(Encoding::name))
+}
+
+/// KDFs accepted for passphrases; HKDF is only used with random keys
+fn password_kdf_names() -> impl Iterator<Item = &'static str> {
+    KdfAlgorithm::ALL
+        .into_iter()
+        .filter(KdfAlgorithm::is_password_kdf)
+        .map(|algorithm| algorithm.name())
+}
+
+fn kdf_param() -> ParamSpec {
+    ParamSpec::string("kdf", "Key derivation function")
+        .default(ParamDefault::String(KdfAlgorithm::Scrypt.name()))
+        .one_of(password_kdf_names())
+}
+
+fn cipher_param() -> ParamSpec {
+    ParamSpec::string("cipher", "AEAD cipher")
+        .default(ParamDefault::String(AeadCipher::Aes256Gcm.name()))
+        .one_of(AeadCipher::ALL.iter().map(AeadCipher::name))
+}
+
+/// Cost parameters overriding the KDF defaults
+fn kdf_option_params() -> Vec<ParamSpec> {
+    vec![
+        ParamSpec::integer("iterations", "PBKDF2 iterations or Argon2id passes"),
+        ParamSpec::integer("memory", "Argon2id memory in KiB"),
+        ParamSpec::integer("parallelism", "Argon2id lanes or scrypt p"),
+        ParamSpec::integer("cost", "scrypt N, a power of two"),
+    ]
+}
+
+/// `algorithm` of encrypt: an AEAD cipher
+fn encrypt_algorithm_param() -> ParamSpec {
+    ParamSpec::string("algorithm", "AEAD cipher")
+        .default(ParamDefault::String(AeadCipher::Aes256Gcm.name()))
+        .one_of(AeadCipher::ALL.iter().map(AeadCipher::name))
+}
+
+/// `algorithm` of decrypt: an AEAD cipher or the legacy format
+fn decrypt_algorithm_param() -> ParamSpec {
+    ParamSpec::string(
+        "algorithm",
+        "Cipher, detected from the ciphertext when omitted",
+    )
+    .one_of(std::iter::once(LEGACY_CIPHER).chain(AeadCipher::ALL.iter().map(AeadCipher::name)))
+}
+
+fn shift_param() -> ParamSpec {
+    ParamSpec::integer("shift", "Letters to rotate by, 0-255")
+        .default(ParamDefault::Integer(u64::from(DEFAULT_SHIFT)))
+}
+
+fn otp_params(spec: MethodSpec) -> MethodSpec {
+    spec.param(
+        ParamSpec::string(
+            "key",
+            "Base32 secret, or scrypt-encrypted secret with a passphrase",
+        )
+        .required(),
+    )
+    .param(passphrase_param("Passphrase of an encrypted secret"))
+    .param(
+        ParamSpec::integer("digits", "Code length")
+            .default(ParamDefault::Integer(u64::from(otp::DEFAULT_DIGITS))),
+    )
+    .param(
+        ParamSpec::integer("period", "Time step in seconds")
+            .default(ParamDefault::Integer(otp::DEFAULT_PERIOD)),
+    )
+    .param(
+        ParamSpec::string("algorithm", "HMAC algorithm")
+            .default(ParamDefault::String(OtpAlgorithm::default().name()))
+            .one_of(OtpAlgorithm::ALL.iter().map(OtpAlgorithm::name)),
+    )
+    .errors(&[Code::DecodeError, Code::DecryptError])
+}
+
+fn jwt_params(spec: MethodSpec) -> MethodSpec {
+    spec.param(
+        ParamSpec::string(
+            "algorithm",
+            "JWS algorithm, dir or a PBES2 key management algorithm",
+        )
+        .default(ParamDefault::String(JwsAlgorithm::Hs256.name())),
+    )
+    .param(ParamSpec::string(
+        "key",
+        "HS256 secret, PEM/JWK/base64 DER key, or encoded dir key",
+    ))
+    .param(passphrase_param(
+        "Passphrase of an encrypted key, or the PBES2 password",
+    ))
+    .param(binary_encoding_param("Encoding of a dir key"))
+    .errors(&[Code::KeyError])
+}
+
+fn envelope_params(spec: MethodSpec) -> MethodSpec {
+    spec.param(ParamSpec::string(
+        "master_key",
+        "Name of a configured master key, needed when several are configured",
+    ))
+}
+
+fn file_job_params(spec: MethodSpec) -> MethodSpec {
+    spec.param(ParamSpec::string("path", "Input file, relative to the base directory").required())
+        .param(
+            ParamSpec::string("output", "Output file, relative to the base directory").required(),
+        )
+        .param(ParamSpec::boolean(
+            "overwrite",
+            "Replace an existing output file",
+        ))
+        .param(passphrase_param("Passphrase").required())
+}
+
+fn job_param(spec: MethodSpec) -> MethodSpec {
+    spec.param(ParamSpec::integer("job", "Job id").required())
+        .errors(&[Code::EncodeError])
+}
+
+fn registry() -> Vec<Method> {
+    let mut methods = vec![
+        Method::new(
+            MethodSpec::new("decode64", "Decode standard base64")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Base64 text"))
+                .param(payload_encoding_param("Encoding of the decoded bytes"))
+                .errors(&[Code::DecodeError, Code::ParseError]),
+            |_, param| Crypto::decode_base64(param.input, param.encoding),
+        ),
+        Method::new(
+            MethodSpec::new("encode64", "Encode as standard base64")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Payload"))
+                .param(payload_encoding_param("Encoding of the input"))
+                .errors(&[Code::DecodeError]),
+            |_, param| Crypto::encode_base64(param.input, param.encoding),
+        ),
+        Method::new(
+            MethodSpec::new("decode64-nopad", "Decode unpadded base64")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Base64 text without padding"))
+                .param(payload_encoding_param("Encoding of the decoded bytes"))
+                .errors(&[Code::DecodeError, Code::ParseError]),
+            |_, param| Crypto::decode_base64_nopad(param.input, param.encoding),
+        ),
+        Method::new(
+            MethodSpec::new("encode64-nopad", "Encode as unpadded base64")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Payload"))
+                .param(payload_encoding_param("Encoding of the input"))
+                .errors(&[Code::DecodeError]),
+            |_, param| Crypto::encode_base64_nopad(param.input, param.encoding),
+        ),
+        Method::blocking(
+            MethodSpec::new("decode52", "Decode base52")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Base52 text"))
+                .param(payload_encoding_param("Encoding of the decoded bytes"))
+                .errors(&[Code::DecodeError, Code::ParseError]),
+            |_, param| Crypto::decode_base52(param.input, param.encoding),
+        ),
+        Method::blocking(
+            MethodSpec::new("encode52", "Encode as base52")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Payload"))
+                .param(payload_encoding_param("Encoding of the input"))
+                .errors(&[Code::DecodeError]),
+            |_, param| Crypto::encode_base52(param.input, param.encoding),
+        ),
+        Method::blocking(
+            MethodSpec::new("encrypt", "Encrypt with a passphrase")
+                .group(MethodGroup::Encryption)
+                .param(input_param("Plaintext"))
+                .param(passphrase_param("Passphrase").required())
+                .param(encrypt_algorithm_param())
+                .param(
+                    ParamSpec::string("kdf", "Key derivation function")
+                        .one_of(password_kdf_names()),
+                )
+                .params(kdf_option_params())
+                .param(payload_encoding_param("Encoding of the plaintext"))
+                .errors(&[Code::DecodeError, Code::EncryptError]),
+            |crypto, param| crypto.encrypt_with_algorithm(param),
+        ),
+        Method::blocking(
+            MethodSpec::new(
+                "decrypt",
+                "Decrypt the output of encrypt or of its legacy aes-256-cbc format",
+            )
+            .group(MethodGroup::Encryption)
+            .param(input_param("Ciphertext"))
+            .param(passphrase_param("Passphrase").required())
+            .param(decrypt_algorithm_param())
+            .param(payload_encoding_param("Encoding of the plaintext"))
+            .errors(&[Code::DecryptError]),
+            |crypto, param| crypto.decrypt_with_algorithm(param),
+        ),
+        Method::blocking(
+            MethodSpec::new("scrypt-encrypt", "Encrypt with a passphrase-derived key")
+                .group(MethodGroup::Encryption)
+                .param(input_param("Plaintext"))
+                .param(passphrase_param("Passphrase").required())
+                .param(kdf_param())
+                .params(kdf_option_params())
+                .param(cipher_param())
+                .param(payload_encoding_param("Encoding of the plaintext"))
+                .errors(&[Code::DecodeError, Code::EncryptError]),
+            |crypto, param| Crypto::scrypt_encrypt_param(param.with_kdf_defaults(&crypto.config)),
+        ),
+        Method::blocking(
+            MethodSpec::new("scrypt-decrypt", "Decrypt the output of scrypt-encrypt")
+                .group(MethodGroup::Encryption)
+                .param(input_param("Ciphertext"))
+                .param(passphrase_param("Passphrase").required())
+                .param(payload_encoding_param("Encoding of the plaintext"))
+                .errors(&[Code::DecryptError]),
+            |crypto, param| crypto.scrypt_decrypt(param.input, param.passphrase, param.encoding),
+        ),
+        Method::new(
+            MethodSpec::new("rot-encode", "Rotate letters by a shift")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Text"))
+                .param(shift_param()),
+            |_, param| Crypto::rot_n_encode(param.input, param.shift.unwrap_or(DEFAULT_SHIFT)),
+        ),
+        Method::new(
+            MethodSpec::new("rot-decode", "Undo rot-encode")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Text"))
+                .param(shift_param()),
+            |_, param| Crypto::rot_n_decode(param.input, param.shift.unwrap_or(DEFAULT_SHIFT)),
+        ),
+        Method::new(
+            MethodSpec::new("rot8-encode", "Rotate letters by 8")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Text")),
+            |_, param| Crypto::rot_n_encode(param.input, 8),
+        ),
+        Method::new(
+            MethodSpec::new("rot8-decode", "Undo rot8-encode")
+                .group(MethodGroup::Encoding)
+                .param(input_param("Text")),
+            |_, param| Crypto::rot_n_decode(param.input, 8),
+        ),
+        Method::new(
+            MethodSpec::new("random-bytes", "Random bytes")
+                .group(MethodGroup::Random)
+                .param(
+                    ParamSpec::integer("length", "Number of bytes")
+                        .default(ParamDefault::Integer(32)),
+                )
+                .param(binary_encoding_param("Output encoding")),
+            |_, param| Crypto::random_bytes(param.length.unwrap_or(32), param.binary_encoding),
+        ),
+        Method::new(
+            MethodSpec::new("random-token", "URL-safe random token")
+                .group(MethodGroup::Random)
+                .param(
+                    ParamSpec::integer("length", "Number of random bytes")
+                        .default(ParamDefault::Integer(32)),
+                ),
+            |_, param| Crypto::random_token(param.length.unwrap_or(32)),
+        ),
+        Method::new(
+            MethodSpec::new("uuid", "Random version 4 UUID").group(MethodGroup::Random),
+            |_, _| Crypto::uuid(),
+        ),
+        Method::new(
+            MethodSpec::new("password", "Random password with its entropy")
+                .group(MethodGroup::Random)
+                .param(
+                    ParamSpec::integer("length", "Number of characters")
+                        .default(ParamDefault::Integer(20)),
+                )
+                .param(ParamSpec::string(
+                    "classes",
+                    "Comma-separated lower, upper, digits, symbols and no-ambiguous",
+                )),
+            |_, param| Crypto::password(param.length.unwrap_or(20), param.classes),
+        ),
+        Method::blocking(
+            MethodSpec::new("passphrase", "Random passphrase with its entropy")
+                .group(MethodGroup::Random)
+                .param(ParamSpec::string("path", "Wordlist file in the base directory").required())
+                .param(
+                    ParamSpec::integer("words", "Number of words")
+                        .default(ParamDefault::Integer(6)),
+                )
+                .param(
+                    ParamSpec::string("separator", "Word separator")
+                        .default(ParamDefault::String("-")),
+                ),
+            |crypto, param| {
+                crypto.passphrase(param.words.unwrap_or(6), param.path, param.separator)
+            },
+        ),
+        Method::blocking(
+            MethodSpec::new("key-generate", "Generate a private key")
+                .group(MethodGroup::Keys)
+                .param(
+                    ParamSpec::string("algorithm", "rsa-<bits>, ec-p256, ec-p384 or ed25519")
+                        .default(ParamDefault::String("rsa-2048")),
+                )
+                .param(key_format_param())
+                .param(passphrase_param("Passphrase protecting the key"))
+                .errors(&[Code::KeyError]),
+            |_, param| Crypto::key_generate(param.algorithm, param.format, param.passphrase),
+        ),
+        Method::blocking(
+            MethodSpec::new("key-convert", "Convert a key to another format")
+                .group(MethodGroup::Keys)
+                .param(input_param("PEM, JWK or base64 DER key"))
+                .param(passphrase_param("Passphrase of the input key"))
+                .param(key_format_param())
+                .param(ParamSpec::boolean("public", "Export the public key"))
+                .param(ParamSpec::string(
+                    "new_passphrase",
+                    "Passphrase protecting the private key output",
+                ))
+                .errors(&[Code::KeyError]),
+            |_, param| {
+                Crypto::key_convert(
+                    param.input,
+                    param.passphrase,
+                    param.format,
+                    param.public,
+                    param.new_passphrase,
+                )
+            },
+        ),
+        Method::blocking(
+            MethodSpec::new("key-fingerprint", "SHA-256 fingerprint of a public key")
+                .group(MethodGroup::Keys)
+                .param(input_param("PEM, JWK or base64 DER key"))
+                .param(passphrase_param("Passphrase of an encrypted key"))
+                .errors(&[Code::KeyError]),
+            |_, param| Crypto::key_fingerprint(param.input, param.passphrase, false),
+        ),
+        Method::blocking(
+            MethodSpec::new("jwk-thumbprint", "RFC 7638 thumbprint of a key")
+                .group(MethodGroup::Keys)
+                .param(input_param("PEM, JWK or base64 DER key"))
+                .param(passphrase_param("Passphrase of an encrypted key"))
+                .errors(&[Code::KeyError]),
+            |_, param| Crypto::key_fingerprint(param.input, param.passphrase, true),
+        ),
+        Method::blocking(
+            jwt_params(
+                MethodSpec::new("jwt-sign", "Sign or encrypt JWT claims")
+                    .param(input_param("Claims JSON")),
+            )
+            .group(MethodGroup::Jwt)
+            .errors(&[Code::ParseError, Code::EncryptError]),
+            |_, param| Crypto::jwt(param, true),
+        ),
+        Method::blocking(
+            jwt_params(
+                MethodSpec::new(
+                    "jwt-verify",
+                    "Verify or decrypt a JWT, returning its claims",
+                )
+                .param(input_param("Token")),
+            )
+            .group(MethodGroup::Jwt)
+            .param(
+                ParamSpec::integer("leeway", "Clock skew tolerance in seconds")
+                    .default(ParamDefault::Integer(jose::DEFAULT_LEEWAY)),
+            )
+            .param(ParamSpec::string("audience", "Required aud claim"))
+            .param(ParamSpec::string("issuer", "Required iss claim"))
+            .param(ParamSpec::integer(
+                "time",
+                "Validation time in Unix seconds, now by default",
+            ))
+            .errors(&[Code::VerifyError]),
+            |_, param| Crypto::jwt(param, false),
+        ),
+        Method::new(
+            key_wrap_spec("wrap-key", "Wrap a key with AES-KW", "Key to wrap")
+                .group(MethodGroup::Encryption)
+                .errors(&[Code::EncryptError]),
+            |_, param| {
+                Crypto::key_wrap(
+                    param.input,
+                    param.key,
+                    param.binary_encoding,
+                    param.padding,
+                    false,
+                )
+            },
+        ),
+        Method::new(
+            key_wrap_spec("unwrap-key", "Unwrap an AES-KW wrapped key", "Wrapped key")
+                .group(MethodGroup::Encryption)
+                .errors(&[Code::DecryptError]),
+            |_, param| {
+                Crypto::key_wrap(
+                    param.input,
+                    param.key,
+                    param.binary_encoding,
+                    param.padding,
+                    true,
+                )
+            },
+        ),
+        Method::new(
+            deterministic_spec(
+                "deterministic-encrypt",
+                "Deterministic AES-SIV encryption",
+                "Plaintext",
+            )
+            .group(MethodGroup::Encryption)
+            .errors(&[Code::EncryptError]),
+            |_, param| {
+                Crypto::deterministic_crypt(
+                    param.input,
+                    param.key,
+                    param.aad,
+                    param.encoding,
+                    param.binary_encoding,
+                    false,
+                )
+            },
+        ),
+        Method::new(
+            deterministic_spec(
+                "deterministic-decrypt",
+                "Decrypt deterministic AES-SIV",
+                "Ciphertext",
+            )
+            .group(MethodGroup::Encryption)
+            .errors(&[Code::DecryptError]),
+            |_, param| {
+                Crypto::deterministic_crypt(
+                    param.input,
+                    param.key,
+                    param.aad,
+                    param.encoding,
+                    param.binary_encoding,
+                    true,
+                )
+            },
+        ),
+        Method::new(
+            fpe_spec(
+                "fpe-encrypt",
+                "Format-preserving encryption",
+                "Plaintext over the alphabet",
+            )
+            .group(MethodGroup::Encryption)
+            .errors(&[Code::EncryptError]),
+            |_, param| {
+                Crypto::fpe_crypt(
+                    param.input,
+                    param.key,
+                    param.tweak,
+                    param.alphabet,
+                    param.algorithm,
+                    param.binary_encoding,
+                    false,
+                )
+            },
+        ),
+        Method::new(
+            fpe_spec(
+                "fpe-decrypt",
+                "Format-preserving decryption",
+                "Ciphertext over the alphabet",
+            )
+            .group(MethodGroup::Encryption)
+            .errors(&[Code::DecryptError]),
+            |_, param| {
+                Crypto::fpe_crypt(
+                    param.input,
+                    param.key,
+                    param.tweak,
+                    param.alphabet,
+                    param.algorithm,
+                    param.binary_encoding,
+                    true,
+                )
+            },
+        ),
+        Method::blocking(
+            envelope_params(
+                MethodSpec::new("envelope-encrypt", "Seal under a fresh wrapped data key")
+                    .param(input_param("Plaintext"))
+                    .param(payload_encoding_param("Encoding of the plaintext")),
+            )
+            .group(MethodGroup::Encryption)
+            .param(cipher_param())
+            .errors(&[Code::DecodeError, Code::EncryptError, Code::KeyError]),
+            |crypto, param| crypto.envelope(param, false),
+        ),
+        Method::blocking(
+            envelope_params(
+                MethodSpec::new("envelope-decrypt", "Open an envelope")
+                    .param(input_param("Envelope JSON"))
+                    .param(payload_encoding_param("Encoding of the plaintext")),
+            )
+            .group(MethodGroup::Encryption)
+            .errors(&[Code::DecodeError, Code::DecryptError, Code::KeyError]),
+            |crypto, param| crypto.envelope(param, true),
+        ),
+        Method::new(
+            file_job_params(MethodSpec::new(
+                "encrypt-file",
+                "Start encrypting a file, returning the job id",
+            ))
+            .group(MethodGroup::Files)
+            .param(kdf_param())
+            .params(kdf_option_params())
+            .param(cipher_param())
+            .errors(&[Code::EncryptError, Code::QueueFull]),
+            |crypto, param| crypto.encrypt_file_job(param.with_kdf_defaults(&crypto.config)),
+        ),
+        Method::new(
+            file_job_params(MethodSpec::new(
+                "decrypt-file",
+                "Start decrypting a file, returning the job id",
+            ))
+            .group(MethodGroup::Files)
+            .errors(&[Code::DecryptError, Code::QueueFull]),
+            |crypto, param| {
+                crypto.decrypt_file_job(param.path, param.output, param.overwrite, param.passphrase)
+            },
+        ),
+        Method::new(
+            job_param(MethodSpec::new(
+                "job-status",
+                "Progress and state of a file job; a finished job is forgotten once reported",
+            ))
+            .group(MethodGroup::Files),
+            |crypto, param| crypto.job_status(param.job, false),
+        ),
+        Method::new(
+            job_param(MethodSpec::new("job-cancel", "Cancel a running file job"))
+                .group(MethodGroup::Files),
+            |crypto, param| crypto.job_status(param.job, true),
+        ),
+        Method::blocking(
+            MethodSpec::new("otp-secret", "Random OTP secret")
+                .group(MethodGroup::Otp)
+                .param(passphrase_param("Passphrase encrypting the secret"))
+                .errors(&[Code::EncryptError]),
+            |_, param| Crypto::otp_secret(param.passphrase),
+        ),
+        Method::new(
+            MethodSpec::new("otpauth-parse", "Parse an otpauth:// URI")
+                .group(MethodGroup::Otp)
+                .param(input_param("otpauth:// URI"))
+                .errors(&[Code::ParseError]),
+            |_, param| Crypto::otpauth_parse(param.input),
+        ),
+        Method::blocking(
+            otp_params(MethodSpec::new("totp", "TOTP code"))
+                .group(MethodGroup::Otp)
+                .param(ParamSpec::integer("time", "Unix seconds, now by default")),
+            |crypto, param| crypto.otp("totp", param),
+        ),
+        Method::blocking(
+            otp_params(
+                MethodSpec::new("totp-verify", "Verify a TOTP code, returning the drift")
+                    .param(input_param("Code")),
+            )
+            .group(MethodGroup::Otp)
+            .param(ParamSpec::integer("time", "Unix seconds, now by default"))
+            .param(window_param())
+            .errors(&[Code::VerifyError]),
+            |crypto, param| crypto.otp("totp-verify", param),
+        ),
+        Method::blocking(
+            otp_params(MethodSpec::new("hotp", "HOTP code"))
+                .group(MethodGroup::Otp)
+                .param(ParamSpec::integer("counter", "Counter").required()),
+            |crypto, param| crypto.otp("hotp", param),
+        ),
+        Method::blocking(
+            otp_params(
+                MethodSpec::new(
+                    "hotp-verify",
+                    "Verify an HOTP code, returning the next counter",
+                )
+                .param(input_param("Code")),
+            )
+            .group(MethodGroup::Otp)
+            .param(ParamSpec::integer("counter", "Counter").required())
+            .param(window_param())
+            .errors(&[Code::VerifyError]),
+            |crypto, param| crypto.otp("hotp-verify", param),
+        ),
+        Method::blocking(
+            otp_params(MethodSpec::new(
+                "otpauth-uri",
+                "otpauth:// provisioning URI",
+            ))
+            .group(MethodGroup::Otp)
+            .param(ParamSpec::string("account", "Account name").required())
+            .param(ParamSpec::string("issuer", "Issuer"))
+            .param(ParamSpec::integer(
+                "counter",
+                "HOTP counter, TOTP when omitted",
+            )),
+            |crypto, param| crypto.otp("otpauth-uri", param),
+        ),
+        Method::with_handler(
+            MethodSpec::new(
+                "batch",
+                "Run several operations, returning their results in order",
+            )
+            .group(MethodGroup::Meta)
+            .param(
+                ParamSpec::array("operations", "Operations to run")
+                    .items(json!({
+                        "type": "object",
+                        "properties": {
+                            "method": { "type": "string" },
+                            "args": { "type": "object" },
+                        },
+                        "required": ["method"],
+                    }))
+                    .required(),
+            )
+            .param(ParamSpec::boolean(
+                "parallel",
+                "Run the operations concurrently on the blocking thread pool",
+            )),
+            Handler::Batch,
+        ),
+        Method::new(
+            MethodSpec::new("list-methods", "Descriptions of every method")
+                .group(MethodGroup::Meta),
+            |crypto, _| crypto.list_methods(),
+        ),
+        Method::new(
+            MethodSpec::new("describe", "Description of one method")
+                .group(MethodGroup::Meta)
+                .param(ParamSpec::string("method", "Method name").required())
+                .errors(&[Code::UnknownMethodError]),
+            |crypto, param| crypto.describe(param.method),
+        ),
+        Method::new(
+            MethodSpec::new("version", "Crate version and supported algorithms")
+                .group(MethodGroup::Meta),
+            |_, _| Crypto::version(),
+        ),
+    ];
+
+    // sha256, sha3-512, blake2b512, ... and their hmac-* variants
+    for algorithm in HashAlgorithm::ALL {
+        methods.push(Method::blocking(
+            MethodSpec::new(algorithm.name(), "Digest of the input or of a file")
+                .group(MethodGroup::Hash)
+                .param(ParamSpec::string("input", "Data to hash"))
+                .param(ParamSpec::string(
+                    "path",
+                    "File to hash instead of the input, relative to the base directory",
+                ))
+                .param(binary_encoding_param("Output encoding"))
+                .errors(&[Code::HashError]),
+            move |crypto, param| {
+                crypto.hash(algorithm, param.input, param.path, param.binary_encoding)
+            },
+        ));
+    }
+    for algorithm in HashAlgorithm::ALL {
+        methods.push(Method::blocking(
+            MethodSpec::new(
+                format!("hmac-{}", algorithm.name()),
+                "HMAC of the input or of a file",
+            )
+            .group(MethodGroup::Hash)
+            .param(ParamSpec::string("input", "Data to authenticate"))
+            .param(ParamSpec::string("key", "HMAC key").required())
+            .param(ParamSpec::string(
+                "path",
+                "File to authenticate instead of the input, relative to the base directory",
+            ))
+            .param(binary_encoding_param("Output encoding"))
+            .errors(&[Code::HashError]),
+            move |crypto, param| {
+                crypto.hmac(
+                    algorithm,
+                    param.input,
+                    param.key,
+                    param.path,
+                    param.binary_encoding,
+                )
+            },
+        ));
+    }
+    methods
+}
+
+fn key_format_param() -> ParamSpec {
+    ParamSpec::string("format", "Key format")
+        .default(ParamDefault::String(KeyFormat::default().name()))
+        .one_of(["pem", "der", "pkcs1", "pkcs1-der", "jwk"])
+}
+
+fn window_param() -> ParamSpec {
+    ParamSpec::integer("window", "Accepted steps on each side").default(ParamDefault::Integer(1))
+}
+
+fn key_wrap_spec(name: &'static str, description: &'static str, input: &'static str) -> MethodSpec {
+    MethodSpec::new(name, description)
+        .param(input_param(input))
+        .param(ParamSpec::string("key", "Key-encryption key").required())
+        .param(binary_encoding_param("Encoding of the keys and the result"))
+        .param(ParamSpec::boolean("padding", "Use RFC 5649 AES-KWP"))
+        .errors(&[Code::DecodeError])
+}
+
+fn deterministic_spec(
+    name: &'static str,
+    description: &'static str,
+    input: &'static str,
+) -> MethodSpec {
+    MethodSpec::new(name, description)
+        .param(input_param(input))
+        .param(ParamSpec::string("key", "AES-SIV key").required())
+        .param(ParamSpec::string("aad", "Associated data"))
+        .param(payload_encoding_param(
+            "Encoding of the plaintext and the associated data",
+        ))
+        .param(binary_encoding_param(
+            "Encoding of the key and the ciphertext",
+        ))
+        .errors(&[Code::DecodeError])
+}
+
+fn fpe_spec(name: &'static str, description: &'static str, input: &'static str) -> MethodSpec {
+    MethodSpec::new(name, description)
+        .param(input_param(input))
+        .param(ParamSpec::string("key", "AES key").required())
+        .param(ParamSpec::string("tweak", "Tweak"))
+        .param(
+            ParamSpec::string(
+                "alphabet",
+                "digits, alphanumeric, base52 or literal characters",
+            )
+            .default(ParamDefault::String("digits")),
+        )
+        .param(
+            ParamSpec::string("algorithm", "FPE mode")
+                .default(ParamDefault::String(FpeMode::Ff1.name()))
+                .one_of(FpeMode::ALL.iter().map(FpeMode::name)),
+        )
+        .param(binary_encoding_param("Encoding of the key and the tweak"))
+        .errors(&[Code::DecodeError])
+}
+
+#[async_trait]
+impl SharedObject for Crypto {
+    /// ipc-broker does not tell workers who called, so only the `token` argument
+    /// identifies the caller
+    async fn call(&self, method: &str, args: &Value) -> Value {
+        self.call_as(&Caller::from_args(args), method, args).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::methods::ParamType;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    /// "secret" under "pw" in the legacy aes-256-cbc format
+    const LEGACY_CIPHERTEXT: &str = "4SLBfRfdflW+tK+mC93C+A==";
+
+    fn results(response: &Value) -> Vec<Value> {
+        assert_eq!(response["code"], 0, "{response}");
+        serde_json::from_str(response["result"].as_str().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let config = DaemonConfig {
+            access: serde_json::from_value(json!({ "groups": { "hash": ["web"] } })).unwrap(),
+            ..DaemonConfig::default()
+        };
+        let crypto = Crypto::with_config(activity_tx, config);
+        let web = Caller {
+            identity: Some("web".into()),
+            token: None,
+        };
+
+        // One method of each handler kind
+        let handler = |name| &find_method(name).unwrap().handler;
+        assert!(matches!(handler("encode64"), Handler::Inline(_)));
+        assert!(matches!(handler("sha256"), Handler::Blocking(_)));
+        assert!(matches!(handler("batch"), Handler::Batch));
+        let response = crypto.call("encode64", &json!({ "input": "hello" })).await;
+        assert_eq!(response, json!({ "code": 0, "result": "aGVsbG8=" }));
+        let response = crypto
+            .call_as(&web, "sha256", &json!({ "input": "abc" }))
+            .await;
+        assert_eq!(
+            response["result"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let operations = json!([{ "method": "encode64", "args": { "input": "hello" } }]);
+        let response = crypto
+            .call("batch", &json!({ "operations": operations }))
+            .await;
+        assert_eq!(results(&response)[0]["result"], "aGVsbG8=");
+
+        // Groups the caller may not call
+        for caller in [
+            Caller::default(),
+            Caller {
+                identity: Some("other".into()),
+                token: None,
+            },
+        ] {
+            let response = crypto
+                .call_as(&caller, "sha256", &json!({ "input": "abc" }))
+                .await;
+            assert_eq!(response["code"], Code::PermissionDenied as i32);
+            assert_eq!(response["error"], "Permission denied: sha256");
+        }
+
+        let response = crypto.call("nope", &json!({})).await;
+        assert_eq!(response["code"], Code::UnknownMethodError as i32);
+        assert!(response["error"].is_string());
+
+        // Arguments that are not an object or of the wrong type
+        for args in [
+            json!("hello"),
+            json!([1, 2]),
+            json!({ "input": 5 }),
+            json!({ "input": "hello", "encoding": true }),
+        ] {
+            let response = crypto.call("encode64", &args).await;
+            assert_eq!(
+                response["code"],
+                Code::InvalidArgumentsError as i32,
+                "{args}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_argument_validation() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let crypto = Crypto::new(activity_tx);
+
+        // Every declared parameter is read with its declared type
+        for method in METHODS.iter() {
+            let name = method.spec.name.as_ref();
+            for param in &method.spec.params {
+                let wrong = match param.kind {
+                    ParamType::String => json!(5),
+                    ParamType::Integer => json!("x"),
+                    ParamType::Boolean => json!("x"),
+                    ParamType::Array => json!(5),
+                };
+                let args = json!({ param.name: wrong });
+                let response = crypto.call(name, &args).await;
+                assert_eq!(
+                    response["code"],
+                    Code::InvalidArgumentsError as i32,
+                    "{name} {args} {response}"
+                );
+            }
+        }
+
+        // Checks of single methods
+        for (method, args, code) in [
+            (
+                "random-bytes",
+                json!({ "length": -1 }),
+                Code::InvalidArgumentsError,
+            ),
+            (
+                "rot-encode",
+                json!({ "input": "a", "shift": 256 }),
+                Code::InvalidArgumentsError,
+            ),
+            ("describe", json!({}), Code::InvalidArgumentsError),
+            ("job-status", json!({}), Code::InvalidArgumentsError),
+            (
+                "job-status",
+                json!({ "job": 1 }),
+                Code::InvalidArgumentsError,
+            ),
+            ("batch", json!({}), Code::InvalidArgumentsError),
+            ("encrypt", json!({ "input": "secret" }), Code::EncryptError),
+            (
+                "encrypt",
+                json!({ "input": "secret", "passphrase": "pw", "algorithm": "nope" }),
+                Code::InvalidArgumentsError,
+            ),
+            (
+                "scrypt-encrypt",
+                json!({ "input": "secret", "passphrase": "pw", "kdf": "nope" }),
+                Code::InvalidArgumentsError,
+            ),
+            ("decode64", json!({ "input": "%%" }), Code::DecodeError),
+        ] {
+            let response = crypto.call(method, &args).await;
+            assert_eq!(response["code"], code as i32, "{method} {args} {response}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let crypto = Crypto::new(activity_tx);
+        let operations = json!([
+            { "method": "encode64", "args": { "input": "hello" } },
+            { "method": "sha256", "args": { "input": "abc" } },
+            { "method": "nope" },
+            { "method": "random-bytes", "args": { "length": "x" } },
+            { "method": "batch", "args": { "operations": [] } },
+            { "method": "uuid" },
+        ]);
+
+        for parallel in [false, true] {
+            let response = crypto
+                .call(
+                    "batch",
+                    &json!({ "operations": operations, "parallel": parallel }),
+                )
+                .await;
+            let results = results(&response);
+            assert_eq!(results.len(), 6);
+            assert_eq!(results[0]["result"], "aGVsbG8=");
+            assert_eq!(
+                results[1]["result"],
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+            );
+            assert_eq!(results[2]["code"], Code::UnknownMethodError as i32);
+            assert_eq!(results[3]["code"], Code::InvalidArgumentsError as i32);
+            assert_eq!(results[4]["code"], Code::InvalidArgumentsError as i32);
+            assert_eq!(results[5]["code"], 0);
+        }
+
+        let too_many = vec![json!({ "method": "uuid" }); MAX_BATCH_SIZE + 1];
+        let response = crypto
+            .call("batch", &json!({ "operations": too_many }))
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+        let response = crypto.call("batch", &json!({})).await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+    }
+
+    #[tokio::test]
+    async fn test_failed_parallel_operation_keeps_its_slot() {
+        let tasks: Vec<BoxFuture<'static, Value>> = vec![
+            Box::pin(async { json!("first") }),
+            Box::pin(async { panic!("operation panicked") }),
+            Box::pin(async { json!("third") }),
+        ];
+        let results = run_limited(tasks, 2).await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], "first");
+        assert_eq!(results[1]["code"], Code::InternalError as i32);
+        assert!(results[1]["error"].is_string(), "{}", results[1]);
+        assert_eq!(results[2], "third");
+    }
+
+    #[tokio::test]
+    async fn test_meta_methods() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let crypto = Crypto::new(activity_tx);
+
+        let listed = results(&crypto.call("list-methods", &json!({})).await);
+        let names: Vec<&str> = listed
+            .iter()
+            .map(|method| method["name"].as_str().unwrap())
+            .collect();
+        let registered: Vec<&str> = METHODS
+            .iter()
+            .map(|method| method.spec.name.as_ref())
+            .collect();
+        assert_eq!(names, registered);
+        let unique: std::collections::HashSet<_> = names.iter().collect();
+        assert_eq!(unique.len(), names.len(), "duplicate method names");
+
+        for described in &listed {
+            let name = described["name"].as_str().unwrap();
+            let response = crypto.call("describe", &json!({ "method": name })).await;
+            let response: Value =
+                serde_json::from_str(response["result"].as_str().unwrap()).unwrap();
+            assert_eq!(&response, described);
+            assert_eq!(described["params"]["properties"]["token"]["type"], "string");
+            // Every listed method is dispatched; missing arguments are not an unknown method
+            let response = crypto.call(name, &json!({})).await;
+            assert_ne!(response["code"], Code::UnknownMethodError as i32, "{name}");
+        }
+        let response = crypto.call("describe", &json!({ "method": "nope" })).await;
+        assert_eq!(response["code"], Code::UnknownMethodError as i32);
+        let response = crypto.call("describe", &json!({})).await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+
+        let response = crypto.call("version", &json!({})).await;
+        let version: Value = serde_json::from_str(response["result"].as_str().unwrap()).unwrap();
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert!(
+            version["algorithms"]["hash"]
+                .as_array()
+                .unwrap()
+                .contains(&json!("sha256"))
+        );
+        assert!(
+            !version["algorithms"]["kdf"]
+                .as_array()
+                .unwrap()
+                .contains(&json!("hkdf-sha256"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_binary_payloads() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let master_key = "11".repeat(32);
+        let mut config = DaemonConfig::default();
+        config
+            .envelope
+            .keys
+            .insert("main".into(), EnvelopeKey::Mock { key: master_key });
+        let crypto = Crypto::with_config(activity_tx, config);
+        // Not valid UTF-8
+        let binary = general_purpose::STANDARD.encode([0xff, 0x00, 0x80, 0xfe]);
+
+        let args = json!({ "input": binary, "encoding": "base64" });
+        assert_eq!(crypto.call("decode64", &args).await["result"], binary);
+        let response = crypto.call("decode64", &json!({ "input": binary })).await;
+        assert_eq!(response["code"], Code::ParseError as i32);
+
+        let key = "00".repeat(64);
+        for (encrypt, decrypt, extra) in [
+            ("encrypt", "decrypt", json!({ "passphrase": "pw" })),
+            (
+                "encrypt",
+                "decrypt",
+                json!({ "passphrase": "pw", "algorithm": "chacha20-poly1305" }),
+            ),
+            (
+                "scrypt-encrypt",
+                "scrypt-decrypt",
+                json!({ "passphrase": "pw" }),
+            ),
+            (
+                "deterministic-encrypt",
+                "deterministic-decrypt",
+                json!({ "key": key, "aad": binary }),
+            ),
+            ("envelope-encrypt", "envelope-decrypt", json!({})),
+        ] {
+            let mut args = extra;
+            args["input"] = binary.clone().into();
+            args["encoding"] = "base64".into();
+            let encrypted = crypto.call(encrypt, &args).await;
+            assert_eq!(encrypted["code"], 0, "{encrypt} {encrypted}");
+            args["input"] = encrypted["result"].clone();
+            let decrypted = crypto.call(decrypt, &args).await;
+            assert_eq!(decrypted["result"], binary, "{decrypt} {decrypted}");
+
+            args.as_object_mut().unwrap().remove("encoding");
+            let response = crypto.call(decrypt, &args).await;
+            assert_ne!(response["code"], 0, "{decrypt} {response}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_envelope_master_keys() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let mut config = DaemonConfig::default();
+        let keys = &mut config.envelope.keys;
+        keys.insert(
+            "a".into(),
+            EnvelopeKey::Mock {
+                key: "11".repeat(32),
+            },
+        );
+        keys.insert(
+            "b".into(),
+            EnvelopeKey::Mock {
+                key: "22".repeat(32),
+            },
+        );
+        keys.insert(
+            "gone".into(),
+            EnvelopeKey::File {
+                path: "/nonexistent/master.key".into(),
+            },
+        );
+        let crypto = Crypto::with_config(activity_tx, config);
+
+        let response = crypto
+            .call("envelope-encrypt", &json!({ "input": "secret" }))
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+        let response = crypto
+            .call(
+                "envelope-encrypt",
+                &json!({ "input": "secret", "master_key": "c" }),
+            )
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+        let response = crypto
+            .call(
+                "envelope-encrypt",
+                &json!({ "input": "secret", "master_key": "gone" }),
+            )
+            .await;
+        assert_eq!(response["code"], Code::KeyError as i32);
+        assert!(
+            !response["error"].as_str().unwrap().contains("nonexistent"),
+            "{response}"
+        );
+
+        // Decryption finds the key the envelope was sealed with unless one is named
+        let sealed = crypto
+            .call(
+                "envelope-encrypt",
+                &json!({ "input": "secret", "master_key": "b" }),
+            )
+            .await;
+        assert_eq!(sealed["code"], 0, "{sealed}");
+        let args = json!({ "input": sealed["result"] });
+        assert_eq!(
+            crypto.call("envelope-decrypt", &args).await["result"],
+            "secret"
+        );
+        let mut args = args;
+        args["master_key"] = "a".into();
+        let response = crypto.call("envelope-decrypt", &args).await;
+        assert_eq!(response["code"], Code::DecryptError as i32);
+    }
+
+    #[tokio::test]
+    async fn test_algorithm_and_cost_params() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let crypto = Crypto::new(activity_tx);
+
+        let response = crypto
+            .call(
+                "rot-encode",
+                &json!({ "input": "Hello, World", "shift": 3 }),
+            )
+            .await;
+        assert_eq!(response["result"], "Khoor, Zruog");
+        let response = crypto
+            .call(
+                "rot-decode",
+                &json!({ "input": "Khoor, Zruog", "shift": 3 }),
+            )
+            .await;
+        assert_eq!(response["result"], "Hello, World");
+        let response = crypto
+            .call("rot-encode", &json!({ "input": "x", "shift": 300 }))
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+
+        let args = json!({
+            "input": "secret",
+            "passphrase": "pw",
+            "algorithm": "chacha20-poly1305",
+            "kdf": "argon2id",
+            "iterations": 1,
+            "memory": 1024,
+            "parallelism": 1,
+        });
+        let encrypted = crypto.call("encrypt", &args).await;
+        assert_eq!(encrypted["code"], 0, "{encrypted}");
+        let mut decrypt = json!({ "input": encrypted["result"], "passphrase": "pw" });
+        for (algorithm, code) in [
+            ("chacha20-poly1305", 0),
+            ("aes-256-gcm", Code::InvalidArgumentsError as i32),
+            ("rot13", Code::InvalidArgumentsError as i32),
+        ] {
+            decrypt["algorithm"] = algorithm.into();
+            let response = crypto.call("decrypt", &decrypt).await;
+            assert_eq!(response["code"], code, "{algorithm} {response}");
+        }
+        decrypt["algorithm"] = "chacha20-poly1305".into();
+
+        // A legacy ciphertext carries no AEAD header and is only ever decrypted
+        decrypt["input"] = LEGACY_CIPHERTEXT.into();
+        let response = crypto.call("decrypt", &decrypt).await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+        for algorithm in [json!(LEGACY_CIPHER), Value::Null] {
+            decrypt["algorithm"] = algorithm;
+            let response = crypto.call("decrypt", &decrypt).await;
+            assert_eq!(response["result"], "secret", "{response}");
+        }
+        let legacy = json!({ "input": "secret", "passphrase": "pw", "algorithm": LEGACY_CIPHER });
+        let response = crypto.call("encrypt", &legacy).await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+
+        for (key, value) in [
+            ("iterations", json!(u32::MAX)),
+            ("parallelism", json!(u32::MAX)),
+            ("memory", json!(u32::MAX)),
+            ("cost", json!(1024)),
+        ] {
+            let mut args = args.clone();
+            args[key] = value;
+            let response = crypto.call("encrypt", &args).await;
+            assert_eq!(
+                response["code"],
+                Code::InvalidArgumentsError as i32,
+                "{key}"
+            );
+        }
+        let mut args = args.clone();
+        args["kdf"] = "hkdf-sha256".into();
+        for key in ["iterations", "memory", "parallelism"] {
+            args.as_object_mut().unwrap().remove(key);
+        }
+        let response = crypto.call("encrypt", &args).await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+    }
+
+    #[tokio::test]
+    async fn test_config() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let mut config = DaemonConfig {
+            methods: vec![
+                MethodGroup::Encoding,
+                MethodGroup::Encryption,
+                MethodGroup::Meta,
+            ],
+            ..DaemonConfig::default()
+        };
+        config.limits.max_batch_size = 2;
+        config.limits.max_batch_bytes = 200;
+        config.limits.max_input_bytes = 128;
+        config.kdf.algorithm = Some("pbkdf2-sha256".into());
+        config.kdf.iterations = Some(1000);
+        config.limits.max_kdf_iterations = 2000;
+        let crypto = Crypto::with_config(activity_tx, config);
+
+        let response = crypto.call("sha256", &json!({ "input": "abc" })).await;
+        assert_eq!(response["code"], Code::UnknownMethodError as i32);
+        let response = crypto.call("describe", &json!({ "method": "uuid" })).await;
+        assert_eq!(response["code"], Code::UnknownMethodError as i32);
+        let listed = results(&crypto.call("list-methods", &json!({})).await);
+        assert!(listed.iter().any(|method| method["name"] == "encode64"));
+        assert!(listed.iter().all(|method| method["group"] != "hash"));
+
+        let response = crypto
+            .call("encode64", &json!({ "input": "x".repeat(129) }))
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+        let three = vec![json!({ "method": "encode64" }); 3];
+        let response = crypto.call("batch", &json!({ "operations": three })).await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+        // Each input is within the call limit, together they are over the batch limit
+        let large = json!({ "method": "encode64", "args": { "input": "x".repeat(120) } });
+        let response = crypto
+            .call("batch", &json!({ "operations": [large, large] }))
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+        assert!(response["error"].as_str().unwrap().contains("bytes"));
+        let response = crypto
+            .call("batch", &json!({ "operations": [large] }))
+            .await;
+        assert_eq!(response["code"], 0, "{response}");
+
+        // The default KDF applies to every format encrypt writes
+        for (method, args) in [
+            ("scrypt-encrypt", json!({})),
+            ("encrypt", json!({ "algorithm": "aes-256-gcm" })),
+            ("encrypt", json!({})),
+        ] {
+            let mut args = args;
+            args["input"] = "secret".into();
+            args["passphrase"] = "pw".into();
+            let encrypted = crypto.call(method, &args).await;
+            assert_eq!(encrypted["code"], 0, "{method} {args}");
+            args["input"] = encrypted["result"].clone();
+            let decrypt = method.replace("encrypt", "decrypt");
+            let decrypted = crypto.call(&decrypt, &args).await;
+            assert_eq!(decrypted["result"], "secret", "{method} {args}");
+        }
+        let response = crypto
+            .call(
+                "scrypt-encrypt",
+                &json!({ "input": "secret", "passphrase": "pw", "kdf": "scrypt", "iterations": 5 }),
+            )
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+
+        // Callers may pick costs up to the KDF caps, decryption only reads up to the limits
+        for (encrypt, decrypt) in [("scrypt-encrypt", "scrypt-decrypt"), ("encrypt", "decrypt")] {
+            let mut args = json!({
+                "input": "secret",
+                "passphrase": "pw",
+                "kdf": "pbkdf2-sha256",
+                "iterations": 5000,
+            });
+            let encrypted = crypto.call(encrypt, &args).await;
+            assert_eq!(encrypted["code"], 0, "{encrypted}");
+            args["input"] = encrypted["result"].clone();
+            let response = crypto.call(decrypt, &args).await;
+            assert_eq!(response["code"], Code::DecryptError as i32, "{decrypt}");
+            assert!(
+                response["error"].as_str().unwrap().contains("allowed"),
+                "{decrypt} {response}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_policy() {
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let config = DaemonConfig {
+            access: serde_json::from_value(json!({
+                "clients": { "vault": "vault-token" },
+                "groups": { "encryption": ["vault"] },
+                "default": ["*"],
+            }))
+            .unwrap(),
+            ..DaemonConfig::default()
+        };
+        let crypto = Crypto::with_config(activity_tx, config);
+        let args = json!({ "input": "secret", "passphrase": "pw" });
+
+        let response = crypto.call("encrypt", &args).await;
+        assert_eq!(response["code"], Code::PermissionDenied as i32);
+        let mut with_token = args.clone();
+        with_token["token"] = "vault-token".into();
+        assert_eq!(crypto.call("encrypt", &with_token).await["code"], 0);
+        with_token["token"] = "guess".into();
+        let response = crypto.call("encrypt", &with_token).await;
+        assert_eq!(response["code"], Code::PermissionDenied as i32);
+        assert_eq!(crypto.call("uuid", &json!({})).await["code"], 0);
+
+        let identified = Caller {
+            identity: Some("vault".into()),
+            token: None,
+        };
+        assert_eq!(
+            crypto.call_as(&identified, "encrypt", &args).await["code"],
+            0
+        );
+
+        // A batch cannot reach methods its caller may not call
+        let operations = json!([
+            { "method": "encrypt", "args": args },
+            { "method": "encode64", "args": { "input": "hello" } },
+        ]);
+        let response = crypto
+            .call("batch", &json!({ "operations": operations }))
+            .await;
+        let results = results(&response);
+        assert_eq!(results[0]["code"], Code::PermissionDenied as i32);
+        assert_eq!(results[1]["result"], "aGVsbG8=");
+    }
+
+    #[tokio::test]
+    async fn test_file_jobs_are_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("plain.txt"), b"file contents").unwrap();
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let mut config = DaemonConfig::default();
+        config.files.base_dir = Some(dir.path().to_path_buf());
+        config.limits.max_jobs = 1;
+        let crypto = Crypto::with_config(activity_tx, config);
+
+        let running = crypto
+            .jobs
+            .spawn(
+                "encrypt-file",
+                dir.path().join("other"),
+                None,
+                |_, _| async {
+                    tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                    Ok(())
+                },
+            )
+            .unwrap();
+        let args = json!({ "path": "plain.txt", "output": "plain.enc", "passphrase": "pw" });
+        let response = crypto.call("encrypt-file", &args).await;
+        assert_eq!(response["code"], Code::QueueFull as i32);
+        crypto.jobs.cancel(running).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_paths_stay_in_base_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("plain.txt"), b"file contents").unwrap();
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let disabled = Crypto::new(activity_tx.clone());
+        let mut config = DaemonConfig::default();
+        config.files.base_dir = Some(dir.path().to_path_buf());
+        let crypto = Crypto::with_config(activity_tx, config);
+
+        let args = |path: &str, output: &str| {
+            json!({
+                "path": path,
+                "output": output,
+                "passphrase": "pw",
+                "kdf": "pbkdf2-sha256",
+                "iterations": 1000,
+            })
+        };
+        let response = disabled
+            .call("encrypt-file", &args("plain.txt", "plain.enc"))
+            .await;
+        assert_eq!(response["code"], Code::PermissionDenied as i32);
+        let absolute = dir.path().join("plain.txt");
+        for (path, output) in [
+            (absolute.to_str().unwrap(), "plain.enc"),
+            ("../plain.txt", "plain.enc"),
+            ("plain.txt", "../plain.enc"),
+        ] {
+            let response = crypto.call("encrypt-file", &args(path, output)).await;
+            assert_eq!(
+                response["code"],
+                Code::PermissionDenied as i32,
+                "{path} {output}"
+            );
+        }
+        let response = crypto
+            .call("encrypt-file", &args("plain.txt", "plain.txt"))
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+
+        let wait = |id: &Value| {
+            let crypto = crypto.clone();
+            let job = json!({ "job": id.as_str().unwrap().parse::<u64>().unwrap() });
+            async move {
+                loop {
+                    let response = crypto.call("job-status", &job).await;
+                    let status: Value =
+                        serde_json::from_str(response["result"].as_str().unwrap()).unwrap();
+                    if status["state"] != "running" {
+                        return status["state"].clone();
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                }
+            }
+        };
+        let response = crypto
+            .call("encrypt-file", &args("plain.txt", "plain.enc"))
+            .await;
+        assert_eq!(wait(&response["result"]).await, "completed");
+        let response = crypto
+            .call("decrypt-file", &args("plain.enc", "plain.txt"))
+            .await;
+        assert_eq!(response["code"], Code::InvalidArgumentsError as i32);
+        let mut overwrite = args("plain.enc", "plain.txt");
+        overwrite["overwrite"] = true.into();
+        let response = crypto.call("decrypt-file", &overwrite).await;
+        assert_eq!(wait(&response["result"]).await, "completed");
+        assert_eq!(
+            std::fs::read(dir.path().join("plain.txt")).unwrap(),
+            b"file contents"
+        );
+        overwrite["passphrase"] = "wrong".into();
+        let response = crypto.call("decrypt-file", &overwrite).await;
+        assert_eq!(wait(&response["result"]).await, "failed");
+        assert_eq!(
+            std::fs::read(dir.path().join("plain.txt")).unwrap(),
+            b"file contents"
+        );
+
+        // Hashing a file is confined the same way
+        let digest = crypto
+            .call("sha256", &json!({ "input": "file contents" }))
+            .await;
+        let response = crypto.call("sha256", &json!({ "path": "plain.txt" })).await;
+        assert_eq!(response["result"], digest["result"]);
+        let response = crypto
+            .call("hmac-sha256", &json!({ "path": "plain.txt", "key": "k" }))
+            .await;
+        assert_eq!(response["code"], 0);
+        for (crypto, method, args) in [
+            (&crypto, "sha256", json!({ "path": absolute })),
+            (&crypto, "sha256", json!({ "path": "../plain.txt" })),
+            (&disabled, "sha256", json!({ "path": "plain.txt" })),
+            (
+                &disabled,
+                "hmac-sha256",
+                json!({ "path": "plain.txt", "key": "k" }),
+            ),
+        ] {
+            let response = crypto.call(method, &args).await;
+            assert_eq!(response["code"], Code::PermissionDenied as i32, "{args}");
+        }
+
+        // So is reading a passphrase wordlist
+        std::fs::write(dir.path().join("words.txt"), "alpha\nbravo\ncharlie\n").unwrap();
+        let response = crypto
+            .call("passphrase", &json!({ "path": "words.txt", "words": 4 }))
+            .await;
+        assert_eq!(response["code"], 0, "{response}");
+        assert_eq!(response["result"].as_str().unwrap().split('-').count(), 4);
+        for (crypto, path) in [
+            (&crypto, "/etc/passwd"),
+            (&crypto, "../words.txt"),
+            (&disabled, "words.txt"),
+        ] {
+            let response = crypto.call("passphrase", &json!({ "path": path })).await;
+            assert_eq!(response["code"], Code::PermissionDenied as i32, "{path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_heavy_methods_do_not_block_the_runtime() {
+        // A single-threaded runtime: an inline scrypt call would hold its only thread
+        let (activity_tx, _activity_rx) = unbounded_channel();
+        let crypto = Crypto::with_pool(activity_tx, BlockingPool::new(1, 0));
+        let slow_args = json!({ "input": "secret", "passphrase": "pw", "cost": 1 << 17 });
+        let slow = tokio::spawn({
+            let crypto = crypto.clone();
+            let args = slow_args.clone();
+            async move { crypto.call("scrypt-encrypt", &args).await }
+        });
+        let started = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while crypto.pool.running() == 0 {
+                tokio::task::yield_now().await;
+            }
+        });
+        assert!(
+            started.await.is_ok(),
+            "scrypt-encrypt never reached the pool"
+        );
+
+        let response = crypto.call("encode64", &json!({ "input": "hello" })).await;
+        assert_eq!(response["result"], "aGVsbG8=");
+        assert!(!slow.is_finished());
+        let response = crypto.call("scrypt-encrypt", &slow_args).await;
+        assert_eq!(response["code"], Code::QueueFull as i32);
+        assert_eq!(slow.await.unwrap()["code"], 0);
     }
 }
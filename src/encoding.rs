@@ -0,0 +1,163 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose};
+
+use crate::base52;
+
+/// Text encodings for binary output such as digests, keys and random bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Hex,
+    Base64,
+    Base52,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 3] = [Encoding::Hex, Encoding::Base64, Encoding::Base52];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Hex => "hex",
+            Encoding::Base64 => "base64",
+            Encoding::Base52 => "base52",
+        }
+    }
+
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Hex => hex::encode(bytes),
+            Encoding::Base64 => general_purpose::STANDARD.encode(bytes),
+            Encoding::Base52 => base52::encode(bytes),
+        }
+    }
+
+    pub fn decode(&self, text: &str) -> Result<Vec<u8>> {
+        Ok(match self {
+            Encoding::Hex => hex::decode(text.trim())?,
+            Encoding::Base64 => general_purpose::STANDARD.decode(text.trim())?,
+            Encoding::Base52 => base52::decode(text.trim())?,
+        })
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Encoding::ALL
+            .into_iter()
+            .find(|encoding| encoding.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("unknown encoding: {s}"))
+    }
+}
+
+/// Encoding of a plaintext payload over IPC: UTF-8 text, or binary data in one of the
+/// text encodings so bytes that are not valid UTF-8 can round-trip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadEncoding {
+    #[default]
+    Utf8,
+    Binary(Encoding),
+}
+
+impl PayloadEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PayloadEncoding::Utf8 => "utf8",
+            PayloadEncoding::Binary(encoding) => encoding.name(),
+        }
+    }
+
+    /// Bytes of a payload given as text
+    pub fn decode(&self, text: &str) -> Result<Vec<u8>> {
+        match self {
+            PayloadEncoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            PayloadEncoding::Binary(encoding) => encoding.decode(text),
+        }
+    }
+
+    /// Text of a payload, failing for utf8 when the bytes are not valid UTF-8
+    pub fn encode(&self, bytes: Vec<u8>) -> Result<String> {
+        match self {
+            PayloadEncoding::Utf8 => String::from_utf8(bytes)
+                .map_err(|e| anyhow!("{e}; request a binary encoding such as base64")),
+            PayloadEncoding::Binary(encoding) => Ok(encoding.encode(&bytes)),
+        }
+    }
+}
+
+impl fmt::Display for PayloadEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PayloadEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("utf8") || s.eq_ignore_ascii_case("utf-8") {
+            return Ok(PayloadEncoding::Utf8);
+        }
+        s.parse().map(PayloadEncoding::Binary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_all_encodings() {
+        let data = [0x00u8, 0x01, 0x7f, 0x80, 0xff, b'a'];
+        for encoding in Encoding::ALL {
+            let text = encoding.encode(&data);
+            assert_eq!(encoding.decode(&text).unwrap(), data, "{encoding}");
+        }
+    }
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(Encoding::Hex.encode(b"\x01\xab"), "01ab");
+        assert_eq!(Encoding::Base64.encode(b"hello"), "aGVsbG8=");
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(Encoding::Hex.decode("xyz").is_err());
+        assert!(Encoding::Base64.decode("###").is_err());
+        assert!(Encoding::Base52.decode("abc1").is_err());
+    }
+
+    #[test]
+    fn test_names() {
+        for encoding in Encoding::ALL {
+            assert_eq!(encoding.name().parse::<Encoding>().unwrap(), encoding);
+        }
+        assert!("utf16".parse::<Encoding>().is_err());
+    }
+    #[test]
+    fn test_payload_encodings() {
+        let binary = vec![0x00u8, 0xff, 0xfe, b'a'];
+        for name in ["hex", "base64", "base52"] {
+            let payload: PayloadEncoding = name.parse().unwrap();
+            let text = payload.encode(binary.clone()).unwrap();
+            assert_eq!(payload.decode(&text).unwrap(), binary, "{name}");
+        }
+        let utf8: PayloadEncoding = "UTF-8".parse().unwrap();
+        assert_eq!(utf8, PayloadEncoding::default());
+        assert_eq!(utf8.decode("héllo").unwrap(), "héllo".as_bytes());
+        assert_eq!(utf8.encode(b"hi".to_vec()).unwrap(), "hi");
+        assert!(utf8.encode(binary).is_err());
+        assert!("latin1".parse::<PayloadEncoding>().is_err());
+    }
+}
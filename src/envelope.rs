@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose};
+use openssl::rand::rand_bytes;
+use openssl::sha::sha256;
+use serde::{Deserialize, Serialize};
+
+use crate::aead::{self, AeadCipher};
+use crate::keywrap;
+
+/// Version written into every envelope
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Source of the master key (key-encryption key) that wraps per-record data keys.
+///
+/// The provider name and key id are stored in each envelope so it can be routed back
+/// to the right provider and master key when decrypting.
+pub trait KeyProvider {
+    /// Name recorded in envelopes: "file", "env" or "mock"
+    fn name(&self) -> &'static str;
+
+    /// Identifier of the master key used by [`KeyProvider::wrap_key`]
+    fn key_id(&self) -> &str;
+
+    /// Wrap a data key with the current master key
+    fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>>;
+
+    /// Unwrap a data key wrapped by the master key `key_id`
+    fn unwrap_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// AES master key with its identifier, wrapping data keys with RFC 3394
+struct MasterKey {
+    id: String,
+    kek: Vec<u8>,
+}
+
+impl MasterKey {
+    fn new(kek: Vec<u8>) -> Result<Self> {
+        if !matches!(kek.len(), 16 | 24 | 32) {
+            return Err(anyhow!(
+                "master key must be 16, 24 or 32 bytes, got {}",
+                kek.len()
+            ));
+        }
+        // The id is derived from the key so a wrong master key is reported as such
+        let id = format!("sha256:{}", hex::encode(&sha256(&kek)[..8]));
+        Ok(Self { id, kek })
+    }
+
+    fn wrap(&self, data_key: &[u8]) -> Result<Vec<u8>> {
+        keywrap::wrap(&self.kek, data_key)
+    }
+
+    fn unwrap(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        if key_id != self.id {
+            return Err(anyhow!(
+                "envelope was sealed with master key {key_id}, not {}",
+                self.id
+            ));
+        }
+        keywrap::unwrap(&self.kek, wrapped)
+    }
+}
+
+impl Drop for MasterKey {
+    fn drop(&mut self) {
+        self.kek.fill(0);
+    }
+}
+
+/// Master key text: hex or base64 of a 16, 24 or 32 byte AES key
+pub fn parse_master_key(text: &str) -> Result<Vec<u8>> {
+    let text = text.trim();
+    hex::decode(text)
+        .ok()
+        .or_else(|| general_purpose::STANDARD.decode(text).ok())
+        .ok_or_else(|| anyhow!("master key must be hex or base64"))
+}
+
+/// Master key stored in a file, either as raw bytes or as hex/base64 text
+pub struct FileKeyProvider {
+    key: MasterKey,
+}
+
+impl FileKeyProvider {
+    pub fn new(path: &Path) -> Result<Self> {
+        let data = fs::read(path)
+            .map_err(|e| anyhow!("cannot read master key {}: {e}", path.display()))?;
+        let kek = match std::str::from_utf8(&data) {
+            Ok(text) if !matches!(data.len(), 16 | 24 | 32) => parse_master_key(text)?,
+            _ => data,
+        };
+        Ok(Self {
+            key: MasterKey::new(kek)?,
+        })
+    }
+}
+
+impl KeyProvider for FileKeyProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn key_id(&self) -> &str {
+        &self.key.id
+    }
+
+    fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>> {
+        self.key.wrap(data_key)
+    }
+
+    fn unwrap_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        self.key.unwrap(key_id, wrapped)
+    }
+}
+
+/// Master key held as hex or base64 in an environment variable
+pub struct EnvKeyProvider {
+    key: MasterKey,
+}
+
+impl EnvKeyProvider {
+    pub fn new(variable: &str) -> Result<Self> {
+        let text = std::env::var(variable)
+            .map_err(|e| anyhow!("cannot read master key from ${variable}: {e}"))?;
+        Ok(Self {
+            key: MasterKey::new(parse_master_key(&text)?)?,
+        })
+    }
+}
+
+impl KeyProvider for EnvKeyProvider {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn key_id(&self) -> &str {
+        &self.key.id
+    }
+
+    fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>> {
+        self.key.wrap(data_key)
+    }
+
+    fn unwrap_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        self.key.unwrap(key_id, wrapped)
+    }
+}
+
+/// In-process stand-in for a KMS: random master keys that can be rotated while
+/// older keys stay available for unwrapping
+pub struct MockKeyProvider {
+    keys: HashMap<String, MasterKey>,
+    current: String,
+}
+
+impl MockKeyProvider {
+    pub fn new() -> Result<Self> {
+        let mut provider = Self {
+            keys: HashMap::new(),
+            current: String::new(),
+        };
+        provider.rotate()?;
+        Ok(provider)
+    }
+
+    /// Mock holding a known master key, e.g. to share it with another process
+    pub fn with_key(kek: &[u8]) -> Result<Self> {
+        let key = MasterKey::new(kek.to_vec())?;
+        let current = key.id.clone();
+        Ok(Self {
+            keys: HashMap::from([(current.clone(), key)]),
+            current,
+        })
+    }
+
+    /// Make a new random master key current and return its id
+    pub fn rotate(&mut self) -> Result<&str> {
+        let mut kek = vec![0u8; 32];
+        rand_bytes(&mut kek)?;
+        let key = MasterKey::new(kek)?;
+        self.current = key.id.clone();
+        self.keys.insert(key.id.clone(), key);
+        Ok(&self.current)
+    }
+}
+
+impl KeyProvider for MockKeyProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn key_id(&self) -> &str {
+        &self.current
+    }
+
+    fn wrap_key(&self, data_key: &[u8]) -> Result<Vec<u8>> {
+        self.keys[&self.current].wrap(data_key)
+    }
+
+    fn unwrap_key(&self, key_id: &str, wrapped: &[u8]) -> Result<Vec<u8>> {
+        self.keys
+            .get(key_id)
+            .ok_or_else(|| anyhow!("unknown master key: {key_id}"))?
+            .unwrap(key_id, wrapped)
+    }
+}
+
+/// Where a provider finds its master key, e.g. as given to the IPC worker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProviderConfig {
+    File(PathBuf),
+    Env(String),
+    /// Mock provider with a fixed master key
+    Mock(Vec<u8>),
+}
+
+impl ProviderConfig {
+    pub fn provider(&self) -> Result<Box<dyn KeyProvider>> {
+        Ok(match self {
+            ProviderConfig::File(path) => Box::new(FileKeyProvider::new(path)?),
+            ProviderConfig::Env(variable) => Box::new(EnvKeyProvider::new(variable)?),
+            ProviderConfig::Mock(kek) => Box::new(MockKeyProvider::with_key(kek)?),
+        })
+    }
+}
+
+/// Self-describing envelope; binary fields are base64
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub version: u32,
+    pub provider: String,
+    pub key_id: String,
+    pub cipher: String,
+    pub wrapped_key: String,
+    pub nonce: String,
+    /// Ciphertext || tag
+    pub ciphertext: String,
+}
+
+impl Envelope {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let envelope: Envelope =
+            serde_json::from_str(json).map_err(|e| anyhow!("invalid envelope: {e}"))?;
+        if envelope.version != ENVELOPE_VERSION {
+            return Err(anyhow!("unsupported envelope version {}", envelope.version));
+        }
+        Ok(envelope)
+    }
+}
+
+/// Encrypt `plaintext` under a fresh data key wrapped by the provider's master key
+pub fn seal(provider: &dyn KeyProvider, plaintext: &[u8], cipher: AeadCipher) -> Result<Envelope> {
+    let mut data_key = vec![0u8; aead::KEY_LEN];
+    rand_bytes(&mut data_key)?;
+    let mut nonce = vec![0u8; cipher.nonce_len()];
+    rand_bytes(&mut nonce)?;
+
+    let sealed = aead::seal(cipher, &data_key, &nonce, plaintext);
+    let wrapped_key = provider.wrap_key(&data_key);
+    data_key.fill(0);
+
+    Ok(Envelope {
+        version: ENVELOPE_VERSION,
+        provider: provider.name().to_string(),
+        key_id: provider.key_id().to_string(),
+        cipher: cipher.name().to_string(),
+        wrapped_key: general_purpose::STANDARD.encode(wrapped_key?),
+        nonce: general_purpose::STANDARD.encode(nonce),
+        ciphertext: general_purpose::STANDARD.encode(sealed?),
+    })
+}
+
+/// Decrypt an envelope, failing when it belongs to another provider or was modified
+pub fn open(provider: &dyn KeyProvider, envelope: &Envelope) -> Result<Vec<u8>> {
+    if envelope.provider != provider.name() {
+        return Err(anyhow!(
+            "envelope was sealed by the {} provider, not {}",
+            envelope.provider,
+            provider.name()
+        ));
+    }
+    let cipher: AeadCipher = envelope.cipher.parse()?;
+    let decode = |field: &str| {
+        general_purpose::STANDARD
+            .decode(field)
+            .map_err(|e| anyhow!("invalid envelope: {e}"))
+    };
+    let mut data_key = provider.unwrap_key(&envelope.key_id, &decode(&envelope.wrapped_key)?)?;
+    let plaintext = aead::open(
+        cipher,
+        &data_key,
+        &decode(&envelope.nonce)?,
+        &decode(&envelope.ciphertext)?,
+    );
+    data_key.fill(0);
+    plaintext
+}
+
+/// [`seal`] with AES-256-GCM, serialized as JSON
+pub fn encrypt(provider: &dyn KeyProvider, plaintext: &[u8]) -> Result<String> {
+    seal(provider, plaintext, AeadCipher::Aes256Gcm)?.to_json()
+}
+
+/// [`open`] of a JSON envelope
+pub fn decrypt(provider: &dyn KeyProvider, json: &str) -> Result<Vec<u8>> {
+    open(provider, &Envelope::from_json(json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_provider_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let hex_path = dir.path().join("master.hex");
+        fs::write(&hex_path, format!("{}\n", hex::encode([5u8; 32]))).unwrap();
+        let raw_path = dir.path().join("master.key");
+        fs::write(&raw_path, [5u8; 32]).unwrap();
+
+        let provider = FileKeyProvider::new(&hex_path).unwrap();
+        let json = encrypt(&provider, b"card 4111").unwrap();
+        let envelope = Envelope::from_json(&json).unwrap();
+        assert_eq!(envelope.provider, "file");
+        assert_eq!(envelope.cipher, "aes-256-gcm");
+
+        // Same key in raw form, through the configuration the worker uses
+        let config = ProviderConfig::File(raw_path);
+        let reopened = config.provider().unwrap();
+        assert_eq!(reopened.key_id(), provider.key_id());
+        assert_eq!(decrypt(reopened.as_ref(), &json).unwrap(), b"card 4111");
+        assert_ne!(encrypt(&provider, b"card 4111").unwrap(), json);
+    }
+
+    #[test]
+    fn test_mock_rotation_and_wrong_provider() {
+        let mut mock = MockKeyProvider::new().unwrap();
+        let old = seal(&mock, b"record", AeadCipher::XChaCha20Poly1305).unwrap();
+        let old_id = mock.key_id().to_string();
+        assert_ne!(mock.rotate().unwrap(), old_id);
+        let new = seal(&mock, b"record", AeadCipher::Aes256Gcm).unwrap();
+        assert_ne!(old.key_id, new.key_id);
+        assert_eq!(open(&mock, &old).unwrap(), b"record");
+        assert_eq!(open(&mock, &new).unwrap(), b"record");
+
+        let other = MockKeyProvider::new().unwrap();
+        assert!(open(&other, &new).is_err());
+        let shared = MockKeyProvider::with_key(&[1u8; 16]).unwrap();
+        assert!(open(&shared, &new).is_err());
+
+        let mut tampered = new.clone();
+        tampered.provider = "file".to_string();
+        assert!(open(&mock, &tampered).is_err());
+        let mut tampered = new.clone();
+        tampered.ciphertext = general_purpose::STANDARD.encode(b"not the ciphertext!");
+        assert!(open(&mock, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_invalid_configuration() {
+        assert!(EnvKeyProvider::new("ENZO_CRYPTO_TEST_UNSET_MASTER_KEY").is_err());
+        assert!(MockKeyProvider::with_key(&[1u8; 20]).is_err());
+        assert_eq!(parse_master_key(" 0102 ").unwrap(), [1, 2]);
+        assert_eq!(parse_master_key("AQI=").unwrap(), [1, 2]);
+        assert!(parse_master_key("not a key!").is_err());
+        assert!(Envelope::from_json("{\"version\":2}").is_err());
+        assert!(
+            Envelope::from_json(
+                "{\"version\":2,\"provider\":\"mock\",\"key_id\":\"\",\"cipher\":\"\",\
+                 \"wrapped_key\":\"\",\"nonce\":\"\",\"ciphertext\":\"\"}"
+            )
+            .is_err()
+        );
+    }
+}
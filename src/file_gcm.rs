@@ -1,60 +1,180 @@
 use anyhow::{Result, anyhow};
-use openssl::pkcs5;
 use openssl::rand::rand_bytes;
-use openssl::symm::{Cipher, Crypter, Mode};
+use openssl::symm::Mode;
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
+use crate::aead::{self, AeadCipher};
+use crate::kdf::{self, Kdf, Scrypt};
+
 const SALT_LEN: usize = 16;
+#[cfg(test)]
 const NONCE_LEN: usize = 12; // Recommended nonce size for GCM
 const KEY_LEN: usize = 32;
 const TAG_LEN: usize = 16;
+const BLOCK_SIZE: usize = 16;
 
-// scrypt parameters (adjust based on your security requirements)
-const SCRYPT_N: u64 = 16384;
-const SCRYPT_R: u64 = 8;
-const SCRYPT_P: u64 = 1;
-const SCRYPT_MAXMEM: u64 = 512 * 1024 * 1024;
-
-fn derive_key_scrypt(password: &str, salt: &[u8]) -> Result<Vec<u8>> {
+fn derive_key(kdf: &dyn Kdf, password: &str, salt: &[u8]) -> Result<Vec<u8>> {
+    kdf::ensure_password_kdf(kdf.algorithm())?;
     let mut key = vec![0u8; KEY_LEN];
-    pkcs5::scrypt(
-        password.as_bytes(),
-        salt,
-        SCRYPT_N,
-        SCRYPT_R,
-        SCRYPT_P,
-        SCRYPT_MAXMEM,
-        &mut key,
-    )?;
+    kdf.derive(password.as_bytes(), salt, &mut key)?;
     Ok(key)
 }
 
+#[cfg(test)]
+fn derive_key_scrypt(password: &str, salt: &[u8]) -> Result<Vec<u8>> {
+    derive_key(&Scrypt::default(), password, salt)
+}
+
+/// Cipher, KDF, total header length and salt at the start of a file
+struct Header {
+    cipher: AeadCipher,
+    kdf: Box<dyn Kdf>,
+    len: usize,
+    salt: [u8; SALT_LEN],
+}
+
+/// Read the optional cipher and KDF headers and the salt from the start of the file.
+fn read_header<R: Read>(reader: &mut R) -> Result<Header> {
+    let mut prefix = [0u8; kdf::HEADER_PREFIX_LEN];
+    reader.read_exact(&mut prefix)?;
+    let mut salt = [0u8; SALT_LEN];
+
+    // The cipher header has the same length as a KDF header prefix
+    let (cipher, cipher_header_len) = match aead::decode_header(&prefix)? {
+        Some(header) => {
+            reader.read_exact(&mut prefix)?;
+            header
+        }
+        None => (AeadCipher::Aes256Gcm, 0),
+    };
+
+    match kdf::params_len(&prefix) {
+        Some(params_len) => {
+            let mut header = prefix.to_vec();
+            header.resize(kdf::HEADER_PREFIX_LEN + params_len, 0);
+            reader.read_exact(&mut header[kdf::HEADER_PREFIX_LEN..])?;
+            let (kdf, header_len) =
+                kdf::decode_header(&header)?.ok_or_else(|| anyhow!("Invalid KDF header"))?;
+            reader.read_exact(&mut salt)?;
+            Ok(Header {
+                cipher,
+                kdf,
+                len: cipher_header_len + header_len,
+                salt,
+            })
+        }
+        None => {
+            // Legacy file: the prefix is the beginning of the salt
+            salt[..kdf::HEADER_PREFIX_LEN].copy_from_slice(&prefix);
+            reader.read_exact(&mut salt[kdf::HEADER_PREFIX_LEN..])?;
+            Ok(Header {
+                cipher,
+                kdf: Box::new(Scrypt::default()),
+                len: cipher_header_len,
+                salt,
+            })
+        }
+    }
+}
+
 /// Encrypt large file with password and write output file with format:
 /// [salt(16 bytes)] [nonce(12 bytes)] [ciphertext stream ...] [tag(16 bytes)]
 pub fn encrypt_file(input_path: &Path, output_path: &Path, password: &str) -> Result<()> {
+    encrypt_file_inner(
+        BufReader::new(File::open(input_path)?),
+        output_path,
+        password,
+        &Scrypt::default(),
+        AeadCipher::Aes256Gcm,
+        Vec::new(),
+    )
+}
+
+/// Encrypt large file with password using the given KDF and write output file with format:
+/// [kdf header] [salt(16 bytes)] [nonce(12 bytes)] [ciphertext stream ...] [tag(16 bytes)]
+pub fn encrypt_file_with_kdf(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    kdf: &dyn Kdf,
+) -> Result<()> {
+    encrypt_file_inner(
+        BufReader::new(File::open(input_path)?),
+        output_path,
+        password,
+        kdf,
+        AeadCipher::Aes256Gcm,
+        kdf::encode_header(kdf)?,
+    )
+}
+
+/// Encrypt large file with password using the given KDF and cipher and write output file with format:
+/// [cipher header] [kdf header] [salt(16 bytes)] [nonce(12 or 24 bytes)] [ciphertext stream ...] [tag(16 bytes)]
+pub fn encrypt_file_with_cipher(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    kdf: &dyn Kdf,
+    cipher: AeadCipher,
+) -> Result<()> {
+    let mut header = aead::encode_header(cipher);
+    header.extend_from_slice(&kdf::encode_header(kdf)?);
+    encrypt_file_inner(
+        BufReader::new(File::open(input_path)?),
+        output_path,
+        password,
+        kdf,
+        cipher,
+        header,
+    )
+}
+
+/// Encrypt everything read from `reader` into an output file in the format of
+/// [`encrypt_file_with_kdf`], e.g. to re-encrypt decrypted data without a plaintext file
+pub fn encrypt_reader_with_kdf<R: Read>(
+    reader: R,
+    output_path: &Path,
+    password: &str,
+    kdf: &dyn Kdf,
+) -> Result<()> {
+    encrypt_file_inner(
+        reader,
+        output_path,
+        password,
+        kdf,
+        AeadCipher::Aes256Gcm,
+        kdf::encode_header(kdf)?,
+    )
+}
+
+fn encrypt_file_inner<R: Read>(
+    mut infile: R,
+    output_path: &Path,
+    password: &str,
+    kdf: &dyn Kdf,
+    cipher: AeadCipher,
+    header: Vec<u8>,
+) -> Result<()> {
     let mut salt = [0u8; SALT_LEN];
-    let mut nonce = [0u8; NONCE_LEN];
+    let mut nonce = vec![0u8; cipher.nonce_len()];
     rand_bytes(&mut salt)?;
     rand_bytes(&mut nonce)?;
 
-    let key = derive_key_scrypt(password, &salt)?;
-
-    let cipher = Cipher::aes_256_gcm();
+    let key = derive_key(kdf, password, &salt)?;
 
-    let mut crypter = Crypter::new(cipher, Mode::Encrypt, &key, Some(&nonce))?;
-    crypter.pad(false); // GCM does not use padding
+    let mut crypter = cipher.crypter(Mode::Encrypt, &key, &nonce)?;
 
-    let mut infile = BufReader::new(File::open(input_path)?);
     let mut outfile = BufWriter::new(File::create(output_path)?);
 
-    // Write salt and nonce at the start of output
+    // Write header, salt and nonce at the start of output
+    outfile.write_all(&header)?;
     outfile.write_all(&salt)?;
     outfile.write_all(&nonce)?;
 
     let mut buffer = [0u8; 4096];
-    let mut ciphertext_chunk = vec![0u8; 4096 + cipher.block_size()];
+    let mut ciphertext_chunk = vec![0u8; 4096 + BLOCK_SIZE];
     loop {
         let count = infile.read(&mut buffer)?;
         if count == 0 {
@@ -79,36 +199,40 @@ pub fn encrypt_file(input_path: &Path, output_path: &Path, password: &str) -> Re
 }
 
 /// Decrypt large file with password from file format:
-/// [salt(16 bytes)] [nonce(12 bytes)] [ciphertext stream ...] [tag(16 bytes)]
+/// [cipher header (optional)] [kdf header (optional)] [salt(16 bytes)] [nonce(12 or 24 bytes)]
+/// [ciphertext stream ...] [tag(16 bytes)]
 pub fn decrypt_file(input_path: &Path, output_path: &Path, password: &str) -> Result<()> {
     let mut infile = BufReader::new(File::open(input_path)?);
 
-    // Read salt and nonce from file start
-    let mut salt = [0u8; SALT_LEN];
-    infile.read_exact(&mut salt)?;
-    let mut nonce = [0u8; NONCE_LEN];
+    // Read header, salt and nonce from file start
+    let Header {
+        cipher,
+        kdf,
+        len: header_len,
+        salt,
+    } = read_header(&mut infile)?;
+    let mut nonce = vec![0u8; cipher.nonce_len()];
     infile.read_exact(&mut nonce)?;
 
     // Determine file length to find where tag is located
     let metadata = infile.get_ref().metadata()?;
     let file_size = metadata.len();
 
-    // Calculate ciphertext size: total - salt - nonce - tag
-    let ciphertext_len = file_size as usize - SALT_LEN - NONCE_LEN - TAG_LEN;
-
-    let key = derive_key_scrypt(password, &salt)?;
+    // Calculate ciphertext size: total - header - salt - nonce - tag
+    let ciphertext_len = (file_size as usize)
+        .checked_sub(header_len + SALT_LEN + nonce.len() + TAG_LEN)
+        .ok_or_else(|| anyhow!("Input file too short"))?;
 
-    let cipher = Cipher::aes_256_gcm();
+    let key = derive_key(kdf.as_ref(), password, &salt)?;
 
-    let mut crypter = Crypter::new(cipher, Mode::Decrypt, &key, Some(&nonce))?;
-    crypter.pad(false);
+    let mut crypter = cipher.crypter(Mode::Decrypt, &key, &nonce)?;
 
     let mut outfile = BufWriter::new(File::create(output_path)?);
 
     // Buffer for reading ciphertext chunks
     let mut buffer = vec![0u8; 4096];
     // Buffer for decrypted plaintext output
-    let mut plaintext_chunk = vec![0u8; 4096 + cipher.block_size()];
+    let mut plaintext_chunk = vec![0u8; 4096 + BLOCK_SIZE];
 
     // Read ciphertext chunks until just before tag
     let mut total_read = 0usize;
@@ -272,6 +396,44 @@ mod tests {
         assert!(tag.iter().any(|&b| b != 0));
     }
 
+    #[test]
+    fn test_encrypt_decrypt_file_with_kdf() {
+        let password = "strongpassword";
+        let tempdir = TempDir::with_prefix_in("test", "./").unwrap();
+
+        let infile = write_temp_file(&tempdir, "input.txt", b"PBKDF2 protected data");
+        let outfile_enc = tempdir.path().join("encrypted.bin");
+        let outfile_dec = tempdir.path().join("decrypted.txt");
+
+        let kdf = crate::kdf::Pbkdf2 {
+            sha512: true,
+            iterations: 1000,
+        };
+        encrypt_file_with_kdf(&infile, &outfile_enc, password, &kdf).expect("encryption failed");
+
+        let encrypted = read_file_to_vec(&outfile_enc);
+        assert!(encrypted.starts_with(kdf::HEADER_MAGIC));
+        assert_eq!(
+            encrypted[kdf::HEADER_MAGIC.len()],
+            kdf::KdfAlgorithm::Pbkdf2Sha512 as u8
+        );
+
+        decrypt_file(&outfile_enc, &outfile_dec, password).expect("decryption failed");
+        assert_eq!(read_file_to_vec(&outfile_dec), b"PBKDF2 protected data");
+
+        let result = decrypt_file(&outfile_enc, &outfile_dec, "wrong_password");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decrypt_too_short_file_fails() {
+        let tempdir = TempDir::with_prefix_in("test", "./").unwrap();
+        let infile = write_temp_file(&tempdir, "short.bin", &[0x42u8; SALT_LEN + NONCE_LEN]);
+        let outfile_dec = tempdir.path().join("decrypted.txt");
+
+        assert!(decrypt_file(&infile, &outfile_dec, "password").is_err());
+    }
+
     #[test]
     fn test_encrypt_decrypt_huge_file() {
         use rand::rngs::OsRng;
@@ -323,4 +485,42 @@ mod tests {
             "Decrypted data does not match original"
         );
     }
+
+    #[test]
+    fn test_encrypt_decrypt_file_with_cipher() {
+        let password = "strongpassword";
+        let tempdir = TempDir::with_prefix_in("test", "./").unwrap();
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i % 253) as u8).collect();
+        let infile = write_temp_file(&tempdir, "input.bin", &data);
+        let outfile_enc = tempdir.path().join("encrypted.bin");
+        let outfile_dec = tempdir.path().join("decrypted.bin");
+
+        let kdf = crate::kdf::Pbkdf2 {
+            sha512: false,
+            iterations: 1000,
+        };
+        for cipher in AeadCipher::ALL {
+            encrypt_file_with_cipher(&infile, &outfile_enc, password, &kdf, cipher)
+                .expect("encryption failed");
+
+            let encrypted = read_file_to_vec(&outfile_enc);
+            assert_eq!(
+                aead::decode_header(&encrypted).unwrap(),
+                Some((cipher, aead::HEADER_LEN))
+            );
+            assert_eq!(
+                encrypted.len(),
+                aead::HEADER_LEN
+                    + kdf::encode_header(&kdf).unwrap().len()
+                    + SALT_LEN
+                    + cipher.nonce_len()
+                    + data.len()
+                    + TAG_LEN
+            );
+
+            decrypt_file(&outfile_enc, &outfile_dec, password).expect("decryption failed");
+            assert_eq!(read_file_to_vec(&outfile_dec), data, "{cipher}");
+            assert!(decrypt_file(&outfile_enc, &outfile_dec, "wrong_password").is_err());
+        }
+    }
 }
@@ -0,0 +1,339 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use openssl::hash::{Hasher, MessageDigest};
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Digest algorithms available for hashing and HMAC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+    Sha3_256,
+    Sha3_384,
+    Sha3_512,
+    Blake2b512,
+    Blake2s256,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 8] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha384,
+        HashAlgorithm::Sha512,
+        HashAlgorithm::Sha3_256,
+        HashAlgorithm::Sha3_384,
+        HashAlgorithm::Sha3_512,
+        HashAlgorithm::Blake2b512,
+        HashAlgorithm::Blake2s256,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha384 => "sha384",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Sha3_256 => "sha3-256",
+            HashAlgorithm::Sha3_384 => "sha3-384",
+            HashAlgorithm::Sha3_512 => "sha3-512",
+            HashAlgorithm::Blake2b512 => "blake2b512",
+            HashAlgorithm::Blake2s256 => "blake2s256",
+        }
+    }
+
+    pub fn digest(&self) -> Result<MessageDigest> {
+        let digest = match self {
+            HashAlgorithm::Sha256 => Some(MessageDigest::sha256()),
+            HashAlgorithm::Sha384 => Some(MessageDigest::sha384()),
+            HashAlgorithm::Sha512 => Some(MessageDigest::sha512()),
+            HashAlgorithm::Sha3_256 => Some(MessageDigest::sha3_256()),
+            HashAlgorithm::Sha3_384 => Some(MessageDigest::sha3_384()),
+            HashAlgorithm::Sha3_512 => Some(MessageDigest::sha3_512()),
+            HashAlgorithm::Blake2b512 => MessageDigest::from_name("BLAKE2b512"),
+            HashAlgorithm::Blake2s256 => MessageDigest::from_name("BLAKE2s256"),
+        };
+        digest.ok_or_else(|| anyhow!("{self} is not supported by this OpenSSL build"))
+    }
+
+    /// Digest length in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Sha3_256 | HashAlgorithm::Blake2s256 => 32,
+            HashAlgorithm::Sha384 | HashAlgorithm::Sha3_384 => 48,
+            HashAlgorithm::Sha512 | HashAlgorithm::Sha3_512 | HashAlgorithm::Blake2b512 => 64,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        HashAlgorithm::ALL
+            .into_iter()
+            .find(|alg| alg.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("unknown hash algorithm: {s}"))
+    }
+}
+
+/// Hash a byte slice
+pub fn hash(algorithm: HashAlgorithm, data: &[u8]) -> Result<Vec<u8>> {
+    Ok(openssl::hash::hash(algorithm.digest()?, data)?.to_vec())
+}
+
+/// Hash everything readable from `reader` in fixed-size chunks
+pub fn hash_reader<R: Read>(algorithm: HashAlgorithm, mut reader: R) -> Result<Vec<u8>> {
+    let mut hasher = Hasher::new(algorithm.digest()?)?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count])?;
+    }
+    Ok(hasher.finish()?.to_vec())
+}
+
+/// Hash a file without loading it into memory
+pub fn hash_file(algorithm: HashAlgorithm, path: &Path) -> Result<Vec<u8>> {
+    hash_reader(algorithm, BufReader::new(File::open(path)?))
+}
+
+/// HMAC of a byte slice
+pub fn hmac(algorithm: HashAlgorithm, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    hmac_reader(algorithm, key, data)
+}
+
+/// HMAC of everything readable from `reader` in fixed-size chunks
+pub fn hmac_reader<R: Read>(
+    algorithm: HashAlgorithm,
+    key: &[u8],
+    mut reader: R,
+) -> Result<Vec<u8>> {
+    let pkey = PKey::hmac(key)?;
+    let mut signer = Signer::new(algorithm.digest()?, &pkey)?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        signer.update(&buffer[..count])?;
+    }
+    Ok(signer.sign_to_vec()?)
+}
+
+/// HMAC of a file without loading it into memory
+pub fn hmac_file(algorithm: HashAlgorithm, key: &[u8], path: &Path) -> Result<Vec<u8>> {
+    hmac_reader(algorithm, key, BufReader::new(File::open(path)?))
+}
+
+/// Check an HMAC in constant time
+pub fn verify_hmac(algorithm: HashAlgorithm, key: &[u8], data: &[u8], mac: &[u8]) -> Result<bool> {
+    let expected = hmac(algorithm, key, data)?;
+    Ok(memcmp_eq(&expected, mac))
+}
+
+/// Result of checking one line of a checksum manifest
+#[derive(Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub ok: bool,
+}
+
+/// Verify a `sha256sum`-style manifest (`<hex digest>  <file>` per line).
+/// Relative paths are resolved against `base_dir`. Missing or unreadable
+/// files are reported as failed entries.
+pub fn verify_manifest(
+    algorithm: HashAlgorithm,
+    manifest: &Path,
+    base_dir: &Path,
+) -> Result<Vec<ManifestEntry>> {
+    let reader = BufReader::new(File::open(manifest)?);
+    let mut entries = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (digest, name) = line
+            .split_once(' ')
+            .ok_or_else(|| anyhow!("malformed manifest line {}", index + 1))?;
+        // "  name" for text mode, " *name" for binary mode
+        let name = name
+            .strip_prefix(' ')
+            .or_else(|| name.strip_prefix('*'))
+            .unwrap_or(name);
+        let expected = hex::decode(digest)
+            .map_err(|e| anyhow!("malformed digest on manifest line {}: {e}", index + 1))?;
+
+        let path = base_dir.join(name);
+        let ok = match hash_file(algorithm, &path) {
+            Ok(actual) => memcmp_eq(&actual, &expected),
+            Err(e) => {
+                log::warn!("Cannot hash {}: {e}", path.display());
+                false
+            }
+        };
+        entries.push(ManifestEntry {
+            path: PathBuf::from(name),
+            ok,
+        });
+    }
+    Ok(entries)
+}
+
+fn memcmp_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && memcmp::eq(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn hex_hash(algorithm: HashAlgorithm, data: &[u8]) -> String {
+        hex::encode(hash(algorithm, data).unwrap())
+    }
+
+    #[test]
+    fn test_known_digests() {
+        assert_eq!(
+            hex_hash(HashAlgorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex_hash(HashAlgorithm::Sha3_256, b"abc"),
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+        );
+        assert_eq!(
+            hex_hash(HashAlgorithm::Blake2s256, b"abc"),
+            "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982"
+        );
+        assert_eq!(
+            hex_hash(HashAlgorithm::Sha512, b""),
+            "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce\
+             47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e"
+        );
+    }
+
+    #[test]
+    fn test_digest_sizes() {
+        for algorithm in HashAlgorithm::ALL {
+            assert_eq!(hash(algorithm, b"x").unwrap().len(), algorithm.size());
+        }
+    }
+
+    #[test]
+    fn test_hmac_rfc4231_case2() {
+        let mac = hmac(
+            HashAlgorithm::Sha256,
+            b"Jefe",
+            b"what do ya want for nothing?",
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(&mac),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(
+            verify_hmac(
+                HashAlgorithm::Sha256,
+                b"Jefe",
+                b"what do ya want for nothing?",
+                &mac
+            )
+            .unwrap()
+        );
+        assert!(!verify_hmac(HashAlgorithm::Sha256, b"Jefe", b"tampered", &mac).unwrap());
+    }
+
+    #[test]
+    fn test_hmac_all_algorithms() {
+        for algorithm in HashAlgorithm::ALL {
+            let mac = hmac(algorithm, b"key", b"data").unwrap();
+            assert_eq!(mac.len(), algorithm.size(), "{algorithm}");
+        }
+    }
+
+    #[test]
+    fn test_stream_matches_one_shot() {
+        let data = vec![0x5au8; CHUNK_SIZE * 3 + 17];
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("data.bin");
+        std::fs::write(&path, &data).unwrap();
+
+        for algorithm in HashAlgorithm::ALL {
+            assert_eq!(
+                hash_file(algorithm, &path).unwrap(),
+                hash(algorithm, &data).unwrap()
+            );
+            assert_eq!(
+                hmac_file(algorithm, b"k", &path).unwrap(),
+                hmac(algorithm, b"k", &data).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_manifest() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"alpha").unwrap();
+        std::fs::write(dir.path().join("b.txt"), b"bravo").unwrap();
+
+        let manifest = dir.path().join("SHA256SUMS");
+        let mut f = File::create(&manifest).unwrap();
+        writeln!(f, "{}  a.txt", hex_hash(HashAlgorithm::Sha256, b"alpha")).unwrap();
+        writeln!(f, "{} *b.txt", hex_hash(HashAlgorithm::Sha256, b"changed")).unwrap();
+        writeln!(f, "{}  missing.txt", hex_hash(HashAlgorithm::Sha256, b"")).unwrap();
+        drop(f);
+
+        let entries = verify_manifest(HashAlgorithm::Sha256, &manifest, dir.path()).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ManifestEntry {
+                    path: "a.txt".into(),
+                    ok: true
+                },
+                ManifestEntry {
+                    path: "b.txt".into(),
+                    ok: false
+                },
+                ManifestEntry {
+                    path: "missing.txt".into(),
+                    ok: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_algorithm_names() {
+        for algorithm in HashAlgorithm::ALL {
+            assert_eq!(
+                algorithm.name().parse::<HashAlgorithm>().unwrap(),
+                algorithm
+            );
+        }
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+}
@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, Sender, UnboundedSender};
+use tokio::sync::oneshot;
+
+use crate::async_file_gcm::Progress;
+use crate::pool::PoolError;
+
+/// How often running jobs report progress
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a finished job stays known when its outcome is never polled
+pub const FINISHED_JOB_TTL: Duration = Duration::from_secs(600);
+
+/// Jobs allowed to run at once before new ones are refused
+pub const DEFAULT_MAX_JOBS: usize = 4;
+
+pub type JobId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Snapshot of a background job as returned to IPC clients
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobStatus {
+    pub id: JobId,
+    pub operation: &'static str,
+    pub state: JobState,
+    pub bytes_processed: usize,
+    pub total_bytes: usize,
+    pub percentage: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Job {
+    status: JobStatus,
+    cancel: Option<oneshot::Sender<()>>,
+    finished: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: JobId,
+    jobs: HashMap<JobId, Job>,
+}
+
+impl Registry {
+    /// Forget jobs that finished more than `ttl` ago
+    fn evict(&mut self, ttl: Duration) {
+        self.jobs
+            .retain(|_, job| job.finished.is_none_or(|finished| finished.elapsed() < ttl));
+    }
+}
+
+/// Background file jobs with progress, polling and cancellation, at most
+/// `max_running` at a time
+#[derive(Clone)]
+pub struct JobManager {
+    registry: Arc<Mutex<Registry>>,
+    max_running: usize,
+    ttl: Duration,
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_JOBS)
+    }
+}
+
+/// Hidden file next to `output` that job `id` writes before it is renamed into place
+fn partial_path(output: &Path, id: JobId) -> PathBuf {
+    let name = output
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    output.with_file_name(format!(".{name}.{}-{id}.part", std::process::id()))
+}
+
+impl JobManager {
+    pub fn new(max_running: usize) -> Self {
+        Self::with_ttl(max_running, FINISHED_JOB_TTL)
+    }
+
+    /// Manager forgetting unpolled finished jobs after `ttl`
+    pub fn with_ttl(max_running: usize, ttl: Duration) -> Self {
+        Self {
+            registry: Arc::default(),
+            max_running: max_running.max(1),
+            ttl,
+        }
+    }
+
+    /// Run `work` on the tokio runtime and return its id immediately, or
+    /// [`PoolError::QueueFull`] when `max_running` jobs are already running.
+    ///
+    /// `work` receives the file to write and the channel its progress is reported on.
+    /// The file is renamed to `output` when the job completes and removed otherwise, so
+    /// a failed or cancelled job leaves neither a partial file nor a clobbered `output`.
+    /// Every progress report is also sent on `activity_tx` to keep an idle timeout from
+    /// firing.
+    pub fn spawn<F, Fut>(
+        &self,
+        operation: &'static str,
+        output: PathBuf,
+        activity_tx: Option<UnboundedSender<()>>,
+        work: F,
+    ) -> Result<JobId, PoolError>
+    where
+        F: FnOnce(PathBuf, Sender<Progress>) -> Fut,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let (progress_tx, mut progress_rx) = mpsc::channel::<Progress>(16);
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let id = {
+            let mut registry = self.lock();
+            registry.evict(self.ttl);
+            let running = registry
+                .jobs
+                .values()
+                .filter(|job| job.finished.is_none())
+                .count();
+            if running >= self.max_running {
+                return Err(PoolError::QueueFull);
+            }
+            registry.next_id += 1;
+            let id = registry.next_id;
+            registry.jobs.insert(
+                id,
+                Job {
+                    status: JobStatus {
+                        id,
+                        operation,
+                        state: JobState::Running,
+                        bytes_processed: 0,
+                        total_bytes: 0,
+                        percentage: 0.0,
+                        error: None,
+                    },
+                    cancel: Some(cancel_tx),
+                    finished: None,
+                },
+            );
+            id
+        };
+
+        let partial = partial_path(&output, id);
+        let work = work(partial.clone(), progress_tx);
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let progress = async {
+                while let Some(progress) = progress_rx.recv().await {
+                    manager.update(id, |status| {
+                        status.bytes_processed = progress.bytes_processed();
+                        status.total_bytes = progress.total_bytes();
+                        status.percentage = progress.percentage();
+                    });
+                    if let Some(activity_tx) = &activity_tx {
+                        let _ = activity_tx.send(());
+                    }
+                }
+            };
+            let outcome = tokio::select! {
+                (result, ()) = async { tokio::join!(work, progress) } => Some(result),
+                Ok(()) = cancel_rx => None,
+            };
+
+            let outcome = match outcome {
+                Some(Ok(())) => Some(
+                    tokio::fs::rename(&partial, &output)
+                        .await
+                        .map_err(|e| anyhow!("cannot move output into place: {e}")),
+                ),
+                outcome => outcome,
+            };
+            let (state, error) = match outcome {
+                Some(Ok(())) => (JobState::Completed, None),
+                Some(Err(e)) => (JobState::Failed, Some(e.to_string())),
+                None => (JobState::Cancelled, None),
+            };
+            if state != JobState::Completed {
+                let _ = tokio::fs::remove_file(&partial).await;
+            }
+            log::info!("Job {id} ({operation}) finished: {state:?}");
+            if let Some(job) = manager.lock().jobs.get_mut(&id) {
+                job.finished = Some(Instant::now());
+                let status = &mut job.status;
+                status.state = state;
+                status.error = error;
+                if state == JobState::Completed {
+                    status.bytes_processed = status.total_bytes;
+                    status.percentage = 100.0;
+                }
+            }
+        });
+        Ok(id)
+    }
+
+    /// Status of a job; a finished job is forgotten once its outcome was reported
+    pub fn status(&self, id: JobId) -> Option<JobStatus> {
+        let mut registry = self.lock();
+        registry.evict(self.ttl);
+        let job = registry.jobs.get(&id)?;
+        if job.finished.is_some() {
+            registry.jobs.remove(&id).map(|job| job.status)
+        } else {
+            Some(job.status.clone())
+        }
+    }
+
+    /// Request cancellation of a running job; the job reports `cancelled` once it stopped
+    pub fn cancel(&self, id: JobId) -> Result<JobStatus> {
+        let mut registry = self.lock();
+        let job = registry
+            .jobs
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("unknown job: {id}"))?;
+        match job.cancel.take() {
+            Some(cancel) if job.status.state == JobState::Running => {
+                let _ = cancel.send(());
+                Ok(job.status.clone())
+            }
+            _ => Err(anyhow!("job {id} is not running")),
+        }
+    }
+
+    fn update(&self, id: JobId, apply: impl FnOnce(&mut JobStatus)) {
+        if let Some(job) = self.lock().jobs.get_mut(&id) {
+            apply(&mut job.status);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.registry
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_file_gcm;
+    use tempfile::TempDir;
+
+    async fn wait(jobs: &JobManager, id: JobId) -> JobStatus {
+        loop {
+            let status = jobs.status(id).unwrap();
+            if status.state != JobState::Running {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_jobs_complete_and_fail() {
+        let dir = TempDir::new().unwrap();
+        let plain = dir.path().join("plain.txt");
+        let encrypted = dir.path().join("plain.enc");
+        let decrypted = dir.path().join("plain.out");
+        tokio::fs::write(&plain, vec![7u8; 100_000]).await.unwrap();
+
+        let jobs = JobManager::default();
+        let input = plain.clone();
+        let id = jobs
+            .spawn(
+                "encrypt-file",
+                encrypted.clone(),
+                None,
+                |output, tx| async move {
+                    async_file_gcm::encrypt_file(&input, &output, "pw", tx, PROGRESS_INTERVAL).await
+                },
+            )
+            .unwrap();
+        let status = wait(&jobs, id).await;
+        assert_eq!(status.state, JobState::Completed);
+        assert_eq!(status.total_bytes, 100_000);
+        assert_eq!(status.percentage, 100.0);
+
+        let input = encrypted.clone();
+        let id = jobs
+            .spawn(
+                "decrypt-file",
+                decrypted.clone(),
+                None,
+                |output, tx| async move {
+                    async_file_gcm::decrypt_file(&input, &output, "wrong", tx, PROGRESS_INTERVAL)
+                        .await
+                },
+            )
+            .unwrap();
+        let status = wait(&jobs, id).await;
+        assert_eq!(status.state, JobState::Failed);
+        assert!(status.error.is_some());
+        assert!(!decrypted.exists());
+        assert!(jobs.cancel(id).is_err());
+        assert!(jobs.status(id + 1).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_keeps_existing_output() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("existing");
+        tokio::fs::write(&output, b"existing").await.unwrap();
+
+        let jobs = JobManager::default();
+        let id = jobs
+            .spawn("encrypt-file", output.clone(), None, |partial, _tx| async {
+                tokio::fs::write(partial, b"partial").await?;
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(())
+            })
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(jobs.cancel(id).unwrap().state, JobState::Running);
+        assert_eq!(wait(&jobs, id).await.state, JobState::Cancelled);
+        assert_eq!(tokio::fs::read(&output).await.unwrap(), b"existing");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_finished_jobs_are_evicted() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("out");
+        let done = |partial: PathBuf, _tx| async move { Ok(tokio::fs::write(partial, b"").await?) };
+
+        // Once the outcome was reported
+        let jobs = JobManager::default();
+        let id = jobs
+            .spawn("encrypt-file", output.clone(), None, done)
+            .unwrap();
+        assert_eq!(wait(&jobs, id).await.state, JobState::Completed);
+        assert!(jobs.status(id).is_none());
+        assert!(output.exists());
+
+        // Or when nobody asked before the TTL ran out
+        let jobs = JobManager::with_ttl(DEFAULT_MAX_JOBS, Duration::ZERO);
+        let id = jobs
+            .spawn("encrypt-file", output.clone(), None, done)
+            .unwrap();
+        while jobs.lock().jobs[&id].finished.is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        jobs.spawn("encrypt-file", output, None, done).unwrap();
+        assert!(!jobs.lock().jobs.contains_key(&id));
+    }
+
+    #[tokio::test]
+    async fn test_running_jobs_are_capped() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("out");
+        let slow = |_partial, _tx| async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        };
+
+        let jobs = JobManager::new(1);
+        let id = jobs
+            .spawn("encrypt-file", output.clone(), None, slow)
+            .unwrap();
+        assert_eq!(
+            jobs.spawn("encrypt-file", output.clone(), None, slow),
+            Err(PoolError::QueueFull)
+        );
+        jobs.cancel(id).unwrap();
+        assert_eq!(wait(&jobs, id).await.state, JobState::Cancelled);
+        assert!(jobs.spawn("encrypt-file", output, None, slow).is_ok());
+    }
+}
@@ -0,0 +1,712 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose};
+use openssl::aes::{AesKey, unwrap_key, wrap_key};
+use openssl::bn::BigNum;
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public};
+use openssl::rand::rand_bytes;
+use openssl::sign::{Signer, Verifier};
+use openssl::symm::{Cipher, decrypt_aead, encrypt_aead};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::hash::{self, HashAlgorithm};
+use crate::otp;
+
+/// Shortest HS256 secret allowed (RFC 7518 section 3.2)
+pub const MIN_HMAC_KEY_LEN: usize = 32;
+/// PBES2 iteration count used when encrypting with a password
+pub const PBES2_ITERATIONS: u32 = 210_000;
+/// Largest PBES2 iteration count accepted from a token header
+pub const MAX_PBES2_ITERATIONS: u32 = 10_000_000;
+/// Default clock skew tolerance for exp and nbf, in seconds
+pub const DEFAULT_LEEWAY: u64 = 60;
+
+const A256GCM: &str = "A256GCM";
+const CEK_LEN: usize = 32;
+const GCM_IV_LEN: usize = 12;
+const GCM_TAG_LEN: usize = 16;
+const PBES2_SALT_LEN: usize = 16;
+
+fn b64url(bytes: &[u8]) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn b64url_decode(text: &str) -> Result<Vec<u8>> {
+    Ok(general_purpose::URL_SAFE_NO_PAD.decode(text)?)
+}
+
+/// JWS signature algorithms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JwsAlgorithm {
+    Hs256,
+    Rs256,
+    Es256,
+    EdDsa,
+}
+
+impl JwsAlgorithm {
+    pub const ALL: [JwsAlgorithm; 4] = [
+        JwsAlgorithm::Hs256,
+        JwsAlgorithm::Rs256,
+        JwsAlgorithm::Es256,
+        JwsAlgorithm::EdDsa,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            JwsAlgorithm::Hs256 => "HS256",
+            JwsAlgorithm::Rs256 => "RS256",
+            JwsAlgorithm::Es256 => "ES256",
+            JwsAlgorithm::EdDsa => "EdDSA",
+        }
+    }
+}
+
+impl fmt::Display for JwsAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for JwsAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        JwsAlgorithm::ALL
+            .into_iter()
+            .find(|alg| alg.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("unsupported JWS algorithm: {s}"))
+    }
+}
+
+/// JOSE header members used by this module
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub alg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enc: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cty: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p2s: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p2c: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crit: Option<Vec<String>>,
+}
+
+impl Header {
+    fn encode(&self) -> Result<String> {
+        Ok(b64url(&serde_json::to_vec(self)?))
+    }
+
+    fn decode(text: &str) -> Result<Self> {
+        let header: Header = serde_json::from_slice(&b64url_decode(text)?)?;
+        // No extensions are understood, so any critical one must be rejected (RFC 7515 4.1.11)
+        if header.crit.is_some() {
+            return Err(anyhow!("unsupported critical header parameters"));
+        }
+        Ok(header)
+    }
+}
+
+/// Key for JWS signing or verification. The algorithm follows from the key,
+/// so a token can never pick a weaker or different algorithm than intended.
+pub enum JoseKey {
+    /// HS256 shared secret
+    Secret(Vec<u8>),
+    /// RSA (RS256), P-256 (ES256) or Ed25519 (EdDSA) private key
+    Private(PKey<Private>),
+    /// Public key for verification only
+    Public(PKey<Public>),
+}
+
+fn key_algorithm<T: HasPublic>(key: &PKeyRef<T>) -> Result<JwsAlgorithm> {
+    match key.id() {
+        Id::RSA if key.bits() >= 2048 => Ok(JwsAlgorithm::Rs256),
+        Id::RSA => Err(anyhow!("RS256 requires an RSA key of at least 2048 bits")),
+        Id::EC if key.ec_key()?.group().curve_name() == Some(Nid::X9_62_PRIME256V1) => {
+            Ok(JwsAlgorithm::Es256)
+        }
+        Id::EC => Err(anyhow!("ES256 requires a P-256 key")),
+        Id::ED25519 => Ok(JwsAlgorithm::EdDsa),
+        _ => Err(anyhow!("unsupported key type for JWS")),
+    }
+}
+
+impl JoseKey {
+    pub fn algorithm(&self) -> Result<JwsAlgorithm> {
+        match self {
+            JoseKey::Secret(secret) if secret.len() >= MIN_HMAC_KEY_LEN => Ok(JwsAlgorithm::Hs256),
+            JoseKey::Secret(_) => Err(anyhow!(
+                "HS256 secrets must be at least {MIN_HMAC_KEY_LEN} bytes"
+            )),
+            JoseKey::Private(key) => key_algorithm(key),
+            JoseKey::Public(key) => key_algorithm(key),
+        }
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let algorithm = self.algorithm()?;
+        let key = match self {
+            JoseKey::Secret(secret) => return hash::hmac(HashAlgorithm::Sha256, secret, data),
+            JoseKey::Private(key) => key,
+            JoseKey::Public(_) => return Err(anyhow!("a private key is required to sign")),
+        };
+        match algorithm {
+            JwsAlgorithm::EdDsa => Ok(Signer::new_without_digest(key)?.sign_oneshot_to_vec(data)?),
+            JwsAlgorithm::Es256 => {
+                let der = Signer::new(MessageDigest::sha256(), key)?.sign_oneshot_to_vec(data)?;
+                // JWS uses the fixed-size R || S form instead of DER
+                let sig = EcdsaSig::from_der(&der)?;
+                let mut raw = sig.r().to_vec_padded(32)?;
+                raw.extend(sig.s().to_vec_padded(32)?);
+                Ok(raw)
+            }
+            _ => Ok(Signer::new(MessageDigest::sha256(), key)?.sign_oneshot_to_vec(data)?),
+        }
+    }
+
+    fn verify(&self, data: &[u8], signature: &[u8]) -> Result<bool> {
+        match self {
+            JoseKey::Secret(secret) => {
+                self.algorithm()?;
+                hash::verify_hmac(HashAlgorithm::Sha256, secret, data, signature)
+            }
+            JoseKey::Private(key) => verify_with(key, data, signature),
+            JoseKey::Public(key) => verify_with(key, data, signature),
+        }
+    }
+}
+
+fn verify_with<T: HasPublic>(key: &PKeyRef<T>, data: &[u8], signature: &[u8]) -> Result<bool> {
+    match key_algorithm(key)? {
+        JwsAlgorithm::EdDsa => {
+            Ok(Verifier::new_without_digest(key)?.verify_oneshot(signature, data)?)
+        }
+        JwsAlgorithm::Es256 => {
+            if signature.len() != 64 {
+                return Ok(false);
+            }
+            let sig = EcdsaSig::from_private_components(
+                BigNum::from_slice(&signature[..32])?,
+                BigNum::from_slice(&signature[32..])?,
+            )?;
+            Ok(
+                Verifier::new(MessageDigest::sha256(), key)?
+                    .verify_oneshot(&sig.to_der()?, data)?,
+            )
+        }
+        _ => Ok(Verifier::new(MessageDigest::sha256(), key)?.verify_oneshot(signature, data)?),
+    }
+}
+
+/// Sign `payload` as a JWS in compact serialization
+pub fn sign(payload: &[u8], key: &JoseKey, typ: Option<&str>) -> Result<String> {
+    let header = Header {
+        alg: key.algorithm()?.name().to_string(),
+        typ: typ.map(str::to_string),
+        ..Default::default()
+    };
+    let signing_input = format!("{}.{}", header.encode()?, b64url(payload));
+    let signature = key.sign(signing_input.as_bytes())?;
+    Ok(format!("{signing_input}.{}", b64url(&signature)))
+}
+
+/// Verify a compact JWS and return its header and payload
+pub fn verify(token: &str, key: &JoseKey) -> Result<(Header, Vec<u8>)> {
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    let [header_b64, payload_b64, signature_b64] = parts[..] else {
+        return Err(anyhow!("a JWS must have three parts"));
+    };
+    let header = Header::decode(header_b64)?;
+    let expected = key.algorithm()?;
+    if header.alg != expected.name() {
+        return Err(anyhow!(
+            "unexpected algorithm {}, the key requires {expected}",
+            header.alg
+        ));
+    }
+    let signing_input = &token.trim()[..header_b64.len() + 1 + payload_b64.len()];
+    if !key.verify(signing_input.as_bytes(), &b64url_decode(signature_b64)?)? {
+        return Err(anyhow!("invalid signature"));
+    }
+    Ok((header, b64url_decode(payload_b64)?))
+}
+
+/// Key management for JWE with A256GCM content encryption
+pub enum JweKey {
+    /// `dir`: the 256-bit content encryption key itself
+    Direct(Vec<u8>),
+    /// `PBES2-HS512+A256KW`. `iterations` applies when encrypting; decryption
+    /// reads the count from the header and also accepts the HS256 and HS384 variants.
+    Password { password: String, iterations: u32 },
+}
+
+impl JweKey {
+    pub fn password(password: &str) -> Self {
+        JweKey::Password {
+            password: password.to_string(),
+            iterations: PBES2_ITERATIONS,
+        }
+    }
+}
+
+/// PBES2 variants: (alg, PBKDF2 digest, key wrap key length)
+fn pbes2_params(alg: &str) -> Result<(MessageDigest, usize)> {
+    match alg {
+        "PBES2-HS256+A128KW" => Ok((MessageDigest::sha256(), 16)),
+        "PBES2-HS384+A192KW" => Ok((MessageDigest::sha384(), 24)),
+        "PBES2-HS512+A256KW" => Ok((MessageDigest::sha512(), 32)),
+        _ => Err(anyhow!("unsupported JWE algorithm: {alg}")),
+    }
+}
+
+/// PBKDF2 key for AES key wrap, salted with `alg || 0x00 || p2s` (RFC 7518 4.8.1.1)
+fn pbes2_key(alg: &str, password: &str, p2s: &[u8], iterations: u32) -> Result<Vec<u8>> {
+    let (digest, len) = pbes2_params(alg)?;
+    let mut salt = alg.as_bytes().to_vec();
+    salt.push(0);
+    salt.extend_from_slice(p2s);
+    let mut key = vec![0u8; len];
+    pbkdf2_hmac(
+        password.as_bytes(),
+        &salt,
+        iterations as usize,
+        digest,
+        &mut key,
+    )?;
+    Ok(key)
+}
+
+/// Encrypt `payload` as a compact JWE using A256GCM
+pub fn encrypt(payload: &[u8], key: &JweKey, typ: Option<&str>) -> Result<String> {
+    let mut header = Header {
+        enc: Some(A256GCM.to_string()),
+        typ: typ.map(str::to_string),
+        ..Default::default()
+    };
+    let mut cek = vec![0u8; CEK_LEN];
+    let encrypted_key = match key {
+        JweKey::Direct(direct) => {
+            if direct.len() != CEK_LEN {
+                return Err(anyhow!("dir with A256GCM needs a {CEK_LEN}-byte key"));
+            }
+            header.alg = "dir".to_string();
+            cek.copy_from_slice(direct);
+            Vec::new()
+        }
+        JweKey::Password {
+            password,
+            iterations,
+        } => {
+            let alg = "PBES2-HS512+A256KW";
+            let mut p2s = [0u8; PBES2_SALT_LEN];
+            rand_bytes(&mut p2s)?;
+            let kek = pbes2_key(alg, password, &p2s, *iterations)?;
+            header.alg = alg.to_string();
+            header.p2s = Some(b64url(&p2s));
+            header.p2c = Some(*iterations);
+
+            rand_bytes(&mut cek)?;
+            let wrap = AesKey::new_encrypt(&kek).map_err(|_| anyhow!("invalid key wrap key"))?;
+            let mut wrapped = vec![0u8; CEK_LEN + 8];
+            wrap_key(&wrap, None, &mut wrapped, &cek)
+                .map_err(|_| anyhow!("AES key wrap failed"))?;
+            wrapped
+        }
+    };
+
+    let protected = header.encode()?;
+    let mut iv = [0u8; GCM_IV_LEN];
+    rand_bytes(&mut iv)?;
+    let mut tag = [0u8; GCM_TAG_LEN];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        &cek,
+        Some(&iv),
+        protected.as_bytes(),
+        payload,
+        &mut tag,
+    )?;
+    cek.fill(0);
+
+    Ok(format!(
+        "{protected}.{}.{}.{}.{}",
+        b64url(&encrypted_key),
+        b64url(&iv),
+        b64url(&ciphertext),
+        b64url(&tag)
+    ))
+}
+
+/// Decrypt a compact JWE and return its header and payload
+pub fn decrypt(token: &str, key: &JweKey) -> Result<(Header, Vec<u8>)> {
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    let [protected, encrypted_key, iv, ciphertext, tag] = parts[..] else {
+        return Err(anyhow!("a JWE must have five parts"));
+    };
+    let header = Header::decode(protected)?;
+    if header.enc.as_deref() != Some(A256GCM) {
+        return Err(anyhow!(
+            "unsupported JWE content encryption: {:?}",
+            header.enc
+        ));
+    }
+    let encrypted_key = b64url_decode(encrypted_key)?;
+
+    let cek = match key {
+        JweKey::Direct(direct) => {
+            if header.alg != "dir" || !encrypted_key.is_empty() {
+                return Err(anyhow!("expected a dir JWE, got {}", header.alg));
+            }
+            direct.clone()
+        }
+        JweKey::Password { password, .. } => {
+            let p2s = b64url_decode(
+                header
+                    .p2s
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("PBES2 header is missing p2s"))?,
+            )?;
+            let iterations = header
+                .p2c
+                .ok_or_else(|| anyhow!("PBES2 header is missing p2c"))?;
+            if iterations == 0 || iterations > MAX_PBES2_ITERATIONS {
+                return Err(anyhow!("PBES2 iteration count out of range"));
+            }
+            if encrypted_key.len() != CEK_LEN + 8 {
+                return Err(anyhow!("invalid encrypted key length"));
+            }
+            let kek = pbes2_key(&header.alg, password, &p2s, iterations)?;
+            let unwrap = AesKey::new_decrypt(&kek).map_err(|_| anyhow!("invalid key wrap key"))?;
+            let mut cek = vec![0u8; CEK_LEN];
+            unwrap_key(&unwrap, None, &mut cek, &encrypted_key)
+                .map_err(|_| anyhow!("wrong password or corrupted key"))?;
+            cek
+        }
+    };
+    if cek.len() != CEK_LEN {
+        return Err(anyhow!("dir with A256GCM needs a {CEK_LEN}-byte key"));
+    }
+
+    let payload = decrypt_aead(
+        Cipher::aes_256_gcm(),
+        &cek,
+        Some(&b64url_decode(iv)?),
+        protected.as_bytes(),
+        &b64url_decode(ciphertext)?,
+        &b64url_decode(tag)?,
+    )
+    .map_err(|_| anyhow!("JWE authentication failed"))?;
+    Ok((header, payload))
+}
+
+/// Registered claim checks applied when decoding a JWT
+#[derive(Debug, Clone)]
+pub struct Validation {
+    /// Allowed clock skew for exp and nbf, in seconds
+    pub leeway: u64,
+    /// Required `aud` value, when set
+    pub audience: Option<String>,
+    /// Required `iss` value, when set
+    pub issuer: Option<String>,
+    /// Reject tokens without `exp`
+    pub require_exp: bool,
+    /// Current time override in Unix seconds, mainly for tests
+    pub now: Option<u64>,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self {
+            leeway: DEFAULT_LEEWAY,
+            audience: None,
+            issuer: None,
+            require_exp: false,
+            now: None,
+        }
+    }
+}
+
+fn numeric_claim(claims: &Value, name: &str) -> Result<Option<u64>> {
+    match claims.get(name) {
+        None => Ok(None),
+        Some(value) => value
+            .as_f64()
+            .filter(|v| *v >= 0.0)
+            .map(|v| Some(v as u64))
+            .ok_or_else(|| anyhow!("claim \"{name}\" must be a NumericDate")),
+    }
+}
+
+/// Check exp, nbf, aud and iss against `validation`
+pub fn validate_claims(claims: &Value, validation: &Validation) -> Result<()> {
+    if !claims.is_object() {
+        return Err(anyhow!("JWT claims must be a JSON object"));
+    }
+    let now = match validation.now {
+        Some(now) => now,
+        None => otp::now()?,
+    };
+
+    match numeric_claim(claims, "exp")? {
+        Some(exp) if now >= exp.saturating_add(validation.leeway) => {
+            return Err(anyhow!("token has expired"));
+        }
+        None if validation.require_exp => return Err(anyhow!("token has no \"exp\" claim")),
+        _ => {}
+    }
+    if let Some(nbf) = numeric_claim(claims, "nbf")?
+        && now.saturating_add(validation.leeway) < nbf
+    {
+        return Err(anyhow!("token is not valid yet"));
+    }
+    if let Some(audience) = &validation.audience {
+        let matches = match claims.get("aud") {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !matches {
+            return Err(anyhow!("token audience does not match"));
+        }
+    }
+    if let Some(issuer) = &validation.issuer
+        && claims.get("iss").and_then(Value::as_str) != Some(issuer.as_str())
+    {
+        return Err(anyhow!("token issuer does not match"));
+    }
+    Ok(())
+}
+
+/// Sign a claims object as a JWT
+pub fn encode_jwt(claims: &Value, key: &JoseKey) -> Result<String> {
+    if !claims.is_object() {
+        return Err(anyhow!("JWT claims must be a JSON object"));
+    }
+    sign(&serde_json::to_vec(claims)?, key, Some("JWT"))
+}
+
+/// Verify a signed JWT and validate its claims
+pub fn decode_jwt(token: &str, key: &JoseKey, validation: &Validation) -> Result<Value> {
+    let (_, payload) = verify(token, key)?;
+    let claims: Value = serde_json::from_slice(&payload)?;
+    validate_claims(&claims, validation)?;
+    Ok(claims)
+}
+
+/// Encrypt a claims object as a JWT
+pub fn encrypt_jwt(claims: &Value, key: &JweKey) -> Result<String> {
+    if !claims.is_object() {
+        return Err(anyhow!("JWT claims must be a JSON object"));
+    }
+    encrypt(&serde_json::to_vec(claims)?, key, Some("JWT"))
+}
+
+/// Decrypt an encrypted JWT and validate its claims
+pub fn decrypt_jwt(token: &str, key: &JweKey, validation: &Validation) -> Result<Value> {
+    let (_, payload) = decrypt(token, key)?;
+    let claims: Value = serde_json::from_slice(&payload)?;
+    validate_claims(&claims, validation)?;
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{self, KeySpec};
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_hs256_known_token() {
+        // Cross-checked with `openssl dgst -sha256 -hmac`
+        let token = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                     eyJpc3MiOiJqb2UiLCJleHAiOjEzMDA4MTkzODB9.\
+                     W-tWyajzt9ReqYq40SVKgOihCiZKQUx5UDd7iyyBE1U";
+        let key = JoseKey::Secret(SECRET.to_vec());
+        assert_eq!(
+            sign(br#"{"iss":"joe","exp":1300819380}"#, &key, Some("JWT")).unwrap(),
+            token
+        );
+
+        let validation = Validation {
+            issuer: Some("joe".to_string()),
+            now: Some(1300819300),
+            ..Default::default()
+        };
+        let claims = decode_jwt(token, &key, &validation).unwrap();
+        assert_eq!(claims["iss"], "joe");
+
+        let late = Validation {
+            now: Some(1300819380 + DEFAULT_LEEWAY),
+            ..validation
+        };
+        assert!(decode_jwt(token, &key, &late).is_err());
+        assert!(
+            decode_jwt(
+                token,
+                &JoseKey::Secret(vec![b'x'; 32]),
+                &Validation::default()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rfc8037_ed25519_example() {
+        // RFC 8037 appendix A.4
+        let jwk: keys::Jwk = serde_json::from_value(json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "d": "nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A",
+            "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"
+        }))
+        .unwrap();
+        let key = JoseKey::Private(jwk.to_private_key().unwrap());
+        let token = sign(b"Example of Ed25519 signing", &key, None).unwrap();
+        assert_eq!(
+            token,
+            "eyJhbGciOiJFZERTQSJ9.RXhhbXBsZSBvZiBFZDI1NTE5IHNpZ25pbmc.\
+             hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5BhVsPt9g7sVvpAr_MuM0KAg"
+        );
+        let public = JoseKey::Public(jwk.to_public_key().unwrap());
+        assert_eq!(
+            verify(&token, &public).unwrap().1,
+            b"Example of Ed25519 signing"
+        );
+    }
+
+    #[test]
+    fn test_asymmetric_roundtrips() {
+        let claims = json!({"sub": "svc", "aud": ["api", "web"]});
+        for spec in [
+            KeySpec::Rsa(2048),
+            KeySpec::Ec(keys::Curve::P256),
+            KeySpec::Ed25519,
+        ] {
+            let private = keys::generate(spec).unwrap();
+            let public = keys::load_public(&private.public_key_to_der().unwrap(), None).unwrap();
+            let token = encode_jwt(&claims, &JoseKey::Private(private)).unwrap();
+
+            let validation = Validation {
+                audience: Some("web".to_string()),
+                ..Default::default()
+            };
+            let public = JoseKey::Public(public);
+            assert_eq!(decode_jwt(&token, &public, &validation).unwrap(), claims);
+
+            let mut tampered = token.clone();
+            tampered.insert(token.find('.').unwrap() + 2, 'A');
+            assert!(
+                decode_jwt(&tampered, &public, &validation).is_err(),
+                "{spec}"
+            );
+        }
+        let p384 = keys::generate(KeySpec::Ec(keys::Curve::P384)).unwrap();
+        assert!(encode_jwt(&claims, &JoseKey::Private(p384)).is_err());
+    }
+
+    #[test]
+    fn test_algorithm_confusion_rejected() {
+        let ec = keys::generate(KeySpec::Ec(keys::Curve::P256)).unwrap();
+        let pem = ec.public_key_to_pem().unwrap();
+        // HS256 token keyed with the public key bytes must not verify against the EC key
+        let forged = sign(b"{}", &JoseKey::Secret(pem.clone()), None).unwrap();
+        let public = JoseKey::Public(keys::load_public(&pem, None).unwrap());
+        assert!(verify(&forged, &public).is_err());
+
+        let token = sign(b"{}", &JoseKey::Secret(SECRET.to_vec()), None).unwrap();
+        let (header, rest) = token.split_once('.').unwrap();
+        assert!(header.starts_with("eyJhbGciOiJIUzI1NiJ9"));
+        let none = format!("{}.{rest}", b64url(br#"{"alg":"none"}"#));
+        assert!(verify(&none, &JoseKey::Secret(SECRET.to_vec())).is_err());
+
+        assert!(sign(b"{}", &JoseKey::Secret(b"short".to_vec()), None).is_err());
+    }
+
+    #[test]
+    fn test_claim_validation() {
+        let now = 1_700_000_000;
+        let at = |now| Validation {
+            now: Some(now),
+            leeway: 10,
+            ..Default::default()
+        };
+        let claims = json!({"exp": now + 100, "nbf": now - 100, "iss": "me", "aud": "you"});
+        assert!(validate_claims(&claims, &at(now)).is_ok());
+        assert!(validate_claims(&claims, &at(now + 105)).is_ok());
+        assert!(validate_claims(&claims, &at(now + 110)).is_err());
+        assert!(validate_claims(&claims, &at(now - 105)).is_ok());
+        assert!(validate_claims(&claims, &at(now - 111)).is_err());
+
+        let strict = Validation {
+            issuer: Some("me".to_string()),
+            audience: Some("you".to_string()),
+            ..at(now)
+        };
+        assert!(validate_claims(&claims, &strict).is_ok());
+        let wrong_aud = Validation {
+            audience: Some("them".to_string()),
+            ..strict.clone()
+        };
+        assert!(validate_claims(&claims, &wrong_aud).is_err());
+        let wrong_iss = Validation {
+            issuer: Some("other".to_string()),
+            ..strict
+        };
+        assert!(validate_claims(&claims, &wrong_iss).is_err());
+
+        let require = Validation {
+            require_exp: true,
+            ..at(now)
+        };
+        assert!(validate_claims(&json!({}), &require).is_err());
+        assert!(validate_claims(&json!({"exp": "soon"}), &at(now)).is_err());
+    }
+
+    #[test]
+    fn test_jwe_dir_roundtrip() {
+        let key = JweKey::Direct(SECRET.to_vec());
+        let claims = json!({"sub": "alice"});
+        let token = encrypt_jwt(&claims, &key).unwrap();
+        assert_eq!(token.split('.').count(), 5);
+        assert_eq!(
+            decrypt_jwt(&token, &key, &Validation::default()).unwrap(),
+            claims
+        );
+
+        let other = JweKey::Direct(vec![7u8; 32]);
+        assert!(decrypt(&token, &other).is_err());
+        assert!(encrypt(b"x", &JweKey::Direct(vec![0u8; 16]), None).is_err());
+    }
+
+    #[test]
+    fn test_jwe_pbes2_roundtrip() {
+        let key = JweKey::Password {
+            password: "correct horse".to_string(),
+            iterations: 1000,
+        };
+        let token = encrypt(b"secret payload", &key, None).unwrap();
+        let (header, payload) = decrypt(&token, &JweKey::password("correct horse")).unwrap();
+        assert_eq!(header.alg, "PBES2-HS512+A256KW");
+        assert_eq!(header.p2c, Some(1000));
+        assert_eq!(payload, b"secret payload");
+
+        assert!(decrypt(&token, &JweKey::password("wrong")).is_err());
+        assert!(decrypt(&token, &JweKey::Direct(SECRET.to_vec())).is_err());
+    }
+}
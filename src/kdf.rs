@@ -0,0 +1,909 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use openssl::hash::MessageDigest;
+use openssl::pkcs5;
+use openssl::pkey::Id;
+use openssl::pkey_ctx::PkeyCtx;
+
+/// Magic bytes that start every ciphertext carrying a KDF header.
+pub const HEADER_MAGIC: &[u8; 4] = b"ENZK";
+/// magic(4) || algorithm(1) || params length(1)
+pub const HEADER_PREFIX_LEN: usize = HEADER_MAGIC.len() + 2;
+
+// scrypt parameters (adjust for your threat model)
+const SCRYPT_N: u64 = 16384;
+const SCRYPT_R: u64 = 8;
+const SCRYPT_P: u64 = 1;
+const SCRYPT_MAXMEM: u64 = 512 * 1024 * 1024; // 512 MB Max Memory
+pub const SCRYPT_MAX_N: u64 = 1 << 20;
+pub const SCRYPT_MAX_R: u64 = 32;
+pub const SCRYPT_MAX_P: u64 = 16;
+
+// PBKDF2 iteration counts (OWASP 2023 recommendations)
+const PBKDF2_SHA256_ITERATIONS: u32 = 600_000;
+const PBKDF2_SHA512_ITERATIONS: u32 = 210_000;
+pub const PBKDF2_MAX_ITERATIONS: u32 = 10_000_000;
+
+// Argon2id parameters (RFC 9106 second recommended option)
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_LANES: u32 = 4;
+pub const ARGON2_MAX_MEMORY_KIB: u32 = 512 * 1024; // same ceiling as scrypt
+pub const ARGON2_MAX_ITERATIONS: u32 = 32;
+pub const ARGON2_MAX_LANES: u32 = 16;
+
+const HKDF_INFO: &[u8] = b"enzo-crypto";
+
+// Ceiling on header parameters of decrypted data, below the caps above
+const HEADER_MAX_MEMORY_KIB: u64 = 128 * 1024;
+const HEADER_MAX_ITERATIONS: u32 = 2_000_000;
+const HEADER_MAX_PASSES: u32 = 8;
+
+/// Identifier of a key derivation function, stored in ciphertext headers.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KdfAlgorithm {
+    Scrypt = 1,
+    Pbkdf2Sha256 = 2,
+    Pbkdf2Sha512 = 3,
+    Argon2id = 4,
+    HkdfSha256 = 5,
+}
+
+impl KdfAlgorithm {
+    pub const ALL: [KdfAlgorithm; 5] = [
+        KdfAlgorithm::Scrypt,
+        KdfAlgorithm::Pbkdf2Sha256,
+        KdfAlgorithm::Pbkdf2Sha512,
+        KdfAlgorithm::Argon2id,
+        KdfAlgorithm::HkdfSha256,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KdfAlgorithm::Scrypt => "scrypt",
+            KdfAlgorithm::Pbkdf2Sha256 => "pbkdf2-sha256",
+            KdfAlgorithm::Pbkdf2Sha512 => "pbkdf2-sha512",
+            KdfAlgorithm::Argon2id => "argon2id",
+            KdfAlgorithm::HkdfSha256 => "hkdf-sha256",
+        }
+    }
+
+    /// Whether the algorithm has a work factor, so it may derive keys from passphrases
+    pub fn is_password_kdf(&self) -> bool {
+        *self != KdfAlgorithm::HkdfSha256
+    }
+}
+
+impl fmt::Display for KdfAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for KdfAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        KdfAlgorithm::ALL
+            .into_iter()
+            .find(|alg| alg.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("unknown KDF: {s}"))
+    }
+}
+
+impl TryFrom<u8> for KdfAlgorithm {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        KdfAlgorithm::ALL
+            .into_iter()
+            .find(|alg| *alg as u8 == value)
+            .ok_or_else(|| anyhow!("unknown KDF identifier: {value}"))
+    }
+}
+
+/// A key derivation function turning a secret and a salt into key material.
+pub trait Kdf: Send + Sync {
+    fn algorithm(&self) -> KdfAlgorithm;
+
+    /// Fill `key` with material derived from `secret` and `salt`.
+    fn derive(&self, secret: &[u8], salt: &[u8], key: &mut [u8]) -> Result<()>;
+
+    /// Serialized parameters, recorded in the ciphertext header.
+    fn params(&self) -> Result<Vec<u8>>;
+}
+
+/// scrypt (RFC 7914)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scrypt {
+    pub n: u64,
+    pub r: u64,
+    pub p: u64,
+    pub max_mem: u64,
+}
+
+impl Default for Scrypt {
+    fn default() -> Self {
+        Self {
+            n: SCRYPT_N,
+            r: SCRYPT_R,
+            p: SCRYPT_P,
+            max_mem: SCRYPT_MAXMEM,
+        }
+    }
+}
+
+impl Scrypt {
+    /// Reject parameters outside the ranges this crate writes and reads
+    pub fn validate(&self) -> Result<()> {
+        if self.n < 2 || !self.n.is_power_of_two() {
+            return Err(anyhow!("scrypt cost must be a power of two greater than 1"));
+        }
+        if self.n > SCRYPT_MAX_N {
+            return Err(anyhow!("scrypt cost exceeds the allowed maximum"));
+        }
+        if !(1..=SCRYPT_MAX_R).contains(&self.r) {
+            return Err(anyhow!(
+                "scrypt block size must be between 1 and {SCRYPT_MAX_R}"
+            ));
+        }
+        if !(1..=SCRYPT_MAX_P).contains(&self.p) {
+            return Err(anyhow!(
+                "scrypt parallelism must be between 1 and {SCRYPT_MAX_P}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Kdf for Scrypt {
+    fn algorithm(&self) -> KdfAlgorithm {
+        KdfAlgorithm::Scrypt
+    }
+
+    fn derive(&self, secret: &[u8], salt: &[u8], key: &mut [u8]) -> Result<()> {
+        pkcs5::scrypt(secret, salt, self.n, self.r, self.p, self.max_mem, key)?;
+        Ok(())
+    }
+
+    fn params(&self) -> Result<Vec<u8>> {
+        let r = u32::try_from(self.r).map_err(|_| anyhow!("scrypt block size is too large"))?;
+        let p = u32::try_from(self.p).map_err(|_| anyhow!("scrypt parallelism is too large"))?;
+        let mut params = Vec::with_capacity(16);
+        params.extend_from_slice(&self.n.to_be_bytes());
+        params.extend_from_slice(&r.to_be_bytes());
+        params.extend_from_slice(&p.to_be_bytes());
+        Ok(params)
+    }
+}
+
+/// PBKDF2-HMAC with SHA-256 or SHA-512 (RFC 8018)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pbkdf2 {
+    pub sha512: bool,
+    pub iterations: u32,
+}
+
+impl Pbkdf2 {
+    pub fn sha256() -> Self {
+        Self {
+            sha512: false,
+            iterations: PBKDF2_SHA256_ITERATIONS,
+        }
+    }
+
+    pub fn sha512() -> Self {
+        Self {
+            sha512: true,
+            iterations: PBKDF2_SHA512_ITERATIONS,
+        }
+    }
+
+    /// Reject parameters outside the ranges this crate writes and reads
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=PBKDF2_MAX_ITERATIONS).contains(&self.iterations) {
+            return Err(anyhow!(
+                "PBKDF2 iterations must be between 1 and {PBKDF2_MAX_ITERATIONS}"
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Kdf for Pbkdf2 {
+    fn algorithm(&self) -> KdfAlgorithm {
+        if self.sha512 {
+            KdfAlgorithm::Pbkdf2Sha512
+        } else {
+            KdfAlgorithm::Pbkdf2Sha256
+        }
+    }
+
+    fn derive(&self, secret: &[u8], salt: &[u8], key: &mut [u8]) -> Result<()> {
+        let digest = if self.sha512 {
+            MessageDigest::sha512()
+        } else {
+            MessageDigest::sha256()
+        };
+        pkcs5::pbkdf2_hmac(secret, salt, self.iterations as usize, digest, key)?;
+        Ok(())
+    }
+
+    fn params(&self) -> Result<Vec<u8>> {
+        Ok(self.iterations.to_be_bytes().to_vec())
+    }
+}
+
+/// Argon2id (RFC 9106)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2id {
+    pub iterations: u32,
+    pub memory_kib: u32,
+    pub lanes: u32,
+}
+
+impl Default for Argon2id {
+    fn default() -> Self {
+        Self {
+            iterations: ARGON2_ITERATIONS,
+            memory_kib: ARGON2_MEMORY_KIB,
+            lanes: ARGON2_LANES,
+        }
+    }
+}
+
+impl Argon2id {
+    /// Reject parameters outside the ranges this crate writes and reads
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=ARGON2_MAX_ITERATIONS).contains(&self.iterations) {
+            return Err(anyhow!(
+                "Argon2id iterations must be between 1 and {ARGON2_MAX_ITERATIONS}"
+            ));
+        }
+        if !(1..=ARGON2_MAX_LANES).contains(&self.lanes) {
+            return Err(anyhow!(
+                "Argon2id parallelism must be between 1 and {ARGON2_MAX_LANES}"
+            ));
+        }
+        if self.memory_kib > ARGON2_MAX_MEMORY_KIB {
+            return Err(anyhow!("Argon2id memory cost exceeds the allowed maximum"));
+        }
+        if self.memory_kib < 8 * self.lanes {
+            return Err(anyhow!("Argon2id needs at least 8 KiB of memory per lane"));
+        }
+        Ok(())
+    }
+}
+
+impl Kdf for Argon2id {
+    fn algorithm(&self) -> KdfAlgorithm {
+        KdfAlgorithm::Argon2id
+    }
+
+    fn derive(&self, secret: &[u8], salt: &[u8], key: &mut [u8]) -> Result<()> {
+        let params = argon2::Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.lanes,
+            Some(key.len()),
+        )
+        .map_err(|e| anyhow!("invalid Argon2id parameters: {e}"))?;
+        argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password_into(secret, salt, key)
+            .map_err(|e| anyhow!("Argon2id derivation failed: {e}"))
+    }
+
+    fn params(&self) -> Result<Vec<u8>> {
+        let mut params = Vec::with_capacity(12);
+        params.extend_from_slice(&self.iterations.to_be_bytes());
+        params.extend_from_slice(&self.memory_kib.to_be_bytes());
+        params.extend_from_slice(&self.lanes.to_be_bytes());
+        Ok(params)
+    }
+}
+
+/// HKDF-SHA256 (RFC 5869). Only suitable for secrets that already have
+/// full entropy (e.g. random keys), never for human passphrases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HkdfSha256 {
+    pub info: Vec<u8>,
+}
+
+impl Default for HkdfSha256 {
+    fn default() -> Self {
+        Self {
+            info: HKDF_INFO.to_vec(),
+        }
+    }
+}
+
+impl Kdf for HkdfSha256 {
+    fn algorithm(&self) -> KdfAlgorithm {
+        KdfAlgorithm::HkdfSha256
+    }
+
+    fn derive(&self, secret: &[u8], salt: &[u8], key: &mut [u8]) -> Result<()> {
+        let mut ctx = PkeyCtx::new_id(Id::HKDF)?;
+        ctx.derive_init()?;
+        ctx.set_hkdf_md(openssl::md::Md::sha256())?;
+        ctx.set_hkdf_key(secret)?;
+        ctx.set_hkdf_salt(salt)?;
+        ctx.add_hkdf_info(&self.info)?;
+        ctx.derive(Some(key))?;
+        Ok(())
+    }
+
+    fn params(&self) -> Result<Vec<u8>> {
+        Ok(self.info.clone())
+    }
+}
+
+/// Default-parameter implementation of the given algorithm
+pub fn default_for(algorithm: KdfAlgorithm) -> Box<dyn Kdf> {
+    match algorithm {
+        KdfAlgorithm::Scrypt => Box::new(Scrypt::default()),
+        KdfAlgorithm::Pbkdf2Sha256 => Box::new(Pbkdf2::sha256()),
+        KdfAlgorithm::Pbkdf2Sha512 => Box::new(Pbkdf2::sha512()),
+        KdfAlgorithm::Argon2id => Box::new(Argon2id::default()),
+        KdfAlgorithm::HkdfSha256 => Box::new(HkdfSha256::default()),
+    }
+}
+
+/// Default-parameter implementation looked up by name, e.g. `"argon2id"`
+pub fn from_name(name: &str) -> Result<Box<dyn Kdf>> {
+    Ok(default_for(name.parse()?))
+}
+
+/// Reject KDFs without a work factor for keys derived from passphrases
+pub fn ensure_password_kdf(algorithm: KdfAlgorithm) -> Result<()> {
+    if algorithm.is_password_kdf() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{algorithm} has no work factor and cannot derive keys from passphrases"
+        ))
+    }
+}
+
+/// [`from_name`] limited to KDFs that may derive keys from passphrases
+pub fn password_kdf(name: &str) -> Result<Box<dyn Kdf>> {
+    let algorithm = name.parse()?;
+    ensure_password_kdf(algorithm)?;
+    Ok(default_for(algorithm))
+}
+
+/// Cost parameters overriding an algorithm's defaults; unset fields keep the default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KdfOptions {
+    /// PBKDF2 iterations or Argon2id passes
+    pub iterations: Option<u32>,
+    /// Argon2id memory in KiB
+    pub memory_kib: Option<u32>,
+    /// Argon2id lanes or scrypt p
+    pub parallelism: Option<u32>,
+    /// scrypt N
+    pub cost: Option<u64>,
+}
+
+impl KdfOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == KdfOptions::default()
+    }
+}
+
+/// Passphrase KDF `algorithm` with the parameters set in `options`, rejecting the
+/// ones it does not use
+pub fn configure(algorithm: KdfAlgorithm, options: &KdfOptions) -> Result<Box<dyn Kdf>> {
+    ensure_password_kdf(algorithm)?;
+    let used: &[&str] = match algorithm {
+        KdfAlgorithm::Scrypt => &["parallelism", "cost"],
+        KdfAlgorithm::Pbkdf2Sha256 | KdfAlgorithm::Pbkdf2Sha512 => &["iterations"],
+        KdfAlgorithm::Argon2id => &["iterations", "memory", "parallelism"],
+        KdfAlgorithm::HkdfSha256 => unreachable!("rejected above"),
+    };
+    let set = [
+        ("iterations", options.iterations.is_some()),
+        ("memory", options.memory_kib.is_some()),
+        ("parallelism", options.parallelism.is_some()),
+        ("cost", options.cost.is_some()),
+    ];
+    if let Some((name, _)) = set
+        .into_iter()
+        .find(|(name, set)| *set && !used.contains(name))
+    {
+        return Err(anyhow!("{name} does not apply to {algorithm}"));
+    }
+
+    let kdf: Box<dyn Kdf> = match algorithm {
+        KdfAlgorithm::Scrypt => {
+            let mut scrypt = Scrypt::default();
+            if let Some(n) = options.cost {
+                scrypt.n = n;
+            }
+            if let Some(p) = options.parallelism {
+                scrypt.p = u64::from(p);
+            }
+            scrypt.validate()?;
+            Box::new(scrypt)
+        }
+        KdfAlgorithm::Pbkdf2Sha256 | KdfAlgorithm::Pbkdf2Sha512 => {
+            let mut pbkdf2 = if algorithm == KdfAlgorithm::Pbkdf2Sha512 {
+                Pbkdf2::sha512()
+            } else {
+                Pbkdf2::sha256()
+            };
+            if let Some(iterations) = options.iterations {
+                pbkdf2.iterations = iterations;
+            }
+            pbkdf2.validate()?;
+            Box::new(pbkdf2)
+        }
+        KdfAlgorithm::Argon2id => {
+            let defaults = Argon2id::default();
+            let argon2 = Argon2id {
+                iterations: options.iterations.unwrap_or(defaults.iterations),
+                memory_kib: options.memory_kib.unwrap_or(defaults.memory_kib),
+                lanes: options.parallelism.unwrap_or(defaults.lanes),
+            };
+            argon2.validate()?;
+            Box::new(argon2)
+        }
+        KdfAlgorithm::HkdfSha256 => unreachable!("rejected above"),
+    };
+    Ok(kdf)
+}
+
+/// Ceiling on the cost of KDF parameters read from a ciphertext header, so that
+/// decrypting untrusted data cannot ask for the whole range encryption accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfLimits {
+    /// scrypt or Argon2id memory in KiB
+    pub memory_kib: u64,
+    /// PBKDF2 iterations
+    pub iterations: u32,
+    /// Argon2id passes or scrypt p, which run one after the other
+    pub passes: u32,
+}
+
+impl Default for KdfLimits {
+    fn default() -> Self {
+        Self {
+            memory_kib: HEADER_MAX_MEMORY_KIB,
+            iterations: HEADER_MAX_ITERATIONS,
+            passes: HEADER_MAX_PASSES,
+        }
+    }
+}
+
+impl KdfLimits {
+    /// No ceiling beyond the ranges [`from_params`] accepts
+    pub const MAX: KdfLimits = KdfLimits {
+        memory_kib: SCRYPT_MAXMEM / 1024,
+        iterations: PBKDF2_MAX_ITERATIONS,
+        passes: ARGON2_MAX_ITERATIONS,
+    };
+
+    fn check(
+        &self,
+        algorithm: KdfAlgorithm,
+        memory_kib: u64,
+        iterations: u32,
+        passes: u64,
+    ) -> Result<()> {
+        if memory_kib > self.memory_kib {
+            return Err(anyhow!(
+                "{algorithm} header asks for {memory_kib} KiB of memory, more than the {} KiB allowed",
+                self.memory_kib
+            ));
+        }
+        if iterations > self.iterations {
+            return Err(anyhow!(
+                "{algorithm} header asks for {iterations} iterations, more than the {} allowed",
+                self.iterations
+            ));
+        }
+        if passes > u64::from(self.passes) {
+            return Err(anyhow!(
+                "{algorithm} header asks for {passes} passes, more than the {} allowed",
+                self.passes
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Rebuild a KDF from the algorithm and parameters stored in a header
+pub fn from_params(algorithm: KdfAlgorithm, params: &[u8]) -> Result<Box<dyn Kdf>> {
+    from_params_with_limits(algorithm, params, &KdfLimits::MAX)
+}
+
+/// [`from_params`] rejecting parameters that cost more than `limits`
+pub fn from_params_with_limits(
+    algorithm: KdfAlgorithm,
+    params: &[u8],
+    limits: &KdfLimits,
+) -> Result<Box<dyn Kdf>> {
+    let expected = match algorithm {
+        KdfAlgorithm::Scrypt => Some(16),
+        KdfAlgorithm::Pbkdf2Sha256 | KdfAlgorithm::Pbkdf2Sha512 => Some(4),
+        KdfAlgorithm::Argon2id => Some(12),
+        KdfAlgorithm::HkdfSha256 => None,
+    };
+    if let Some(len) = expected
+        && params.len() != len
+    {
+        return Err(anyhow!("invalid {algorithm} parameters length"));
+    }
+
+    let kdf: Box<dyn Kdf> = match algorithm {
+        KdfAlgorithm::Scrypt => {
+            let mut n = [0u8; 8];
+            n.copy_from_slice(&params[..8]);
+            let scrypt = Scrypt {
+                n: u64::from_be_bytes(n),
+                r: be_u32(&params[8..12]) as u64,
+                p: be_u32(&params[12..16]) as u64,
+                max_mem: SCRYPT_MAXMEM,
+            };
+            scrypt.validate()?;
+            // 128 * r bytes per block, N blocks
+            limits.check(algorithm, scrypt.n * scrypt.r / 8, 0, scrypt.p)?;
+            Box::new(scrypt)
+        }
+        KdfAlgorithm::Pbkdf2Sha256 | KdfAlgorithm::Pbkdf2Sha512 => {
+            let pbkdf2 = Pbkdf2 {
+                sha512: algorithm == KdfAlgorithm::Pbkdf2Sha512,
+                iterations: be_u32(params),
+            };
+            pbkdf2.validate()?;
+            limits.check(algorithm, 0, pbkdf2.iterations, 0)?;
+            Box::new(pbkdf2)
+        }
+        KdfAlgorithm::Argon2id => {
+            let argon2 = Argon2id {
+                iterations: be_u32(&params[..4]),
+                memory_kib: be_u32(&params[4..8]),
+                lanes: be_u32(&params[8..12]),
+            };
+            argon2.validate()?;
+            limits.check(
+                algorithm,
+                u64::from(argon2.memory_kib),
+                0,
+                u64::from(argon2.iterations),
+            )?;
+            Box::new(argon2)
+        }
+        KdfAlgorithm::HkdfSha256 => Box::new(HkdfSha256 {
+            info: params.to_vec(),
+        }),
+    };
+    Ok(kdf)
+}
+
+/// Serialize a header: magic || algorithm || params length || params.
+/// Fails for parameters [`from_params`] would not read back.
+pub fn encode_header(kdf: &dyn Kdf) -> Result<Vec<u8>> {
+    let params = kdf.params()?;
+    let len = u8::try_from(params.len())
+        .map_err(|_| anyhow!("{} parameters are too long", kdf.algorithm()))?;
+    from_params(kdf.algorithm(), &params)?;
+    let mut header = Vec::with_capacity(HEADER_PREFIX_LEN + params.len());
+    header.extend_from_slice(HEADER_MAGIC);
+    header.push(kdf.algorithm() as u8);
+    header.push(len);
+    header.extend_from_slice(&params);
+    Ok(header)
+}
+
+/// Length of the parameters that follow a header prefix, or `None` when the
+/// data does not start with a header (legacy format).
+pub fn params_len(prefix: &[u8]) -> Option<usize> {
+    if prefix.len() >= HEADER_PREFIX_LEN && prefix.starts_with(HEADER_MAGIC) {
+        Some(prefix[HEADER_PREFIX_LEN - 1] as usize)
+    } else {
+        None
+    }
+}
+
+/// Parse a header at the start of `data`.
+/// Returns the KDF and the total header length, or `None` for legacy data without a header.
+pub fn decode_header(data: &[u8]) -> Result<Option<(Box<dyn Kdf>, usize)>> {
+    decode_header_with_limits(data, &KdfLimits::MAX)
+}
+
+/// [`decode_header`] rejecting parameters that cost more than `limits`
+pub fn decode_header_with_limits(
+    data: &[u8],
+    limits: &KdfLimits,
+) -> Result<Option<(Box<dyn Kdf>, usize)>> {
+    let Some(len) = params_len(data) else {
+        return Ok(None);
+    };
+    let end = HEADER_PREFIX_LEN + len;
+    if data.len() < end {
+        return Err(anyhow!("truncated KDF header"));
+    }
+    let algorithm = KdfAlgorithm::try_from(data[HEADER_MAGIC.len()])?;
+    let kdf = from_params_with_limits(algorithm, &data[HEADER_PREFIX_LEN..end], limits)?;
+    Ok(Some((kdf, end)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn test_scrypt_rfc7914_vector() {
+        let kdf = Scrypt {
+            n: 16,
+            r: 1,
+            p: 1,
+            max_mem: SCRYPT_MAXMEM,
+        };
+        let mut key = [0u8; 64];
+        kdf.derive(b"", b"", &mut key).unwrap();
+        assert_eq!(
+            hex(&key),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+             fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        );
+    }
+
+    #[test]
+    fn test_pbkdf2_sha256_vector() {
+        let kdf = Pbkdf2 {
+            sha512: false,
+            iterations: 1,
+        };
+        let mut key = [0u8; 32];
+        kdf.derive(b"password", b"salt", &mut key).unwrap();
+        assert_eq!(
+            hex(&key),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+    }
+
+    #[test]
+    fn test_hkdf_sha256_rfc5869_vector() {
+        let kdf = HkdfSha256 {
+            info: (0xf0..=0xf9).collect(),
+        };
+        let ikm = [0x0bu8; 22];
+        let salt: Vec<u8> = (0x00..=0x0c).collect();
+        let mut okm = [0u8; 42];
+        kdf.derive(&ikm, &salt, &mut okm).unwrap();
+        assert_eq!(
+            hex(&okm),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+    }
+
+    #[test]
+    fn test_argon2id_deterministic() {
+        let kdf = Argon2id {
+            iterations: 1,
+            memory_kib: 64,
+            lanes: 1,
+        };
+        let mut k1 = [0u8; 32];
+        let mut k2 = [0u8; 32];
+        kdf.derive(b"password", b"somesaltsomesalt", &mut k1)
+            .unwrap();
+        kdf.derive(b"password", b"somesaltsomesalt", &mut k2)
+            .unwrap();
+        assert_eq!(k1, k2);
+        assert_ne!(k1, [0u8; 32]);
+    }
+
+    #[test]
+    fn test_header_roundtrip_all_algorithms() {
+        for algorithm in KdfAlgorithm::ALL {
+            let kdf = default_for(algorithm);
+            let mut data = encode_header(kdf.as_ref()).unwrap();
+            let header_len = data.len();
+            data.extend_from_slice(b"payload");
+
+            let (decoded, len) = decode_header(&data).unwrap().unwrap();
+            assert_eq!(len, header_len);
+            assert_eq!(decoded.algorithm(), algorithm);
+            assert_eq!(decoded.params().unwrap(), kdf.params().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_legacy_data_has_no_header() {
+        assert!(decode_header(&[0x11u8; 64]).unwrap().is_none());
+        assert!(decode_header(b"ENZ").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_headers_rejected() {
+        // unknown algorithm
+        assert!(decode_header(b"ENZK\x63\x00").is_err());
+        // truncated params
+        assert!(decode_header(b"ENZK\x02\x04\x00").is_err());
+        // wrong params length
+        assert!(decode_header(b"ENZK\x02\x02\x00\x01").is_err());
+        // excessive argon2 memory
+        let mut data = b"ENZK\x04\x0c".to_vec();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&u32::MAX.to_be_bytes());
+        data.extend_from_slice(&1u32.to_be_bytes());
+        assert!(decode_header(&data).is_err());
+        // excessive PBKDF2 iterations
+        let mut data = b"ENZK\x02\x04".to_vec();
+        data.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode_header(&data).is_err());
+        // excessive Argon2id passes and lanes
+        for (iterations, lanes) in [(u32::MAX, 1), (1, u32::MAX)] {
+            let mut data = b"ENZK\x04\x0c".to_vec();
+            data.extend_from_slice(&iterations.to_be_bytes());
+            data.extend_from_slice(&1024u32.to_be_bytes());
+            data.extend_from_slice(&lanes.to_be_bytes());
+            assert!(decode_header(&data).is_err());
+        }
+        // excessive scrypt N, r and p
+        for (n, r, p) in [
+            (1u64 << 40, 8u32, 1u32),
+            (1 << 10, u32::MAX, 1),
+            (1 << 10, 8, u32::MAX),
+        ] {
+            let mut data = b"ENZK\x01\x10".to_vec();
+            data.extend_from_slice(&n.to_be_bytes());
+            data.extend_from_slice(&r.to_be_bytes());
+            data.extend_from_slice(&p.to_be_bytes());
+            assert!(decode_header(&data).is_err());
+        }
+    }
+
+    #[test]
+    fn test_header_limits() {
+        let limits = KdfLimits::default();
+        for algorithm in KdfAlgorithm::ALL {
+            let header = encode_header(default_for(algorithm).as_ref()).unwrap();
+            assert!(
+                decode_header_with_limits(&header, &limits).is_ok(),
+                "{algorithm}"
+            );
+        }
+        for kdf in [
+            Box::new(Scrypt {
+                n: 1 << 18,
+                ..Scrypt::default()
+            }) as Box<dyn Kdf>,
+            Box::new(Scrypt {
+                p: SCRYPT_MAX_P,
+                ..Scrypt::default()
+            }),
+            Box::new(Pbkdf2 {
+                sha512: true,
+                iterations: PBKDF2_MAX_ITERATIONS,
+            }),
+            Box::new(Argon2id {
+                memory_kib: ARGON2_MAX_MEMORY_KIB,
+                ..Argon2id::default()
+            }),
+            Box::new(Argon2id {
+                iterations: ARGON2_MAX_ITERATIONS,
+                ..Argon2id::default()
+            }),
+        ] {
+            let header = encode_header(kdf.as_ref()).unwrap();
+            assert!(decode_header(&header).is_ok());
+            assert!(decode_header_with_limits(&header, &limits).is_err());
+        }
+    }
+
+    #[test]
+    fn test_encode_header_rejects_unreadable_params() {
+        let scrypt = Scrypt {
+            r: u64::from(u32::MAX) + 1,
+            ..Scrypt::default()
+        };
+        assert!(scrypt.params().is_err());
+        assert!(encode_header(&scrypt).is_err());
+        let scrypt = Scrypt {
+            n: SCRYPT_MAX_N * 2,
+            ..Scrypt::default()
+        };
+        assert!(encode_header(&scrypt).is_err());
+        let pbkdf2 = Pbkdf2 {
+            sha512: false,
+            iterations: PBKDF2_MAX_ITERATIONS + 1,
+        };
+        assert!(encode_header(&pbkdf2).is_err());
+        let hkdf = HkdfSha256 { info: vec![0; 256] };
+        assert!(encode_header(&hkdf).is_err());
+    }
+
+    #[test]
+    fn test_algorithm_names() {
+        for algorithm in KdfAlgorithm::ALL {
+            assert_eq!(algorithm.name().parse::<KdfAlgorithm>().unwrap(), algorithm);
+        }
+        assert!("md5".parse::<KdfAlgorithm>().is_err());
+        assert_eq!(
+            from_name("ARGON2ID").unwrap().algorithm(),
+            KdfAlgorithm::Argon2id
+        );
+        assert!(password_kdf("hkdf-sha256").is_err());
+        for algorithm in KdfAlgorithm::ALL
+            .into_iter()
+            .filter(KdfAlgorithm::is_password_kdf)
+        {
+            assert_eq!(
+                password_kdf(algorithm.name()).unwrap().algorithm(),
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn test_configure_options() {
+        let options = KdfOptions {
+            iterations: Some(1000),
+            ..KdfOptions::default()
+        };
+        let kdf = configure(KdfAlgorithm::Pbkdf2Sha512, &options).unwrap();
+        assert_eq!(kdf.params().unwrap(), 1000u32.to_be_bytes());
+        assert!(configure(KdfAlgorithm::Scrypt, &options).is_err());
+
+        let options = KdfOptions {
+            cost: Some(1 << 10),
+            parallelism: Some(2),
+            ..KdfOptions::default()
+        };
+        let kdf = configure(KdfAlgorithm::Scrypt, &options).unwrap();
+        let decoded = from_params(KdfAlgorithm::Scrypt, &kdf.params().unwrap()).unwrap();
+        assert_eq!(decoded.params().unwrap(), kdf.params().unwrap());
+        assert_eq!(&kdf.params().unwrap()[..8], (1u64 << 10).to_be_bytes());
+
+        for invalid in [
+            KdfOptions {
+                cost: Some(1000),
+                ..KdfOptions::default()
+            },
+            KdfOptions {
+                iterations: Some(0),
+                ..KdfOptions::default()
+            },
+            KdfOptions {
+                memory_kib: Some(u32::MAX),
+                ..KdfOptions::default()
+            },
+            KdfOptions {
+                iterations: Some(u32::MAX),
+                ..KdfOptions::default()
+            },
+            KdfOptions {
+                parallelism: Some(u32::MAX),
+                ..KdfOptions::default()
+            },
+            KdfOptions {
+                cost: Some(1 << 40),
+                ..KdfOptions::default()
+            },
+        ] {
+            assert!(configure(KdfAlgorithm::Scrypt, &invalid).is_err());
+            assert!(configure(KdfAlgorithm::Argon2id, &invalid).is_err());
+        }
+        let too_many = KdfOptions {
+            iterations: Some(PBKDF2_MAX_ITERATIONS + 1),
+            ..KdfOptions::default()
+        };
+        assert!(configure(KdfAlgorithm::Pbkdf2Sha256, &too_many).is_err());
+        assert!(configure(KdfAlgorithm::HkdfSha256, &KdfOptions::default()).is_err());
+    }
+}
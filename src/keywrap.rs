@@ -0,0 +1,262 @@
+use anyhow::{Result, anyhow};
+use openssl::memcmp;
+use openssl::symm::{Cipher, Crypter, Mode};
+
+/// RFC 3394 section 2.2.3.1 default initial value
+const DEFAULT_IV: [u8; 8] = [0xa6; 8];
+/// RFC 5649 section 3 alternative initial value prefix, followed by the 32-bit length
+const PADDED_IV_PREFIX: [u8; 4] = [0xa6, 0x59, 0x59, 0xa6];
+const SEMIBLOCK: usize = 8;
+
+/// Single-block AES in ECB mode with the key size of `kek`
+struct BlockCipher {
+    crypter: Crypter,
+    buffer: [u8; 32],
+}
+
+impl BlockCipher {
+    fn new(kek: &[u8], mode: Mode) -> Result<Self> {
+        let cipher = match kek.len() {
+            16 => Cipher::aes_128_ecb(),
+            24 => Cipher::aes_192_ecb(),
+            32 => Cipher::aes_256_ecb(),
+            n => return Err(anyhow!("KEK must be 16, 24 or 32 bytes, got {n}")),
+        };
+        let mut crypter = Crypter::new(cipher, mode, kek, None)?;
+        crypter.pad(false);
+        Ok(Self {
+            crypter,
+            buffer: [0u8; 32],
+        })
+    }
+
+    fn apply(&mut self, block: &mut [u8; 16]) -> Result<()> {
+        let written = self.crypter.update(block, &mut self.buffer)?;
+        if written != 16 {
+            return Err(anyhow!("AES block operation failed"));
+        }
+        block.copy_from_slice(&self.buffer[..16]);
+        Ok(())
+    }
+}
+
+impl Drop for BlockCipher {
+    fn drop(&mut self) {
+        self.buffer.fill(0);
+    }
+}
+
+/// Wrapping process W of RFC 3394 section 2.2.1 over `plaintext` semiblocks
+fn wrap_blocks(kek: &[u8], iv: [u8; 8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut cipher = BlockCipher::new(kek, Mode::Encrypt)?;
+    let n = plaintext.len() / SEMIBLOCK;
+    let mut a = iv;
+    let mut r = plaintext.to_vec();
+    let mut block = [0u8; 16];
+    for j in 0..6 {
+        for i in 0..n {
+            block[..8].copy_from_slice(&a);
+            block[8..].copy_from_slice(&r[i * SEMIBLOCK..(i + 1) * SEMIBLOCK]);
+            cipher.apply(&mut block)?;
+            let t = ((n * j) + i + 1) as u64;
+            a.copy_from_slice(&block[..8]);
+            a.iter_mut().zip(t.to_be_bytes()).for_each(|(a, t)| *a ^= t);
+            r[i * SEMIBLOCK..(i + 1) * SEMIBLOCK].copy_from_slice(&block[8..]);
+        }
+    }
+    block.fill(0);
+    let mut out = a.to_vec();
+    out.extend_from_slice(&r);
+    r.fill(0);
+    Ok(out)
+}
+
+/// Unwrapping process W^-1, returning the recovered initial value and plaintext
+fn unwrap_blocks(kek: &[u8], wrapped: &[u8]) -> Result<([u8; 8], Vec<u8>)> {
+    let mut cipher = BlockCipher::new(kek, Mode::Decrypt)?;
+    let n = wrapped.len() / SEMIBLOCK - 1;
+    let mut a = [0u8; 8];
+    a.copy_from_slice(&wrapped[..SEMIBLOCK]);
+    let mut r = wrapped[SEMIBLOCK..].to_vec();
+    let mut block = [0u8; 16];
+    for j in (0..6).rev() {
+        for i in (0..n).rev() {
+            let t = ((n * j) + i + 1) as u64;
+            a.iter_mut().zip(t.to_be_bytes()).for_each(|(a, t)| *a ^= t);
+            block[..8].copy_from_slice(&a);
+            block[8..].copy_from_slice(&r[i * SEMIBLOCK..(i + 1) * SEMIBLOCK]);
+            cipher.apply(&mut block)?;
+            a.copy_from_slice(&block[..8]);
+            r[i * SEMIBLOCK..(i + 1) * SEMIBLOCK].copy_from_slice(&block[8..]);
+        }
+    }
+    block.fill(0);
+    Ok((a, r))
+}
+
+/// RFC 3394 AES key wrap. `key` must be at least 16 bytes and a multiple of 8.
+pub fn wrap(kek: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    if key.len() < 2 * SEMIBLOCK || !key.len().is_multiple_of(SEMIBLOCK) {
+        return Err(anyhow!(
+            "key to wrap must be a multiple of 8 bytes and at least 16 bytes, use padding otherwise"
+        ));
+    }
+    wrap_blocks(kek, DEFAULT_IV, key)
+}
+
+/// RFC 3394 AES key unwrap, failing when the integrity check does not match
+pub fn unwrap(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+    if wrapped.len() < 3 * SEMIBLOCK || !wrapped.len().is_multiple_of(SEMIBLOCK) {
+        return Err(anyhow!("invalid wrapped key length"));
+    }
+    let (iv, mut key) = unwrap_blocks(kek, wrapped)?;
+    if !memcmp::eq(&iv, &DEFAULT_IV) {
+        key.fill(0);
+        return Err(anyhow!("key unwrap integrity check failed"));
+    }
+    Ok(key)
+}
+
+/// RFC 5649 AES key wrap with padding, for keys of any length from 1 byte
+pub fn wrap_with_padding(kek: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    let length = u32::try_from(key.len()).map_err(|_| anyhow!("key to wrap is too long"))?;
+    if key.is_empty() {
+        return Err(anyhow!("key to wrap must not be empty"));
+    }
+    let mut iv = [0u8; 8];
+    iv[..4].copy_from_slice(&PADDED_IV_PREFIX);
+    iv[4..].copy_from_slice(&length.to_be_bytes());
+
+    let mut padded = key.to_vec();
+    padded.resize(key.len().div_ceil(SEMIBLOCK) * SEMIBLOCK, 0);
+    let wrapped = if padded.len() == SEMIBLOCK {
+        // A single semiblock is encrypted directly as AIV || P
+        let mut cipher = BlockCipher::new(kek, Mode::Encrypt)?;
+        let mut block = [0u8; 16];
+        block[..8].copy_from_slice(&iv);
+        block[8..].copy_from_slice(&padded);
+        cipher.apply(&mut block)?;
+        block.to_vec()
+    } else {
+        wrap_blocks(kek, iv, &padded)?
+    };
+    padded.fill(0);
+    Ok(wrapped)
+}
+
+/// RFC 5649 AES key unwrap with padding
+pub fn unwrap_with_padding(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+    if wrapped.len() < 2 * SEMIBLOCK || !wrapped.len().is_multiple_of(SEMIBLOCK) {
+        return Err(anyhow!("invalid wrapped key length"));
+    }
+    let (iv, mut padded) = if wrapped.len() == 2 * SEMIBLOCK {
+        let mut cipher = BlockCipher::new(kek, Mode::Decrypt)?;
+        let mut block = [0u8; 16];
+        block.copy_from_slice(wrapped);
+        cipher.apply(&mut block)?;
+        let mut iv = [0u8; 8];
+        iv.copy_from_slice(&block[..8]);
+        let padded = block[8..].to_vec();
+        block.fill(0);
+        (iv, padded)
+    } else {
+        unwrap_blocks(kek, wrapped)?
+    };
+
+    // Check the prefix, the length range and the zero padding without early exits
+    let length = u32::from_be_bytes([iv[4], iv[5], iv[6], iv[7]]) as usize;
+    let mut valid = memcmp::eq(&iv[..4], &PADDED_IV_PREFIX);
+    valid &= length + SEMIBLOCK > padded.len() && length <= padded.len();
+    if valid {
+        valid &= padded[length..].iter().fold(0u8, |acc, b| acc | b) == 0;
+    }
+    if !valid {
+        padded.fill(0);
+        return Err(anyhow!("key unwrap integrity check failed"));
+    }
+    padded.truncate(length);
+    Ok(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(s: &str) -> Vec<u8> {
+        hex::decode(s.replace(' ', "")).unwrap()
+    }
+
+    #[test]
+    fn test_rfc3394_vectors() {
+        // (KEK, key data, wrapped) from RFC 3394 sections 4.1, 4.2, 4.3 and 4.6
+        let vectors = [
+            (
+                "000102030405060708090A0B0C0D0E0F",
+                "00112233445566778899AABBCCDDEEFF",
+                "1FA68B0A8112B447 AEF34BD8FB5A7B82 9D3E862371D2CFE5",
+            ),
+            (
+                "000102030405060708090A0B0C0D0E0F1011121314151617",
+                "00112233445566778899AABBCCDDEEFF",
+                "96778B25AE6CA435 F92B5B97C050AED2 468AB8A17AD84E5D",
+            ),
+            (
+                "000102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F",
+                "00112233445566778899AABBCCDDEEFF",
+                "64E8C3F9CE0F5BA2 63E9777905818A2A 93C8191E7D6E8AE7",
+            ),
+            (
+                "000102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F",
+                "00112233445566778899AABBCCDDEEFF000102030405060708090A0B0C0D0E0F",
+                "28C9F404C4B810F4 CBCCB35CFB87F826 3F5786E2D80ED326 CBC7F0E71A99F43B FB988B9B7A02DD21",
+            ),
+        ];
+        for (kek, key, wrapped) in vectors {
+            assert_eq!(wrap(&h(kek), &h(key)).unwrap(), h(wrapped));
+            assert_eq!(unwrap(&h(kek), &h(wrapped)).unwrap(), h(key));
+        }
+    }
+
+    #[test]
+    fn test_rfc5649_vectors() {
+        let kek = h("5840df6e29b02af1 ab493b705bf16ea1 ae8338f4dcc176a8");
+        let vectors = [
+            (
+                "c37b7e6492584340 bed1220780894115 5068f738",
+                "138bdeaa9b8fa7fc 61f97742e72248ee 5ae6ae5360d1ae6a 5f54f373fa543b6a",
+            ),
+            ("466f7250617369", "afbeb0f07dfbf541 9200f2ccb50bb24f"),
+        ];
+        for (key, wrapped) in vectors {
+            assert_eq!(wrap_with_padding(&kek, &h(key)).unwrap(), h(wrapped));
+            assert_eq!(unwrap_with_padding(&kek, &h(wrapped)).unwrap(), h(key));
+        }
+    }
+
+    #[test]
+    fn test_tampering_and_wrong_kek_fail() {
+        let kek = [7u8; 32];
+        let key = [9u8; 32];
+        let mut wrapped = wrap(&kek, &key).unwrap();
+        assert!(unwrap(&[8u8; 32], &wrapped).is_err());
+        wrapped[10] ^= 1;
+        assert!(unwrap(&kek, &wrapped).is_err());
+
+        for len in [1, 8, 13, 32] {
+            let mut wrapped = wrap_with_padding(&kek, &key[..len]).unwrap();
+            assert_eq!(unwrap_with_padding(&kek, &wrapped).unwrap(), &key[..len]);
+            wrapped[0] ^= 1;
+            assert!(unwrap_with_padding(&kek, &wrapped).is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_lengths() {
+        assert!(wrap(&[0u8; 16], &[0u8; 8]).is_err());
+        assert!(wrap(&[0u8; 16], &[0u8; 20]).is_err());
+        assert!(wrap(&[0u8; 15], &[0u8; 16]).is_err());
+        assert!(wrap_with_padding(&[0u8; 16], &[]).is_err());
+        assert!(unwrap(&[0u8; 16], &[0u8; 16]).is_err());
+        assert!(unwrap_with_padding(&[0u8; 16], &[0u8; 12]).is_err());
+    }
+}
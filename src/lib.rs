@@ -1,10 +1,33 @@
+pub mod access;
+pub mod aead;
+pub mod age;
 pub mod async_file_gcm;
 pub mod base52;
+pub mod calibrate;
+pub mod config;
 pub mod crypto;
+pub mod encoding;
+pub mod envelope;
 pub mod file_gcm;
+pub mod fpe;
+pub mod hash;
+pub mod jobs;
+pub mod jose;
+pub mod kdf;
+pub mod keys;
+pub mod keywrap;
+pub mod methods;
+pub mod openssl_enc;
+pub mod otp;
+pub mod pkcs12;
+pub mod pool;
+pub mod random;
 pub mod rotn;
 pub mod scrypt;
+pub mod shamir;
+pub mod siv;
 pub mod util;
+pub mod x509;
 
 use std::borrow::Cow;
 
@@ -14,6 +37,7 @@ use anyhow::Result;
 use openssl::hash::{MessageDigest, hash};
 use openssl::symm::{Cipher, Crypter, Mode};
 
+/// Key of the legacy format: an unsalted SHA-256 of the password
 fn derive_key(password: Cow<'_, str>) -> Vec<u8> {
     // SHA-256 hash of password
     hash(MessageDigest::sha256(), password.as_bytes())
@@ -21,25 +45,16 @@ fn derive_key(password: Cow<'_, str>) -> Vec<u8> {
         .to_vec()
 }
 
-pub fn encrypt<'a>(plaintext: Cow<'a, str>, password: Cow<'a, str>) -> Result<Cow<'a, str>> {
-    let key = derive_key(password);
-    let iv = [0u8; 16]; // 16 zero bytes IV
-
-    let cipher = Cipher::aes_256_cbc();
-
-    let mut crypter = Crypter::new(cipher, Mode::Encrypt, &key, Some(&iv))?;
-    crypter.pad(true);
-
-    log::info!("[encrypt] Cipher Block Size: {}", cipher.block_size());
-    let mut ciphertext = vec![0; plaintext.len() + cipher.block_size()];
-    let mut count = crypter.update(plaintext.as_bytes(), &mut ciphertext)?;
-    count += crypter.finalize(&mut ciphertext[count..])?;
-    ciphertext.truncate(count);
-
-    Ok(general_purpose::STANDARD.encode(&ciphertext).into())
+/// Decrypt the legacy format: Base64 of AES-256-CBC under [`derive_key`] with a zero IV.
+/// Nothing encrypts to this format any more; new data uses the AEAD format of
+/// [`scrypt::encrypt_base64_with_cipher`].
+pub fn decrypt<'a>(ciphertext_b64: Cow<'a, str>, password: Cow<'a, str>) -> Result<Cow<'a, str>> {
+    let plaintext = decrypt_bytes(&ciphertext_b64, password)?;
+    Ok(Cow::Owned(String::from_utf8(plaintext)?))
 }
 
-pub fn decrypt<'a>(ciphertext_b64: Cow<'a, str>, password: Cow<'a, str>) -> Result<Cow<'a, str>> {
+/// [`decrypt`] to arbitrary bytes
+pub fn decrypt_bytes(ciphertext_b64: &str, password: Cow<'_, str>) -> Result<Vec<u8>> {
     let key = derive_key(password);
     let iv = [0u8; 16];
 
@@ -56,5 +71,18 @@ pub fn decrypt<'a>(ciphertext_b64: Cow<'a, str>, password: Cow<'a, str>) -> Resu
     count += crypter.finalize(&mut plaintext[count..])?;
     plaintext.truncate(count);
 
-    Ok(Cow::Owned(String::from_utf8(plaintext)?))
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_legacy_vector() {
+        // `openssl enc -aes-256-cbc -K <sha256("pw")> -iv 0` of "secret"
+        let plaintext = decrypt("4SLBfRfdflW+tK+mC93C+A==".into(), "pw".into()).unwrap();
+        assert_eq!(plaintext, "secret");
+        assert!(decrypt("4SLBfRfdflW+tK+mC93C+A==".into(), "wrong".into()).is_err());
+    }
 }
@@ -0,0 +1,330 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::crypto::Code;
+
+/// JSON Schema draft of the parameter schemas
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Family of IPC methods, enabled, disabled and access-controlled as a unit
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum MethodGroup {
+    /// base64, base52 and ROT-N
+    Encoding,
+    /// Passphrase, AEAD, key wrap, SIV, FPE and envelope encryption
+    Encryption,
+    /// File encryption jobs
+    Files,
+    /// Digests and HMACs
+    Hash,
+    /// Random bytes, tokens, UUIDs, passwords and passphrases
+    Random,
+    /// Key generation, conversion and fingerprints
+    Keys,
+    Jwt,
+    Otp,
+    /// batch, list-methods, describe and version
+    #[default]
+    Meta,
+}
+
+impl MethodGroup {
+    pub const ALL: [MethodGroup; 9] = [
+        MethodGroup::Encoding,
+        MethodGroup::Encryption,
+        MethodGroup::Files,
+        MethodGroup::Hash,
+        MethodGroup::Random,
+        MethodGroup::Keys,
+        MethodGroup::Jwt,
+        MethodGroup::Otp,
+        MethodGroup::Meta,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MethodGroup::Encoding => "encoding",
+            MethodGroup::Encryption => "encryption",
+            MethodGroup::Files => "files",
+            MethodGroup::Hash => "hash",
+            MethodGroup::Random => "random",
+            MethodGroup::Keys => "keys",
+            MethodGroup::Jwt => "jwt",
+            MethodGroup::Otp => "otp",
+            MethodGroup::Meta => "meta",
+        }
+    }
+}
+
+impl fmt::Display for MethodGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for MethodGroup {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        MethodGroup::ALL
+            .into_iter()
+            .find(|group| group.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("unknown method group: {s}"))
+    }
+}
+
+/// JSON type of an IPC parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    String,
+    Integer,
+    Boolean,
+    Array,
+}
+
+impl ParamType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Integer => "integer",
+            ParamType::Boolean => "boolean",
+            ParamType::Array => "array",
+        }
+    }
+}
+
+/// Value used when an optional parameter is omitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamDefault {
+    String(&'static str),
+    Integer(u64),
+    Boolean(bool),
+}
+
+impl From<ParamDefault> for Value {
+    fn from(default: ParamDefault) -> Value {
+        match default {
+            ParamDefault::String(value) => value.into(),
+            ParamDefault::Integer(value) => value.into(),
+            ParamDefault::Boolean(value) => value.into(),
+        }
+    }
+}
+
+/// One parameter of an IPC method
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub kind: ParamType,
+    pub description: &'static str,
+    pub required: bool,
+    pub default: Option<ParamDefault>,
+    /// Accepted values, empty when any value of the type is accepted
+    pub values: Vec<&'static str>,
+    /// JSON Schema of the elements of an array
+    pub items: Option<Value>,
+}
+
+impl ParamSpec {
+    fn new(name: &'static str, kind: ParamType, description: &'static str) -> Self {
+        Self {
+            name,
+            kind,
+            description,
+            required: false,
+            default: None,
+            values: Vec::new(),
+            items: None,
+        }
+    }
+
+    pub fn string(name: &'static str, description: &'static str) -> Self {
+        Self::new(name, ParamType::String, description)
+    }
+
+    pub fn integer(name: &'static str, description: &'static str) -> Self {
+        Self::new(name, ParamType::Integer, description)
+    }
+
+    pub fn boolean(name: &'static str, description: &'static str) -> Self {
+        Self::new(name, ParamType::Boolean, description).default(ParamDefault::Boolean(false))
+    }
+
+    pub fn array(name: &'static str, description: &'static str) -> Self {
+        Self::new(name, ParamType::Array, description)
+    }
+
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    pub fn default(mut self, default: ParamDefault) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    pub fn one_of(mut self, values: impl IntoIterator<Item = &'static str>) -> Self {
+        self.values = values.into_iter().collect();
+        self
+    }
+
+    pub fn items(mut self, items: Value) -> Self {
+        self.items = Some(items);
+        self
+    }
+
+    /// JSON Schema of the parameter value
+    pub fn schema(&self) -> Value {
+        let mut schema = json!({
+            "type": self.kind.name(),
+            "description": self.description,
+        });
+        if let Some(default) = self.default {
+            schema["default"] = default.into();
+        }
+        if !self.values.is_empty() {
+            schema["enum"] = self.values.clone().into();
+        }
+        if let Some(items) = &self.items {
+            schema["items"] = items.clone();
+        }
+        schema
+    }
+}
+
+/// Name, parameters and error codes of an IPC method
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodSpec {
+    pub name: String,
+    pub group: MethodGroup,
+    pub description: &'static str,
+    pub params: Vec<ParamSpec>,
+    /// Error codes the method can return besides `InvalidArgumentsError` and
+    /// `PermissionDenied`
+    pub errors: Vec<Code>,
+}
+
+impl MethodSpec {
+    pub fn new(name: impl Into<String>, description: &'static str) -> Self {
+        Self {
+            name: name.into(),
+            group: MethodGroup::default(),
+            description,
+            params: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn group(mut self, group: MethodGroup) -> Self {
+        self.group = group;
+        self
+    }
+
+    pub fn param(mut self, param: ParamSpec) -> Self {
+        self.params.push(param);
+        self
+    }
+
+    pub fn params(mut self, params: impl IntoIterator<Item = ParamSpec>) -> Self {
+        self.params.extend(params);
+        self
+    }
+
+    pub fn errors(mut self, errors: &[Code]) -> Self {
+        self.errors.extend_from_slice(errors);
+        self
+    }
+
+    /// JSON Schema of the method's parameter object
+    pub fn params_schema(&self) -> Value {
+        // Any method accepts the token the access policy identifies clients by
+        let token = ParamSpec::string("token", "Client token checked by the access policy");
+        let properties: Map<String, Value> = self
+            .params
+            .iter()
+            .chain(std::iter::once(&token))
+            .map(|param| (param.name.to_string(), param.schema()))
+            .collect();
+        let required: Vec<&str> = self
+            .params
+            .iter()
+            .filter(|param| param.required)
+            .map(|param| param.name)
+            .collect();
+        json!({
+            "$schema": SCHEMA_DIALECT,
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        })
+    }
+
+    /// Description returned by the `describe` and `list-methods` IPC methods
+    pub fn describe(&self) -> Value {
+        // Any method fails with InvalidArgumentsError when its parameters do not parse,
+        // and with PermissionDenied when the access policy refuses the caller
+        let always = [Code::InvalidArgumentsError, Code::PermissionDenied];
+        let errors: Vec<Value> = always
+            .iter()
+            .chain(self.errors.iter().filter(|code| !always.contains(code)))
+            .map(|code| json!({ "code": *code as i32, "name": format!("{code:?}") }))
+            .collect();
+        json!({
+            "name": self.name,
+            "group": self.group.name(),
+            "description": self.description,
+            "params": self.params_schema(),
+            "errors": errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_schema() {
+        let spec = MethodSpec::new("random-bytes", "Random bytes")
+            .param(
+                ParamSpec::integer("length", "Number of bytes").default(ParamDefault::Integer(32)),
+            )
+            .param(ParamSpec::string("input", "Input").required())
+            .param(ParamSpec::string("encoding", "Output encoding").one_of(["hex", "base64"]))
+            .param(ParamSpec::boolean("public", "Public key"))
+            .errors(&[Code::KeyError, Code::InvalidArgumentsError]);
+
+        let described = spec.describe();
+        assert_eq!(described["name"], "random-bytes");
+        assert_eq!(described["group"], "meta");
+        let params = &described["params"];
+        assert_eq!(params["$schema"], SCHEMA_DIALECT);
+        assert_eq!(params["type"], "object");
+        assert_eq!(params["required"], json!(["input"]));
+        assert_eq!(params["properties"]["length"]["type"], "integer");
+        assert_eq!(params["properties"]["length"]["default"], 32);
+        assert_eq!(params["properties"]["public"]["default"], false);
+        assert_eq!(
+            params["properties"]["encoding"]["enum"],
+            json!(["hex", "base64"])
+        );
+        assert!(params["properties"]["input"].get("default").is_none());
+        assert_eq!(params["properties"]["token"]["type"], "string");
+        assert_eq!(
+            described["errors"],
+            json!([
+                { "code": -6, "name": "InvalidArgumentsError" },
+                { "code": -13, "name": "PermissionDenied" },
+                { "code": -10, "name": "KeyError" },
+            ])
+        );
+    }
+}
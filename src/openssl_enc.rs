@@ -0,0 +1,501 @@
+// Files and strings in the `Salted__` format of `openssl enc`:
+// "Salted__" || salt(8 bytes) || CBC ciphertext with PKCS#7 padding.
+// The format carries no integrity protection, so it is only meant for reading
+// legacy data and migrating it to the authenticated GCM format of `file_gcm`.
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose};
+use openssl::hash::MessageDigest;
+use openssl::pkcs5;
+use openssl::rand::rand_bytes;
+use openssl::symm::{Cipher, Crypter, Mode};
+
+use crate::file_gcm;
+use crate::kdf::Kdf;
+
+pub const MAGIC: &[u8; 8] = b"Salted__";
+const SALT_LEN: usize = 8;
+const CHUNK_LEN: usize = 4096;
+/// Base64 line length of `openssl enc -a`
+const BASE64_COLUMNS: usize = 64;
+/// Iteration count of `openssl enc -pbkdf2` when `-iter` is not given
+pub const DEFAULT_PBKDF2_ITERATIONS: u32 = 10_000;
+
+/// Cipher selected with `-aes-128-cbc`, `-aes-192-cbc` or `-aes-256-cbc`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncCipher {
+    Aes128Cbc,
+    Aes192Cbc,
+    Aes256Cbc,
+}
+
+impl EncCipher {
+    fn cipher(self) -> Cipher {
+        match self {
+            EncCipher::Aes128Cbc => Cipher::aes_128_cbc(),
+            EncCipher::Aes192Cbc => Cipher::aes_192_cbc(),
+            EncCipher::Aes256Cbc => Cipher::aes_256_cbc(),
+        }
+    }
+}
+
+impl fmt::Display for EncCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EncCipher::Aes128Cbc => "aes-128-cbc",
+            EncCipher::Aes192Cbc => "aes-192-cbc",
+            EncCipher::Aes256Cbc => "aes-256-cbc",
+        })
+    }
+}
+
+impl FromStr for EncCipher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim_start_matches('-').to_ascii_lowercase().as_str() {
+            "aes-128-cbc" | "aes128" => Ok(EncCipher::Aes128Cbc),
+            "aes-192-cbc" | "aes192" => Ok(EncCipher::Aes192Cbc),
+            "aes-256-cbc" | "aes256" => Ok(EncCipher::Aes256Cbc),
+            _ => Err(anyhow!("unsupported cipher: {s}")),
+        }
+    }
+}
+
+/// Digest selected with `-md`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncDigest {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl EncDigest {
+    fn message_digest(self) -> MessageDigest {
+        match self {
+            EncDigest::Md5 => MessageDigest::md5(),
+            EncDigest::Sha1 => MessageDigest::sha1(),
+            EncDigest::Sha256 => MessageDigest::sha256(),
+            EncDigest::Sha512 => MessageDigest::sha512(),
+        }
+    }
+}
+
+impl FromStr for EncDigest {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "md5" => Ok(EncDigest::Md5),
+            "sha1" => Ok(EncDigest::Sha1),
+            "sha256" => Ok(EncDigest::Sha256),
+            "sha512" => Ok(EncDigest::Sha512),
+            _ => Err(anyhow!("unsupported digest: {s}")),
+        }
+    }
+}
+
+/// How key and IV are derived from the password and salt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncKdf {
+    /// EVP_BytesToKey with a single iteration, used without `-pbkdf2`/`-iter`
+    BytesToKey(EncDigest),
+    /// PBKDF2-HMAC, used with `-pbkdf2` or `-iter`
+    Pbkdf2 { digest: EncDigest, iterations: u32 },
+}
+
+/// Options matching the `openssl enc` command line that produced a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncOptions {
+    pub cipher: EncCipher,
+    pub kdf: EncKdf,
+}
+
+impl Default for EncOptions {
+    /// `openssl enc -aes-256-cbc -pbkdf2 -salt`
+    fn default() -> Self {
+        Self {
+            cipher: EncCipher::Aes256Cbc,
+            kdf: EncKdf::Pbkdf2 {
+                digest: EncDigest::Sha256,
+                iterations: DEFAULT_PBKDF2_ITERATIONS,
+            },
+        }
+    }
+}
+
+impl EncOptions {
+    /// `openssl enc -aes-256-cbc -md md5`, the default before OpenSSL 1.1.0
+    pub fn legacy_md5() -> Self {
+        Self {
+            cipher: EncCipher::Aes256Cbc,
+            kdf: EncKdf::BytesToKey(EncDigest::Md5),
+        }
+    }
+
+    /// Derive key and IV for `salt`
+    fn derive(&self, password: &str, salt: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let cipher = self.cipher.cipher();
+        let iv_len = cipher.iv_len().unwrap_or(0);
+        match self.kdf {
+            EncKdf::BytesToKey(digest) => {
+                let pair = pkcs5::bytes_to_key(
+                    cipher,
+                    digest.message_digest(),
+                    password.as_bytes(),
+                    Some(salt),
+                    1,
+                )?;
+                Ok((pair.key, pair.iv.unwrap_or_default()))
+            }
+            EncKdf::Pbkdf2 { digest, iterations } => {
+                if iterations == 0 {
+                    return Err(anyhow!("PBKDF2 iteration count must not be zero"));
+                }
+                let mut out = vec![0u8; cipher.key_len() + iv_len];
+                pkcs5::pbkdf2_hmac(
+                    password.as_bytes(),
+                    salt,
+                    iterations as usize,
+                    digest.message_digest(),
+                    &mut out,
+                )?;
+                let iv = out.split_off(cipher.key_len());
+                Ok((out, iv))
+            }
+        }
+    }
+}
+
+/// Reader returning the plaintext of a `Salted__` stream
+pub struct Decryptor<R: Read> {
+    inner: R,
+    crypter: Crypter,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    len: usize,
+    finished: bool,
+}
+
+impl<R: Read> Decryptor<R> {
+    /// Read the header from `inner` and derive the key, failing on input without a salt
+    pub fn new(mut inner: R, password: &str, options: &EncOptions) -> Result<Self> {
+        let mut header = [0u8; MAGIC.len() + SALT_LEN];
+        inner
+            .read_exact(&mut header)
+            .map_err(|_| anyhow!("input too short for the Salted__ format"))?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(anyhow!(
+                "missing Salted__ header, data encrypted with -nosalt is not supported"
+            ));
+        }
+        let (key, iv) = options.derive(password, &header[MAGIC.len()..])?;
+        let cipher = options.cipher.cipher();
+        let crypter = Crypter::new(cipher, Mode::Decrypt, &key, Some(&iv))?;
+        Ok(Self {
+            inner,
+            crypter,
+            input: vec![0u8; CHUNK_LEN],
+            output: vec![0u8; CHUNK_LEN + cipher.block_size()],
+            pos: 0,
+            len: 0,
+            finished: false,
+        })
+    }
+}
+
+impl<R: Read> Read for Decryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pos < self.len {
+                let count = buf.len().min(self.len - self.pos);
+                buf[..count].copy_from_slice(&self.output[self.pos..self.pos + count]);
+                self.pos += count;
+                return Ok(count);
+            }
+            if self.finished {
+                return Ok(0);
+            }
+            let count = self.inner.read(&mut self.input)?;
+            self.len = if count == 0 {
+                self.finished = true;
+                self.crypter.finalize(&mut self.output).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "bad decrypt: wrong password, options or corrupted data",
+                    )
+                })?
+            } else {
+                self.crypter
+                    .update(&self.input[..count], &mut self.output)
+                    .map_err(io::Error::other)?
+            };
+            self.pos = 0;
+        }
+    }
+}
+
+/// Encrypt everything read from `reader` into `writer` in the `Salted__` format
+pub fn encrypt<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    password: &str,
+    options: &EncOptions,
+) -> Result<()> {
+    let mut salt = [0u8; SALT_LEN];
+    rand_bytes(&mut salt)?;
+    let (key, iv) = options.derive(password, &salt)?;
+    let cipher = options.cipher.cipher();
+    let mut crypter = Crypter::new(cipher, Mode::Encrypt, &key, Some(&iv))?;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&salt)?;
+
+    let mut buffer = [0u8; CHUNK_LEN];
+    let mut ciphertext_chunk = vec![0u8; CHUNK_LEN + cipher.block_size()];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        let len = crypter.update(&buffer[..count], &mut ciphertext_chunk)?;
+        writer.write_all(&ciphertext_chunk[..len])?;
+    }
+    let len = crypter.finalize(&mut ciphertext_chunk)?;
+    writer.write_all(&ciphertext_chunk[..len])?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Decrypt a `Salted__` stream from `reader` into `writer`
+pub fn decrypt<R: Read, W: Write>(
+    reader: R,
+    mut writer: W,
+    password: &str,
+    options: &EncOptions,
+) -> Result<()> {
+    let mut decryptor = Decryptor::new(reader, password, options)?;
+    io::copy(&mut decryptor, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encrypt plaintext -> Base64 with 64 character lines, as written by `openssl enc -a`
+pub fn encrypt_base64<'a>(
+    plaintext: &[u8],
+    password: Cow<'a, str>,
+    options: &EncOptions,
+) -> Result<Cow<'a, str>> {
+    let mut encrypted = Vec::new();
+    encrypt(plaintext, &mut encrypted, &password, options)?;
+    let encoded = general_purpose::STANDARD.encode(&encrypted);
+
+    let mut wrapped = String::with_capacity(encoded.len() + encoded.len() / BASE64_COLUMNS + 1);
+    for line in encoded.as_bytes().chunks(BASE64_COLUMNS) {
+        // Base64 output is ASCII, so every chunk is valid UTF-8
+        wrapped.push_str(std::str::from_utf8(line)?);
+        wrapped.push('\n');
+    }
+    Ok(wrapped.into())
+}
+
+/// Decrypt Base64 output of `openssl enc -a` (with or without `-A`) -> returns plaintext bytes
+pub fn decrypt_base64<'a>(
+    b64: Cow<'a, str>,
+    password: Cow<'a, str>,
+    options: &EncOptions,
+) -> Result<Vec<u8>> {
+    let compact: String = b64.split_ascii_whitespace().collect();
+    let decoded = general_purpose::STANDARD.decode(compact.as_bytes())?;
+    let mut plaintext = Vec::new();
+    decrypt(decoded.as_slice(), &mut plaintext, &password, options)?;
+    Ok(plaintext)
+}
+
+/// Encrypt a file like `openssl enc -e -salt` with the given options
+pub fn encrypt_file(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    options: &EncOptions,
+) -> Result<()> {
+    let infile = BufReader::new(File::open(input_path)?);
+    let outfile = BufWriter::new(File::create(output_path)?);
+    encrypt(infile, outfile, password, options)
+}
+
+/// Decrypt a file written by `openssl enc -e -salt`, removing the partial output when
+/// decryption fails
+pub fn decrypt_file(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    options: &EncOptions,
+) -> Result<()> {
+    let infile = BufReader::new(File::open(input_path)?);
+    let outfile = BufWriter::new(File::create(output_path)?);
+    let result = decrypt(infile, outfile, password, options);
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    }
+    result
+}
+
+/// Re-encrypt a `Salted__` file into the GCM format of [`file_gcm`] with `kdf`,
+/// streaming the plaintext without writing it to disk
+pub fn migrate_to_gcm(
+    input_path: &Path,
+    output_path: &Path,
+    password: &str,
+    options: &EncOptions,
+    kdf: &dyn Kdf,
+) -> Result<()> {
+    let infile = BufReader::new(File::open(input_path)?);
+    let decryptor = Decryptor::new(infile, password, options)?;
+    let result = file_gcm::encrypt_reader_with_kdf(decryptor, output_path, password, kdf);
+    if result.is_err() {
+        let _ = fs::remove_file(output_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kdf::Pbkdf2;
+    use tempfile::TempDir;
+
+    const PASSWORD: &str = "secret";
+
+    // Produced by OpenSSL 3 `openssl enc -a -pass pass:secret` from "hello legacy\n"
+    // with the options given
+    const VECTORS: [(&str, &str); 3] = [
+        // -aes-256-cbc -pbkdf2
+        ("U2FsdGVkX1+6FWUMYeA0NOZsw/Tjlgkp7Rd8UHfkklA=", "pbkdf2"),
+        // -aes-256-cbc -md md5
+        ("U2FsdGVkX1/Dq42biY5agEwp3fsRzfrRHRGvERx/0N4=", "md5"),
+        // -aes-128-cbc -pbkdf2 -iter 1000 -md sha512
+        ("U2FsdGVkX1/vzTkLE36E9ETuqenf17MZGJgLIUyoFGc=", "sha512"),
+    ];
+
+    // `openssl enc -aes-256-cbc -pbkdf2 -a` of 100 'x' characters, wrapped at 64 columns
+    const WRAPPED_VECTOR: &str = "\
+U2FsdGVkX18J52WFsWZgHAXE/LicBMbZQmJ9o/Uk65mfAJKbB4SP7pmZEe78pyuF
+QGFTuYuQE7SxNYP1eMM6Hesf/I1WKWVZXRLwKCLdcUz+di0KyCWbWxVEYXoa0dsh
+9s2QFohWMNDYsJyBGJVrDHpAx4F+yj7eaJlQxVAAeLM=
+";
+
+    fn options(name: &str) -> EncOptions {
+        match name {
+            "pbkdf2" => EncOptions::default(),
+            "md5" => EncOptions::legacy_md5(),
+            _ => EncOptions {
+                cipher: EncCipher::Aes128Cbc,
+                kdf: EncKdf::Pbkdf2 {
+                    digest: EncDigest::Sha512,
+                    iterations: 1000,
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_decrypt_openssl_vectors() {
+        for (b64, name) in VECTORS {
+            let plaintext =
+                decrypt_base64(Cow::Borrowed(b64), Cow::Borrowed(PASSWORD), &options(name))
+                    .unwrap();
+            assert_eq!(plaintext, b"hello legacy\n", "{name}");
+        }
+
+        let plaintext = decrypt_base64(
+            Cow::Borrowed(WRAPPED_VECTOR),
+            Cow::Borrowed(PASSWORD),
+            &EncOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(plaintext, [b'x'; 100]);
+    }
+
+    #[test]
+    fn test_roundtrip_and_wrong_options() {
+        let plaintext = vec![0x5au8; 10_000];
+        for name in ["pbkdf2", "md5", "sha512"] {
+            let encoded =
+                encrypt_base64(&plaintext, Cow::Borrowed(PASSWORD), &options(name)).unwrap();
+            assert!(encoded.starts_with("U2FsdGVkX1"));
+            assert!(encoded.lines().all(|line| line.len() <= BASE64_COLUMNS));
+
+            let decrypted =
+                decrypt_base64(encoded.clone(), Cow::Borrowed(PASSWORD), &options(name)).unwrap();
+            assert_eq!(decrypted, plaintext);
+            assert!(
+                decrypt_base64(encoded, Cow::Borrowed("wrong"), &options(name))
+                    .map(|decrypted| decrypted != plaintext)
+                    .unwrap_or(true)
+            );
+        }
+        assert!(
+            decrypt_base64(
+                Cow::Borrowed(VECTORS[0].0),
+                Cow::Borrowed(PASSWORD),
+                &EncOptions::legacy_md5()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rejects_unsalted_and_short_input() {
+        let mut out = Vec::new();
+        assert!(decrypt(&b"Salted_"[..], &mut out, PASSWORD, &EncOptions::default()).is_err());
+        assert!(decrypt(&[0u8; 32][..], &mut out, PASSWORD, &EncOptions::default()).is_err());
+        assert!("des-ede3-cbc".parse::<EncCipher>().is_err());
+        assert_eq!(
+            "-aes-128-cbc".parse::<EncCipher>().unwrap(),
+            EncCipher::Aes128Cbc
+        );
+        assert_eq!("SHA-256".parse::<EncDigest>().unwrap(), EncDigest::Sha256);
+    }
+
+    #[test]
+    fn test_file_roundtrip_and_migration() {
+        let tempdir = TempDir::with_prefix_in("test", "./").unwrap();
+        let input = tempdir.path().join("input.txt");
+        let encrypted = tempdir.path().join("input.txt.enc");
+        let decrypted = tempdir.path().join("decrypted.txt");
+        let migrated = tempdir.path().join("migrated.bin");
+        let restored = tempdir.path().join("restored.txt");
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&input, &data).unwrap();
+
+        let options = EncOptions::default();
+        encrypt_file(&input, &encrypted, PASSWORD, &options).unwrap();
+        decrypt_file(&encrypted, &decrypted, PASSWORD, &options).unwrap();
+        assert_eq!(fs::read(&decrypted).unwrap(), data);
+
+        let kdf = Pbkdf2 {
+            sha512: false,
+            iterations: 1000,
+        };
+        migrate_to_gcm(&encrypted, &migrated, PASSWORD, &options, &kdf).unwrap();
+        file_gcm::decrypt_file(&migrated, &restored, PASSWORD).unwrap();
+        assert_eq!(fs::read(&restored).unwrap(), data);
+
+        let mut corrupted = fs::read(&encrypted).unwrap();
+        corrupted.truncate(corrupted.len() - 1);
+        fs::write(&encrypted, &corrupted).unwrap();
+        assert!(decrypt_file(&encrypted, &decrypted, PASSWORD, &options).is_err());
+        assert!(!decrypted.exists());
+        assert!(migrate_to_gcm(&encrypted, &migrated, PASSWORD, &options, &kdf).is_err());
+        assert!(!migrated.exists());
+    }
+}
@@ -0,0 +1,545 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, anyhow};
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+use crate::{random, scrypt};
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+pub const DEFAULT_DIGITS: u32 = 6;
+pub const DEFAULT_PERIOD: u64 = 30;
+/// Recommended shared secret size for HMAC-SHA1 (RFC 4226 section 4)
+pub const DEFAULT_SECRET_LEN: usize = 20;
+
+/// RFC 4648 base32 without padding, as used by authenticator apps
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// Decode base32, ignoring case, spaces and `=` padding
+pub fn base32_decode(text: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&x| x == c.to_ascii_uppercase() as u8)
+            .ok_or_else(|| anyhow!("invalid base32 character: {c}"))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// HMAC algorithm of an OTP generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OtpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl OtpAlgorithm {
+    pub const ALL: [OtpAlgorithm; 3] = [
+        OtpAlgorithm::Sha1,
+        OtpAlgorithm::Sha256,
+        OtpAlgorithm::Sha512,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OtpAlgorithm::Sha1 => "SHA1",
+            OtpAlgorithm::Sha256 => "SHA256",
+            OtpAlgorithm::Sha512 => "SHA512",
+        }
+    }
+
+    fn digest(&self) -> MessageDigest {
+        match self {
+            OtpAlgorithm::Sha1 => MessageDigest::sha1(),
+            OtpAlgorithm::Sha256 => MessageDigest::sha256(),
+            OtpAlgorithm::Sha512 => MessageDigest::sha512(),
+        }
+    }
+}
+
+impl fmt::Display for OtpAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OtpAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Ok(OtpAlgorithm::Sha1),
+            "SHA256" => Ok(OtpAlgorithm::Sha256),
+            "SHA512" => Ok(OtpAlgorithm::Sha512),
+            _ => Err(anyhow!("unknown OTP algorithm: {s}")),
+        }
+    }
+}
+
+fn check_digits(digits: u32) -> Result<()> {
+    if !(6..=10).contains(&digits) {
+        return Err(anyhow!("digits must be between 6 and 10"));
+    }
+    Ok(())
+}
+
+/// HOTP value for `counter` (RFC 4226)
+pub fn hotp(secret: &[u8], counter: u64, digits: u32, algorithm: OtpAlgorithm) -> Result<String> {
+    check_digits(digits)?;
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(algorithm.digest(), &key)?;
+    signer.update(&counter.to_be_bytes())?;
+    let mac = signer.sign_to_vec()?;
+
+    // Dynamic truncation
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        mac[offset] & 0x7f,
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]) as u64;
+    let code = binary % 10u64.pow(digits);
+    Ok(format!("{code:0width$}", width = digits as usize))
+}
+
+fn codes_equal(a: &str, b: &str) -> bool {
+    a.len() == b.len() && memcmp::eq(a.as_bytes(), b.as_bytes())
+}
+
+/// Verify an HOTP code against counters `counter..=counter + look_ahead`.
+/// Returns the counter to store for the next verification on success.
+pub fn verify_hotp(
+    secret: &[u8],
+    code: &str,
+    counter: u64,
+    look_ahead: u64,
+    digits: u32,
+    algorithm: OtpAlgorithm,
+) -> Result<Option<u64>> {
+    for candidate in counter..=counter.saturating_add(look_ahead) {
+        if codes_equal(&hotp(secret, candidate, digits, algorithm)?, code.trim()) {
+            return Ok(Some(candidate + 1));
+        }
+    }
+    Ok(None)
+}
+
+/// Time-based OTP generator (RFC 6238)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Totp {
+    pub secret: Vec<u8>,
+    pub digits: u32,
+    pub period: u64,
+    pub algorithm: OtpAlgorithm,
+}
+
+impl Totp {
+    pub fn new(secret: Vec<u8>) -> Self {
+        Self {
+            secret,
+            digits: DEFAULT_DIGITS,
+            period: DEFAULT_PERIOD,
+            algorithm: OtpAlgorithm::default(),
+        }
+    }
+
+    fn time_step(&self, unix_time: u64) -> Result<u64> {
+        if self.period == 0 {
+            return Err(anyhow!("period must be positive"));
+        }
+        Ok(unix_time / self.period)
+    }
+
+    /// Code valid at `unix_time` (seconds since the epoch)
+    pub fn generate_at(&self, unix_time: u64) -> Result<String> {
+        hotp(
+            &self.secret,
+            self.time_step(unix_time)?,
+            self.digits,
+            self.algorithm,
+        )
+    }
+
+    /// Code valid now
+    pub fn generate(&self) -> Result<String> {
+        self.generate_at(now()?)
+    }
+
+    /// Verify `code` at `unix_time`, accepting up to `window` time steps of
+    /// clock drift in either direction. Returns the matching drift in steps.
+    pub fn verify_at(&self, code: &str, unix_time: u64, window: u64) -> Result<Option<i64>> {
+        let step = self.time_step(unix_time)?;
+        // Check the current step first, then alternate outwards
+        let mut drifts = vec![0i64];
+        for d in 1..=window as i64 {
+            drifts.push(-d);
+            drifts.push(d);
+        }
+        for drift in drifts {
+            let Some(candidate) = step.checked_add_signed(drift) else {
+                continue;
+            };
+            let expected = hotp(&self.secret, candidate, self.digits, self.algorithm)?;
+            if codes_equal(&expected, code.trim()) {
+                return Ok(Some(drift));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Verify `code` against the current time
+    pub fn verify(&self, code: &str, window: u64) -> Result<Option<i64>> {
+        self.verify_at(code, now()?, window)
+    }
+}
+
+/// Seconds since the Unix epoch
+pub fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// New random shared secret
+pub fn generate_secret() -> Result<Vec<u8>> {
+    random::random_bytes(DEFAULT_SECRET_LEN)
+}
+
+/// Protect a shared secret at rest with the scrypt string API
+pub fn encrypt_secret(secret: &[u8], passphrase: &str) -> Result<String> {
+    Ok(scrypt::encrypt_base64(secret, Cow::Borrowed(passphrase))?.into_owned())
+}
+
+/// Recover a shared secret produced by [`encrypt_secret`]
+pub fn decrypt_secret(encrypted: &str, passphrase: &str) -> Result<Vec<u8>> {
+    scrypt::decrypt_base64(Cow::Borrowed(encrypted), Cow::Borrowed(passphrase))
+}
+
+/// OTP kind of an `otpauth://` URI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpKind {
+    Totp { period: u64 },
+    Hotp { counter: u64 },
+}
+
+/// Contents of an `otpauth://` provisioning URI (Google Authenticator key URI format)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpAuth {
+    pub kind: OtpKind,
+    pub account: String,
+    pub issuer: Option<String>,
+    pub secret: Vec<u8>,
+    pub algorithm: OtpAlgorithm,
+    pub digits: u32,
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> Result<String> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = text
+                    .get(i + 1..i + 3)
+                    .ok_or_else(|| anyhow!("truncated percent escape"))?;
+                out.push(u8::from_str_radix(hex, 16)?);
+                i += 3;
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    Ok(String::from_utf8(out)?)
+}
+
+impl OtpAuth {
+    pub fn to_uri(&self) -> String {
+        let (kind, extra) = match self.kind {
+            OtpKind::Totp { period } => ("totp", format!("&period={period}")),
+            OtpKind::Hotp { counter } => ("hotp", format!("&counter={counter}")),
+        };
+        let label = match &self.issuer {
+            Some(issuer) => format!(
+                "{}:{}",
+                percent_encode(issuer),
+                percent_encode(&self.account)
+            ),
+            None => percent_encode(&self.account),
+        };
+        let issuer = self
+            .issuer
+            .as_ref()
+            .map(|issuer| format!("&issuer={}", percent_encode(issuer)))
+            .unwrap_or_default();
+        format!(
+            "otpauth://{kind}/{label}?secret={}{issuer}&algorithm={}&digits={}{extra}",
+            base32_encode(&self.secret),
+            self.algorithm,
+            self.digits
+        )
+    }
+
+    pub fn parse(uri: &str) -> Result<Self> {
+        let rest = uri
+            .strip_prefix("otpauth://")
+            .ok_or_else(|| anyhow!("not an otpauth URI"))?;
+        let (kind, rest) = rest
+            .split_once('/')
+            .ok_or_else(|| anyhow!("missing otpauth label"))?;
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let label = percent_decode(label)?;
+        let (label_issuer, account) = match label.split_once(':') {
+            Some((issuer, account)) => (Some(issuer.to_string()), account.trim().to_string()),
+            None => (None, label),
+        };
+
+        let mut secret = None;
+        let mut issuer = label_issuer;
+        let mut algorithm = OtpAlgorithm::default();
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = None;
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value)?;
+            match name {
+                "secret" => secret = Some(base32_decode(&value)?),
+                "issuer" => issuer = Some(value),
+                "algorithm" => algorithm = value.parse()?,
+                "digits" => digits = value.parse()?,
+                "period" => period = value.parse()?,
+                "counter" => counter = Some(value.parse()?),
+                _ => log::debug!("Ignoring otpauth parameter {name}"),
+            }
+        }
+        check_digits(digits)?;
+
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "totp" => OtpKind::Totp { period },
+            "hotp" => OtpKind::Hotp {
+                counter: counter.ok_or_else(|| anyhow!("hotp URI requires a counter"))?,
+            },
+            _ => return Err(anyhow!("unknown OTP type: {kind}")),
+        };
+
+        Ok(OtpAuth {
+            kind,
+            account,
+            issuer,
+            secret: secret.ok_or_else(|| anyhow!("otpauth URI requires a secret"))?,
+            algorithm,
+            digits,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RFC_SECRET_SHA1: &[u8] = b"12345678901234567890";
+    const RFC_SECRET_SHA256: &[u8] = b"12345678901234567890123456789012";
+    const RFC_SECRET_SHA512: &[u8] =
+        b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    #[test]
+    fn test_base32_rfc4648_vectors() {
+        let cases: [(&[u8], &str); 7] = [
+            (b"", ""),
+            (b"f", "MY"),
+            (b"fo", "MZXQ"),
+            (b"foo", "MZXW6"),
+            (b"foob", "MZXW6YQ"),
+            (b"fooba", "MZXW6YTB"),
+            (b"foobar", "MZXW6YTBOI"),
+        ];
+        for (data, text) in cases {
+            assert_eq!(base32_encode(data), text);
+            assert_eq!(base32_decode(text).unwrap(), data);
+        }
+        assert_eq!(base32_decode("mzxw 6ytb oi======").unwrap(), b"foobar");
+        assert!(base32_decode("MZ1W").is_err());
+    }
+
+    #[test]
+    fn test_hotp_rfc4226_vectors() {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(
+                hotp(RFC_SECRET_SHA1, counter as u64, 6, OtpAlgorithm::Sha1).unwrap(),
+                *code
+            );
+        }
+    }
+
+    #[test]
+    fn test_totp_rfc6238_vectors() {
+        let vectors: [(u64, &str, &str, &str); 6] = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, sha1, sha256, sha512) in vectors {
+            for (secret, algorithm, code) in [
+                (RFC_SECRET_SHA1, OtpAlgorithm::Sha1, sha1),
+                (RFC_SECRET_SHA256, OtpAlgorithm::Sha256, sha256),
+                (RFC_SECRET_SHA512, OtpAlgorithm::Sha512, sha512),
+            ] {
+                let totp = Totp {
+                    secret: secret.to_vec(),
+                    digits: 8,
+                    period: 30,
+                    algorithm,
+                };
+                assert_eq!(
+                    totp.generate_at(time).unwrap(),
+                    code,
+                    "{algorithm} @ {time}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_totp_verify_with_drift() {
+        let totp = Totp::new(RFC_SECRET_SHA1.to_vec());
+        let time = 1_700_000_000;
+        let previous = totp.generate_at(time - 30).unwrap();
+        let next = totp.generate_at(time + 30).unwrap();
+        let far = totp.generate_at(time + 90).unwrap();
+
+        assert_eq!(
+            totp.verify_at(&totp.generate_at(time).unwrap(), time, 0)
+                .unwrap(),
+            Some(0)
+        );
+        assert_eq!(totp.verify_at(&previous, time, 1).unwrap(), Some(-1));
+        assert_eq!(totp.verify_at(&next, time, 1).unwrap(), Some(1));
+        assert_eq!(totp.verify_at(&previous, time, 0).unwrap(), None);
+        assert_eq!(totp.verify_at(&far, time, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_hotp_verify_look_ahead() {
+        let code = hotp(RFC_SECRET_SHA1, 5, 6, OtpAlgorithm::Sha1).unwrap();
+        assert_eq!(
+            verify_hotp(RFC_SECRET_SHA1, &code, 3, 3, 6, OtpAlgorithm::Sha1).unwrap(),
+            Some(6)
+        );
+        assert_eq!(
+            verify_hotp(RFC_SECRET_SHA1, &code, 0, 3, 6, OtpAlgorithm::Sha1).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_digits() {
+        assert!(hotp(RFC_SECRET_SHA1, 0, 4, OtpAlgorithm::Sha1).is_err());
+        assert!(hotp(RFC_SECRET_SHA1, 0, 11, OtpAlgorithm::Sha1).is_err());
+    }
+
+    #[test]
+    fn test_otpauth_uri_roundtrip() {
+        let auth = OtpAuth {
+            kind: OtpKind::Totp { period: 30 },
+            account: "alice@example.com".to_string(),
+            issuer: Some("Example Co".to_string()),
+            secret: RFC_SECRET_SHA1.to_vec(),
+            algorithm: OtpAlgorithm::Sha256,
+            digits: 8,
+        };
+        let uri = auth.to_uri();
+        assert!(uri.starts_with("otpauth://totp/Example%20Co:alice%40example.com?secret="));
+        assert_eq!(OtpAuth::parse(&uri).unwrap(), auth);
+
+        let hotp = OtpAuth {
+            kind: OtpKind::Hotp { counter: 42 },
+            issuer: None,
+            ..auth
+        };
+        assert_eq!(OtpAuth::parse(&hotp.to_uri()).unwrap(), hotp);
+    }
+
+    #[test]
+    fn test_otpauth_parse_defaults() {
+        let auth = OtpAuth::parse(
+            "otpauth://totp/ACME%20Co:john.doe@email.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co",
+        )
+        .unwrap();
+        assert_eq!(auth.kind, OtpKind::Totp { period: 30 });
+        assert_eq!(auth.account, "john.doe@email.com");
+        assert_eq!(auth.issuer.as_deref(), Some("ACME Co"));
+        assert_eq!(auth.algorithm, OtpAlgorithm::Sha1);
+        assert_eq!(auth.digits, 6);
+
+        assert!(OtpAuth::parse("https://example.com").is_err());
+        assert!(OtpAuth::parse("otpauth://totp/x?issuer=y").is_err());
+        assert!(OtpAuth::parse("otpauth://hotp/x?secret=MZXW6").is_err());
+    }
+
+    #[test]
+    fn test_encrypted_secret_roundtrip() {
+        let secret = generate_secret().unwrap();
+        let encrypted = encrypt_secret(&secret, "vault passphrase").unwrap();
+        assert_eq!(
+            decrypt_secret(&encrypted, "vault passphrase").unwrap(),
+            secret
+        );
+    }
+}
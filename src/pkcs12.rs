@@ -0,0 +1,146 @@
+use anyhow::{Result, anyhow};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{PKey, PKeyRef, Private};
+use openssl::stack::Stack;
+use openssl::x509::{X509, X509Ref};
+
+use crate::keys::{self, KeyFormat};
+
+/// Key, certificate and CA chain stored in a PKCS#12 file
+pub struct Bundle {
+    pub key: PKey<Private>,
+    pub cert: X509,
+    pub chain: Vec<X509>,
+}
+
+fn check_passphrase(passphrase: &str) -> Result<()> {
+    if passphrase.is_empty() {
+        return Err(anyhow!("PKCS#12 passphrase must not be empty"));
+    }
+    if passphrase.contains('\0') {
+        return Err(anyhow!(
+            "PKCS#12 passphrase must not contain NUL characters"
+        ));
+    }
+    Ok(())
+}
+
+/// Bundle `key`, its certificate and the CA chain into a DER encoded PKCS#12 file.
+/// Key and certificates are encrypted with AES-256-CBC and the file is
+/// authenticated with an HMAC-SHA256 MAC, independent of the OpenSSL defaults.
+pub fn create(
+    name: &str,
+    key: &PKeyRef<Private>,
+    cert: &X509Ref,
+    chain: &[X509],
+    passphrase: &str,
+) -> Result<Vec<u8>> {
+    check_passphrase(passphrase)?;
+    if !cert.public_key()?.public_eq(key) {
+        return Err(anyhow!("private key does not match the certificate"));
+    }
+    let mut ca = Stack::new()?;
+    for cert in chain {
+        ca.push(cert.clone())?;
+    }
+
+    let mut builder = Pkcs12::builder();
+    builder
+        .name(name)
+        .pkey(key)
+        .cert(cert)
+        .key_algorithm(Nid::AES_256_CBC)
+        .cert_algorithm(Nid::AES_256_CBC)
+        .mac_md(MessageDigest::sha256());
+    if !chain.is_empty() {
+        builder.ca(ca);
+    }
+    Ok(builder.build2(passphrase)?.to_der()?)
+}
+
+/// Decrypt a PKCS#12 file, which must contain a private key and its certificate
+pub fn parse(der: &[u8], passphrase: &str) -> Result<Bundle> {
+    if passphrase.contains('\0') {
+        return Err(anyhow!(
+            "PKCS#12 passphrase must not contain NUL characters"
+        ));
+    }
+    let parsed = Pkcs12::from_der(der)?
+        .parse2(passphrase)
+        .map_err(|_| anyhow!("wrong passphrase or corrupted PKCS#12 file"))?;
+    let key = parsed
+        .pkey
+        .ok_or_else(|| anyhow!("PKCS#12 file has no private key"))?;
+    let cert = parsed
+        .cert
+        .ok_or_else(|| anyhow!("PKCS#12 file has no certificate"))?;
+    let chain = parsed
+        .ca
+        .map(|ca| ca.into_iter().collect())
+        .unwrap_or_default();
+    Ok(Bundle { key, cert, chain })
+}
+
+impl Bundle {
+    /// PKCS#8 PEM private key, encrypted when a passphrase is given
+    pub fn key_pem(&self, passphrase: Option<&str>) -> Result<Vec<u8>> {
+        keys::export_private(&self.key, KeyFormat::Pem, passphrase)
+    }
+
+    /// Certificate followed by the CA chain as concatenated PEM
+    pub fn certs_pem(&self) -> Result<Vec<u8>> {
+        let mut pem = self.cert.to_pem()?;
+        for cert in &self.chain {
+            pem.extend_from_slice(&cert.to_pem()?);
+        }
+        Ok(pem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{Curve, KeySpec};
+    use crate::x509::{self, CertParams};
+
+    #[test]
+    fn test_create_and_parse_with_chain() {
+        let ca_key = keys::generate(KeySpec::Ec(Curve::P256)).unwrap();
+        let ca = x509::create_ca(&ca_key, &CertParams::new("Test CA")).unwrap();
+        let key = keys::generate(KeySpec::Rsa(2048)).unwrap();
+        let cert = x509::issue(&ca, &ca_key, &key, &CertParams::new("client")).unwrap();
+
+        let p12 = create("client", &key, &cert, std::slice::from_ref(&ca), "hunter2").unwrap();
+        let bundle = parse(&p12, "hunter2").unwrap();
+
+        assert!(bundle.key.public_eq(&key));
+        assert_eq!(bundle.cert.to_der().unwrap(), cert.to_der().unwrap());
+        assert_eq!(bundle.chain.len(), 1);
+        assert_eq!(bundle.chain[0].to_der().unwrap(), ca.to_der().unwrap());
+
+        let pem = bundle.certs_pem().unwrap();
+        assert_eq!(x509::load_chain(&pem).unwrap().len(), 2);
+        let key_pem = bundle.key_pem(Some("other")).unwrap();
+        assert!(
+            keys::load_private(&key_pem, Some("other"))
+                .unwrap()
+                .public_eq(&key)
+        );
+    }
+
+    #[test]
+    fn test_wrong_passphrase_and_mismatched_key() {
+        let key = keys::generate(KeySpec::Ec(Curve::P256)).unwrap();
+        let cert = x509::self_signed(&key, &CertParams::new("localhost")).unwrap();
+        let p12 = create("localhost", &key, &cert, &[], "correct").unwrap();
+
+        assert!(parse(&p12, "wrong").is_err());
+        assert!(parse(&p12, "correct").unwrap().chain.is_empty());
+        assert!(create("localhost", &key, &cert, &[], "").is_err());
+
+        let other = keys::generate(KeySpec::Ec(Curve::P256)).unwrap();
+        assert!(create("localhost", &other, &cert, &[], "correct").is_err());
+    }
+}
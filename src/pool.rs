@@ -0,0 +1,134 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::Semaphore;
+
+/// Operations allowed to wait for a free thread before new ones are refused
+pub const DEFAULT_QUEUE_LIMIT: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolError {
+    /// `max_queued` operations are already waiting
+    QueueFull,
+    /// The operation panicked
+    Failed(String),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::QueueFull => f.write_str("too many operations queued, try again later"),
+            PoolError::Failed(e) => write!(f, "operation failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+/// Runs CPU-heavy work on tokio's blocking threads, at most `max_concurrent` at a time
+/// with at most `max_queued` more waiting
+#[derive(Clone)]
+pub struct BlockingPool {
+    permits: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+    max_concurrent: usize,
+    max_queued: usize,
+}
+
+/// One thread per CPU
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, usize::from)
+}
+
+impl Default for BlockingPool {
+    fn default() -> Self {
+        Self::new(default_threads(), DEFAULT_QUEUE_LIMIT)
+    }
+}
+
+impl BlockingPool {
+    pub fn new(max_concurrent: usize, max_queued: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            max_concurrent,
+            max_queued,
+        }
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    pub fn max_queued(&self) -> usize {
+        self.max_queued
+    }
+
+    /// Operations currently holding a thread
+    pub fn running(&self) -> usize {
+        self.max_concurrent - self.permits.available_permits()
+    }
+
+    /// Run `work` on a blocking thread once one of the pool's slots is free
+    pub async fn run<F, T>(&self, work: F) -> Result<T, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let queued = self.queued.fetch_add(1, Ordering::SeqCst);
+                if queued >= self.max_queued {
+                    self.queued.fetch_sub(1, Ordering::SeqCst);
+                    return Err(PoolError::QueueFull);
+                }
+                let permit = self.permits.clone().acquire_owned().await;
+                self.queued.fetch_sub(1, Ordering::SeqCst);
+                permit.map_err(|e| PoolError::Failed(e.to_string()))?
+            }
+        };
+        tokio::task::spawn_blocking(move || {
+            let result = work();
+            drop(permit);
+            result
+        })
+        .await
+        .map_err(|e| PoolError::Failed(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_queue_limit() {
+        let pool = BlockingPool::new(1, 1);
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let running = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(move || release_rx.recv().is_ok()).await }
+        });
+        while pool.running() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let queued = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| 2).await }
+        });
+        while pool.queued.load(Ordering::SeqCst) == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(pool.run(|| 3).await, Err(PoolError::QueueFull));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(running.await.unwrap(), Ok(true));
+        assert_eq!(queued.await.unwrap(), Ok(2));
+        assert_eq!(pool.run(|| 4).await, Ok(4));
+    }
+}
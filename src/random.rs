@@ -0,0 +1,335 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose};
+use openssl::rand::rand_bytes;
+
+use crate::encoding::Encoding;
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!#$%&()*+,-./:;<=>?@[]^_{|}~";
+// Characters that are easily confused when read aloud or printed
+const AMBIGUOUS: &str = "0O1lI|";
+
+/// Upper bound on generated lengths, to keep requests from exhausting memory
+pub const MAX_LENGTH: usize = 4096;
+
+/// Uniform random integer in `0..bound` using rejection sampling
+pub fn uniform(bound: u32) -> Result<u32> {
+    if bound == 0 {
+        return Err(anyhow!("bound must be positive"));
+    }
+    // Largest multiple of bound that fits in u32, values above it are rejected
+    let zone = u32::MAX - (u32::MAX % bound);
+    loop {
+        let mut buf = [0u8; 4];
+        rand_bytes(&mut buf)?;
+        let value = u32::from_be_bytes(buf);
+        if value < zone {
+            return Ok(value % bound);
+        }
+    }
+}
+
+fn check_length(len: usize) -> Result<()> {
+    if len == 0 || len > MAX_LENGTH {
+        return Err(anyhow!("length must be between 1 and {MAX_LENGTH}"));
+    }
+    Ok(())
+}
+
+/// `len` cryptographically secure random bytes
+pub fn random_bytes(len: usize) -> Result<Vec<u8>> {
+    check_length(len)?;
+    let mut bytes = vec![0u8; len];
+    rand_bytes(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// `len` random bytes in the given text encoding
+pub fn random_encoded(len: usize, encoding: Encoding) -> Result<String> {
+    Ok(encoding.encode(&random_bytes(len)?))
+}
+
+/// URL-safe token (base64url without padding) carrying `len` random bytes
+pub fn token(len: usize) -> Result<String> {
+    Ok(general_purpose::URL_SAFE_NO_PAD.encode(random_bytes(len)?))
+}
+
+/// Random (version 4) UUID in the canonical hyphenated form
+pub fn uuid_v4() -> Result<String> {
+    let mut b = [0u8; 16];
+    rand_bytes(&mut b)?;
+    b[6] = (b[6] & 0x0f) | 0x40; // version 4
+    b[8] = (b[8] & 0x3f) | 0x80; // RFC 4122 variant
+    let hex = hex::encode(b);
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Character classes a password is drawn from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharClasses {
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    pub exclude_ambiguous: bool,
+}
+
+impl Default for CharClasses {
+    fn default() -> Self {
+        Self {
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            exclude_ambiguous: false,
+        }
+    }
+}
+
+impl CharClasses {
+    /// Alphabet of every enabled class, one entry per class
+    fn sets(&self) -> Vec<Vec<char>> {
+        [
+            (self.lowercase, LOWERCASE),
+            (self.uppercase, UPPERCASE),
+            (self.digits, DIGITS),
+            (self.symbols, SYMBOLS),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, set)| {
+            set.chars()
+                .filter(|c| !(self.exclude_ambiguous && AMBIGUOUS.contains(*c)))
+                .collect()
+        })
+        .collect()
+    }
+}
+
+/// Parses a comma-separated list such as `"lower,upper,digits"`.
+/// `no-ambiguous` drops look-alike characters.
+impl FromStr for CharClasses {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut classes = CharClasses {
+            lowercase: false,
+            uppercase: false,
+            digits: false,
+            symbols: false,
+            exclude_ambiguous: false,
+        };
+        for class in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            match class {
+                "lower" | "lowercase" => classes.lowercase = true,
+                "upper" | "uppercase" => classes.uppercase = true,
+                "digits" => classes.digits = true,
+                "symbols" => classes.symbols = true,
+                "no-ambiguous" => classes.exclude_ambiguous = true,
+                _ => return Err(anyhow!("unknown character class: {class}")),
+            }
+        }
+        Ok(classes)
+    }
+}
+
+/// A generated secret with its estimated strength
+#[derive(Debug, Clone, PartialEq)]
+pub struct Secret {
+    pub value: String,
+    pub entropy_bits: f64,
+}
+
+/// Random password of `length` characters containing at least one character
+/// of every enabled class. Entropy is estimated as `length * log2(alphabet size)`.
+pub fn password(length: usize, classes: &CharClasses) -> Result<Secret> {
+    check_length(length)?;
+    let sets = classes.sets();
+    if sets.is_empty() {
+        return Err(anyhow!("at least one character class is required"));
+    }
+    if length < sets.len() {
+        return Err(anyhow!(
+            "length must be at least {} to include every character class",
+            sets.len()
+        ));
+    }
+    let alphabet: Vec<char> = sets.iter().flatten().copied().collect();
+
+    // Rejection sampling keeps the result uniform over all valid passwords
+    let value = loop {
+        let candidate = (0..length)
+            .map(|_| uniform(alphabet.len() as u32).map(|i| alphabet[i as usize]))
+            .collect::<Result<String>>()?;
+        if sets
+            .iter()
+            .all(|set| candidate.chars().any(|c| set.contains(&c)))
+        {
+            break candidate;
+        }
+    };
+
+    Ok(Secret {
+        value,
+        entropy_bits: length as f64 * (alphabet.len() as f64).log2(),
+    })
+}
+
+/// Passphrase of `words` words picked from `wordlist`, joined by `separator`.
+/// Entropy is `words * log2(distinct words)`.
+pub fn passphrase<S: AsRef<str>>(words: usize, wordlist: &[S], separator: &str) -> Result<Secret> {
+    check_length(words)?;
+    let distinct: Vec<&str> = {
+        let mut seen = HashSet::new();
+        wordlist
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|w| seen.insert(*w))
+            .collect()
+    };
+    if distinct.len() < 2 {
+        return Err(anyhow!("wordlist must contain at least two distinct words"));
+    }
+
+    let value = (0..words)
+        .map(|_| uniform(distinct.len() as u32).map(|i| distinct[i as usize]))
+        .collect::<Result<Vec<_>>>()?
+        .join(separator);
+
+    Ok(Secret {
+        value,
+        entropy_bits: words as f64 * (distinct.len() as f64).log2(),
+    })
+}
+
+/// Load a wordlist with one word per line. Lines in the EFF dice format
+/// (`11111<TAB>word`) use the last field.
+pub fn load_wordlist(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniform_in_range() {
+        for bound in [1u32, 2, 7, 52, 1000] {
+            for _ in 0..100 {
+                assert!(uniform(bound).unwrap() < bound);
+            }
+        }
+        assert!(uniform(0).is_err());
+    }
+
+    #[test]
+    fn test_random_bytes_lengths() {
+        assert_eq!(random_bytes(32).unwrap().len(), 32);
+        assert_ne!(random_bytes(32).unwrap(), random_bytes(32).unwrap());
+        assert!(random_bytes(0).is_err());
+        assert!(random_bytes(MAX_LENGTH + 1).is_err());
+    }
+
+    #[test]
+    fn test_random_encoded() {
+        for encoding in Encoding::ALL {
+            let text = random_encoded(16, encoding).unwrap();
+            assert_eq!(encoding.decode(&text).unwrap().len(), 16);
+        }
+    }
+
+    #[test]
+    fn test_token_is_url_safe() {
+        let t = token(32).unwrap();
+        assert_eq!(t.len(), 43);
+        assert!(
+            t.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+    }
+
+    #[test]
+    fn test_uuid_v4_format() {
+        let uuid = uuid_v4().unwrap();
+        let parts: Vec<&str> = uuid.split('-').collect();
+        assert_eq!(
+            parts.iter().map(|p| p.len()).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert!(parts[2].starts_with('4'));
+        assert!(matches!(&parts[3][..1], "8" | "9" | "a" | "b"));
+        assert_ne!(uuid, uuid_v4().unwrap());
+    }
+
+    #[test]
+    fn test_password_contains_every_class() {
+        let classes = CharClasses::default();
+        for _ in 0..20 {
+            let p = password(8, &classes).unwrap();
+            assert_eq!(p.value.chars().count(), 8);
+            assert!(p.value.chars().any(|c| c.is_ascii_lowercase()));
+            assert!(p.value.chars().any(|c| c.is_ascii_uppercase()));
+            assert!(p.value.chars().any(|c| c.is_ascii_digit()));
+            assert!(p.value.chars().any(|c| SYMBOLS.contains(c)));
+        }
+    }
+
+    #[test]
+    fn test_password_entropy_and_classes() {
+        let classes: CharClasses = "digits".parse().unwrap();
+        let p = password(10, &classes).unwrap();
+        assert!(p.value.chars().all(|c| c.is_ascii_digit()));
+        assert!((p.entropy_bits - 10.0 * 10f64.log2()).abs() < 1e-9);
+
+        let classes: CharClasses = "lower,upper,digits,no-ambiguous".parse().unwrap();
+        let p = password(64, &classes).unwrap();
+        assert!(!p.value.chars().any(|c| AMBIGUOUS.contains(c)));
+
+        assert!("lower,emoji".parse::<CharClasses>().is_err());
+        assert!(password(10, &"".parse().unwrap()).is_err());
+        assert!(password(2, &CharClasses::default()).is_err());
+    }
+
+    #[test]
+    fn test_passphrase() {
+        let words = ["alpha", "bravo", "charlie", "delta", "alpha"];
+        let p = passphrase(5, &words, "-").unwrap();
+        let picked: Vec<&str> = p.value.split('-').collect();
+        assert_eq!(picked.len(), 5);
+        assert!(picked.iter().all(|w| words.contains(w)));
+        // duplicates do not add entropy
+        assert!((p.entropy_bits - 5.0 * 4f64.log2()).abs() < 1e-9);
+
+        assert!(passphrase(3, &["only"], " ").is_err());
+    }
+
+    #[test]
+    fn test_load_wordlist_eff_format() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("words.txt");
+        fs::write(&path, "11111\tabacus\n11112\tabdomen\n\nplain\n").unwrap();
+        assert_eq!(
+            load_wordlist(&path).unwrap(),
+            vec!["abacus", "abdomen", "plain"]
+        );
+    }
+}
@@ -2,68 +2,138 @@ use std::borrow::Cow;
 
 use anyhow::{Result, anyhow};
 use base64::{Engine as _, engine::general_purpose};
-use openssl::pkcs5;
 use openssl::rand::rand_bytes;
 use openssl::symm::{Cipher, decrypt, encrypt};
 
+use crate::aead::{self, AeadCipher};
+use crate::kdf::{self, Kdf, KdfLimits, Scrypt};
+
 const SALT_LEN: usize = 16;
 const IV_LEN: usize = 16;
 const KEY_LEN: usize = 32;
 
-// scrypt parameters (adjust for your threat model)
-const SCRYPT_N: u64 = 16384;
-const SCRYPT_R: u64 = 8;
-const SCRYPT_P: u64 = 1;
-const SCRYPT_MAXMEM: u64 = 512 * 1024 * 1024; // 512 MB Max Memory
+/// Derive a 32-byte key with the given KDF
+fn derive_key<'a>(kdf: &dyn Kdf, password: Cow<'a, str>, salt: &[u8]) -> Result<Vec<u8>> {
+    kdf::ensure_password_kdf(kdf.algorithm())?;
+    let mut key = vec![0u8; KEY_LEN];
+    kdf.derive(password.as_bytes(), salt, &mut key)?;
+    log::debug!("Derived key with {}", kdf.algorithm());
+    Ok(key)
+}
 
 /// Derive a 32-byte key using OpenSSL scrypt
 fn derive_key_scrypt<'a>(password: Cow<'a, str>, salt: &[u8]) -> Result<Vec<u8>> {
-    let mut key = vec![0u8; KEY_LEN];
-    // pkcs5::scrypt returns Result<(), ErrorStack> and fills key slice
-    pkcs5::scrypt(
-        password.as_bytes(),
-        salt,
-        SCRYPT_N,
-        SCRYPT_R,
-        SCRYPT_P,
-        SCRYPT_MAXMEM,
-        &mut key,
-    )?;
-    log::info!(
-        "Derived key (hex): {}",
-        key.iter()
-            .map(|b| format!("{b:02x}"))
-            .collect::<Vec<_>>()
-            .join(" ")
-    );
-    Ok(key)
+    derive_key(&Scrypt::default(), password, salt)
 }
 
 /// Encrypt plaintext with password -> returns Base64(salt || iv || ciphertext)
 pub fn encrypt_base64<'a>(plaintext: &[u8], password: Cow<'a, str>) -> Result<Cow<'a, str>> {
-    // generate salt and iv
     let mut salt = [0u8; SALT_LEN];
-    let mut iv = [0u8; IV_LEN];
     rand_bytes(&mut salt)?;
-    rand_bytes(&mut iv)?;
-
     let key = derive_key_scrypt(password, &salt)?;
 
-    let cipher = Cipher::aes_256_cbc();
-    let ciphertext = encrypt(cipher, &key, Some(&iv), plaintext)?;
+    encrypt_packed(plaintext, &key, Vec::new(), &salt)
+}
+
+/// Encrypt plaintext with password using the given KDF
+/// -> returns Base64(kdf header || salt || iv || ciphertext)
+pub fn encrypt_base64_with_kdf<'a>(
+    plaintext: &[u8],
+    password: Cow<'a, str>,
+    kdf: &dyn Kdf,
+) -> Result<Cow<'a, str>> {
+    let header = kdf::encode_header(kdf)?;
+    let mut salt = [0u8; SALT_LEN];
+    rand_bytes(&mut salt)?;
+    let key = derive_key(kdf, password, &salt)?;
 
-    // pack salt || iv || ciphertext
-    let mut packed = Vec::with_capacity(SALT_LEN + IV_LEN + ciphertext.len());
+    encrypt_packed(plaintext, &key, header, &salt)
+}
+
+/// Encrypt plaintext with password using the given KDF and AEAD cipher
+/// -> returns Base64(cipher header || kdf header || salt || nonce || ciphertext || tag)
+pub fn encrypt_base64_with_cipher<'a>(
+    plaintext: &[u8],
+    password: Cow<'a, str>,
+    kdf: &dyn Kdf,
+    cipher: AeadCipher,
+) -> Result<Cow<'a, str>> {
+    let kdf_header = kdf::encode_header(kdf)?;
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = vec![0u8; cipher.nonce_len()];
+    rand_bytes(&mut salt)?;
+    rand_bytes(&mut nonce)?;
+    let key = derive_key(kdf, password, &salt)?;
+    let sealed = aead::seal(cipher, &key, &nonce, plaintext)?;
+
+    let mut packed = aead::encode_header(cipher);
+    packed.extend_from_slice(&kdf_header);
     packed.extend_from_slice(&salt);
+    packed.extend_from_slice(&nonce);
+    packed.extend_from_slice(&sealed);
+
+    Ok(general_purpose::STANDARD.encode(&packed).into())
+}
+
+fn encrypt_packed<'a>(
+    plaintext: &[u8],
+    key: &[u8],
+    header: Vec<u8>,
+    salt: &[u8],
+) -> Result<Cow<'a, str>> {
+    let mut iv = [0u8; IV_LEN];
+    rand_bytes(&mut iv)?;
+
+    let cipher = Cipher::aes_256_cbc();
+    let ciphertext = encrypt(cipher, key, Some(&iv), plaintext)?;
+
+    // pack header || salt || iv || ciphertext
+    let mut packed = header;
+    packed.reserve(SALT_LEN + IV_LEN + ciphertext.len());
+    packed.extend_from_slice(salt);
     packed.extend_from_slice(&iv);
     packed.extend_from_slice(&ciphertext);
 
     Ok(general_purpose::STANDARD.encode(&packed).into())
 }
 
-/// Decrypt Base64(salt || iv || ciphertext) with password -> returns plaintext bytes
+/// Decrypt Base64([kdf header] || salt || iv || ciphertext) with password -> returns plaintext bytes.
+/// Input without a KDF header is derived with the default scrypt parameters.
+/// Input starting with a cipher header is decrypted with that AEAD cipher instead of AES-256-CBC.
 pub fn decrypt_base64<'a>(b64: Cow<'a, str>, password: Cow<'a, str>) -> Result<Vec<u8>> {
-    let raw = general_purpose::STANDARD.decode(b64.as_bytes())?;
+    decrypt_base64_with_limits(b64, password, &KdfLimits::MAX)
+}
+
+/// [`decrypt_base64`] refusing KDF headers that cost more than `limits`
+pub fn decrypt_base64_with_limits<'a>(
+    b64: Cow<'a, str>,
+    password: Cow<'a, str>,
+    limits: &KdfLimits,
+) -> Result<Vec<u8>> {
+    let decoded = general_purpose::STANDARD.decode(b64.as_bytes())?;
+
+    let (cipher, cipher_header_len) = match aead::decode_header(&decoded)? {
+        Some((cipher, len)) => (Some(cipher), len),
+        None => (None, 0),
+    };
+    let decoded = &decoded[cipher_header_len..];
+    let (kdf, header_len): (Box<dyn Kdf>, usize) =
+        match kdf::decode_header_with_limits(decoded, limits)? {
+            Some(header) => header,
+            None => (Box::new(Scrypt::default()), 0),
+        };
+    let raw = &decoded[header_len..];
+
+    if let Some(cipher) = cipher {
+        let nonce_len = cipher.nonce_len();
+        if raw.len() < SALT_LEN + nonce_len + aead::TAG_LEN {
+            return Err(anyhow!("input too short"));
+        }
+        let salt = &raw[..SALT_LEN];
+        let nonce = &raw[SALT_LEN..SALT_LEN + nonce_len];
+        let key = derive_key(kdf.as_ref(), password, salt)?;
+        return aead::open(cipher, &key, nonce, &raw[SALT_LEN + nonce_len..]);
+    }
 
     if raw.len() < SALT_LEN + IV_LEN {
         return Err(anyhow!("input too short"));
@@ -73,7 +143,7 @@ pub fn decrypt_base64<'a>(b64: Cow<'a, str>, password: Cow<'a, str>) -> Result<V
     let iv = &raw[SALT_LEN..SALT_LEN + IV_LEN];
     let ciphertext = &raw[SALT_LEN + IV_LEN..];
 
-    let key = derive_key_scrypt(password, salt)?;
+    let key = derive_key(kdf.as_ref(), password, salt)?;
 
     let cipher = Cipher::aes_256_cbc();
     let plaintext = decrypt(cipher, &key, Some(iv), ciphertext)?;
@@ -249,4 +319,100 @@ mod tests {
         assert_eq!(k1, k2, "scrypt must be deterministic");
         assert_eq!(k1.len(), KEY_LEN);
     }
+
+    //
+    // ────────────────────────────────────────────────
+    //  KDF SELECTION
+    // ────────────────────────────────────────────────
+    //
+
+    #[test]
+    fn test_encrypt_decrypt_with_each_kdf() {
+        use crate::kdf::{Argon2id, HkdfSha256, Pbkdf2};
+
+        let kdfs: Vec<Box<dyn Kdf>> = vec![
+            Box::new(Scrypt::default()),
+            Box::new(Pbkdf2 {
+                sha512: false,
+                iterations: 1000,
+            }),
+            Box::new(Pbkdf2 {
+                sha512: true,
+                iterations: 1000,
+            }),
+            Box::new(Argon2id {
+                iterations: 1,
+                memory_kib: 1024,
+                lanes: 1,
+            }),
+        ];
+
+        for kdf in kdfs {
+            let enc = encrypt_base64_with_kdf(
+                b"kdf payload",
+                std::borrow::Cow::Borrowed(PASSWORD),
+                kdf.as_ref(),
+            )
+            .unwrap();
+            let raw = general_purpose::STANDARD.decode(enc.as_bytes()).unwrap();
+            assert!(raw.starts_with(kdf::HEADER_MAGIC));
+            assert_eq!(raw[kdf::HEADER_MAGIC.len()], kdf.algorithm() as u8);
+
+            let dec = decrypt_base64(enc, std::borrow::Cow::Borrowed(PASSWORD)).unwrap();
+            assert_eq!(
+                dec,
+                b"kdf payload",
+                "roundtrip failed for {}",
+                kdf.algorithm()
+            );
+        }
+
+        let hkdf = HkdfSha256::default();
+        assert!(
+            encrypt_base64_with_kdf(b"kdf payload", std::borrow::Cow::Borrowed(PASSWORD), &hkdf)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_kdf_header_wrong_password_fails() {
+        let kdf = crate::kdf::Pbkdf2 {
+            sha512: false,
+            iterations: 1000,
+        };
+        let enc =
+            encrypt_base64_with_kdf(b"secret", std::borrow::Cow::Borrowed(PASSWORD), &kdf).unwrap();
+        let dec = decrypt_base64(enc, std::borrow::Cow::Borrowed("wrong-pass"));
+        assert!(dec.is_err());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_with_each_cipher() {
+        let kdf = crate::kdf::Pbkdf2 {
+            sha512: false,
+            iterations: 1000,
+        };
+        for cipher in AeadCipher::ALL {
+            let enc = encrypt_base64_with_cipher(
+                b"cipher payload",
+                std::borrow::Cow::Borrowed(PASSWORD),
+                &kdf,
+                cipher,
+            )
+            .unwrap();
+            let mut raw = general_purpose::STANDARD.decode(enc.as_bytes()).unwrap();
+            assert!(raw.starts_with(aead::HEADER_MAGIC));
+            assert_eq!(raw[aead::HEADER_MAGIC.len()], cipher as u8);
+            assert!(raw[aead::HEADER_LEN..].starts_with(kdf::HEADER_MAGIC));
+
+            let dec = decrypt_base64(enc, std::borrow::Cow::Borrowed(PASSWORD)).unwrap();
+            assert_eq!(dec, b"cipher payload", "roundtrip failed for {cipher}");
+
+            // AEAD ciphertexts are authenticated, so any bit flip must be rejected
+            let last = raw.len() - 1;
+            raw[last] ^= 1;
+            let tampered = general_purpose::STANDARD.encode(&raw);
+            assert!(decrypt_base64(tampered.into(), std::borrow::Cow::Borrowed(PASSWORD)).is_err());
+        }
+    }
 }
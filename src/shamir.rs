@@ -0,0 +1,258 @@
+use anyhow::{Result, anyhow};
+use openssl::hash::{MessageDigest, hash};
+use openssl::memcmp;
+use openssl::rand::rand_bytes;
+
+use crate::encoding::Encoding;
+
+const SHARE_VERSION: u8 = 1;
+const CHECKSUM_LEN: usize = 4;
+// version || threshold || index
+const SHARE_HEADER_LEN: usize = 3;
+
+/// Multiplication in GF(2^8) modulo the AES polynomial x^8 + x^4 + x^3 + x + 1.
+/// Branch-free so the timing does not depend on secret bytes.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse, a^254 = a^-1 for a != 0
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// Evaluate the polynomial with `coefficients` (constant term first) at `x`
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0u8, |acc, &c| gf_mul(acc, x) ^ c)
+}
+
+/// One share of a split secret
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    /// x coordinate, 1..=255
+    pub index: u8,
+    /// Number of shares needed to recover the secret
+    pub threshold: u8,
+    pub value: Vec<u8>,
+}
+
+fn checksum(data: &[u8]) -> Result<[u8; CHECKSUM_LEN]> {
+    let digest = hash(MessageDigest::sha256(), data)?;
+    let mut sum = [0u8; CHECKSUM_LEN];
+    sum.copy_from_slice(&digest[..CHECKSUM_LEN]);
+    Ok(sum)
+}
+
+impl Share {
+    /// Serialized form: version || threshold || index || value || SHA-256 checksum (4 bytes)
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(SHARE_HEADER_LEN + self.value.len() + CHECKSUM_LEN);
+        bytes.extend_from_slice(&[SHARE_VERSION, self.threshold, self.index]);
+        bytes.extend_from_slice(&self.value);
+        let sum = checksum(&bytes)?;
+        bytes.extend_from_slice(&sum);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() <= SHARE_HEADER_LEN + CHECKSUM_LEN {
+            return Err(anyhow!("share is too short"));
+        }
+        let (body, sum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if !memcmp::eq(&checksum(body)?, sum) {
+            return Err(anyhow!("share checksum mismatch"));
+        }
+        if body[0] != SHARE_VERSION {
+            return Err(anyhow!("unsupported share version {}", body[0]));
+        }
+        let share = Share {
+            threshold: body[1],
+            index: body[2],
+            value: body[SHARE_HEADER_LEN..].to_vec(),
+        };
+        if share.index == 0 || share.threshold < 2 {
+            return Err(anyhow!("malformed share"));
+        }
+        Ok(share)
+    }
+
+    pub fn encode(&self, encoding: Encoding) -> Result<String> {
+        Ok(encoding.encode(&self.to_bytes()?))
+    }
+
+    pub fn decode(text: &str, encoding: Encoding) -> Result<Self> {
+        Share::from_bytes(&encoding.decode(text)?)
+    }
+}
+
+/// Split `secret` into `shares` shares, any `threshold` of which recover it
+pub fn split(secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<Share>> {
+    if secret.is_empty() {
+        return Err(anyhow!("secret must not be empty"));
+    }
+    if threshold < 2 {
+        return Err(anyhow!("threshold must be at least 2"));
+    }
+    if shares < threshold {
+        return Err(anyhow!("number of shares must be at least the threshold"));
+    }
+
+    let mut out: Vec<Share> = (1..=shares)
+        .map(|index| Share {
+            index,
+            threshold,
+            value: Vec::with_capacity(secret.len()),
+        })
+        .collect();
+
+    // One random polynomial of degree threshold - 1 per secret byte
+    let mut coefficients = vec![0u8; threshold as usize];
+    for &byte in secret {
+        coefficients[0] = byte;
+        rand_bytes(&mut coefficients[1..])?;
+        for share in out.iter_mut() {
+            share.value.push(evaluate(&coefficients, share.index));
+        }
+    }
+    coefficients.fill(0);
+    Ok(out)
+}
+
+/// Recover the secret from at least `threshold` distinct shares
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>> {
+    let first = shares.first().ok_or_else(|| anyhow!("no shares given"))?;
+    if shares.len() < first.threshold as usize {
+        return Err(anyhow!(
+            "{} shares are required, got {}",
+            first.threshold,
+            shares.len()
+        ));
+    }
+    for (i, share) in shares.iter().enumerate() {
+        if share.threshold != first.threshold || share.value.len() != first.value.len() {
+            return Err(anyhow!("shares belong to different secrets"));
+        }
+        if shares[..i].iter().any(|other| other.index == share.index) {
+            return Err(anyhow!("duplicate share index {}", share.index));
+        }
+    }
+
+    // Lagrange interpolation at x = 0 over the first `threshold` shares
+    let used = &shares[..first.threshold as usize];
+    let weights: Vec<u8> = used
+        .iter()
+        .map(|share| {
+            let (num, den) = used.iter().filter(|other| other.index != share.index).fold(
+                (1u8, 1u8),
+                |(num, den), other| {
+                    (
+                        gf_mul(num, other.index),
+                        gf_mul(den, other.index ^ share.index),
+                    )
+                },
+            );
+            gf_mul(num, gf_inv(den))
+        })
+        .collect();
+
+    Ok((0..first.value.len())
+        .map(|i| {
+            used.iter()
+                .zip(&weights)
+                .fold(0u8, |acc, (share, &w)| acc ^ gf_mul(share.value[i], w))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gf_arithmetic() {
+        // FIPS 197 section 4.2 example
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_split_and_combine_any_subset() {
+        let secret = b"correct horse battery staple";
+        let shares = split(secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[c].clone(), shares[a].clone(), shares[b].clone()];
+                    assert_eq!(combine(&subset).unwrap(), secret);
+                }
+            }
+        }
+        assert_eq!(combine(&shares).unwrap(), secret);
+    }
+
+    #[test]
+    fn test_below_threshold_fails() {
+        let shares = split(b"secret", 3, 5).unwrap();
+        assert!(combine(&shares[..2]).is_err());
+        assert!(combine(&[]).is_err());
+        assert!(combine(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(split(b"", 2, 3).is_err());
+        assert!(split(b"secret", 1, 3).is_err());
+        assert!(split(b"secret", 4, 3).is_err());
+    }
+
+    #[test]
+    fn test_share_encoding_roundtrip() {
+        let shares = split(b"master passphrase", 2, 3).unwrap();
+        for encoding in [Encoding::Base52, Encoding::Base64] {
+            let encoded: Vec<String> = shares
+                .iter()
+                .map(|share| share.encode(encoding).unwrap())
+                .collect();
+            let decoded: Vec<Share> = encoded
+                .iter()
+                .map(|text| Share::decode(text, encoding).unwrap())
+                .collect();
+            assert_eq!(decoded, shares);
+            assert_eq!(combine(&decoded[1..]).unwrap(), b"master passphrase");
+        }
+    }
+
+    #[test]
+    fn test_checksum_detects_corruption() {
+        let share = split(b"secret", 2, 2).unwrap().remove(0);
+        let mut bytes = share.to_bytes().unwrap();
+        bytes[4] ^= 0x01;
+        assert!(Share::from_bytes(&bytes).is_err());
+        assert!(Share::from_bytes(&bytes[..5]).is_err());
+    }
+}
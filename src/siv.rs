@@ -0,0 +1,239 @@
+use anyhow::{Result, anyhow};
+use openssl::memcmp;
+use openssl::pkey::{PKey, Private};
+use openssl::rand::rand_bytes;
+use openssl::sign::Signer;
+use openssl::symm::{self, Cipher};
+
+/// Length of the synthetic IV that prefixes every ciphertext
+pub const SIV_LEN: usize = 16;
+/// Key length of AES-256-SIV, made of a 32-byte MAC key and a 32-byte CTR key
+pub const KEY_LEN: usize = 64;
+/// RFC 5297 section 7: S2V takes at most 127 strings, the last one being the plaintext
+const MAX_ASSOCIATED_DATA: usize = 126;
+
+/// CMAC keyed with the first half of a SIV key
+struct Cmac {
+    key: PKey<Private>,
+}
+
+impl Cmac {
+    fn new(key: &[u8]) -> Result<Self> {
+        let cipher = match key.len() {
+            16 => Cipher::aes_128_cbc(),
+            24 => Cipher::aes_192_cbc(),
+            32 => Cipher::aes_256_cbc(),
+            _ => return Err(anyhow!("invalid SIV key length")),
+        };
+        Ok(Self {
+            key: PKey::cmac(&cipher, key)?,
+        })
+    }
+
+    fn mac(&self, parts: &[&[u8]]) -> Result<[u8; 16]> {
+        let mut signer = Signer::new_without_digest(&self.key)?;
+        for part in parts {
+            signer.update(part)?;
+        }
+        let mut out = [0u8; 16];
+        signer.sign(&mut out)?;
+        Ok(out)
+    }
+}
+
+/// Doubling in GF(2^128) as defined in RFC 5297 section 2.3
+fn dbl(block: &mut [u8; 16]) {
+    let carry = block[0] >> 7;
+    for i in 0..15 {
+        block[i] = (block[i] << 1) | (block[i + 1] >> 7);
+    }
+    block[15] = (block[15] << 1) ^ (0x87 * carry);
+}
+
+fn xor(block: &mut [u8; 16], other: &[u8]) {
+    block.iter_mut().zip(other).for_each(|(b, o)| *b ^= o);
+}
+
+/// S2V of RFC 5297 section 2.4 over the associated data strings and the plaintext
+fn s2v(cmac: &Cmac, associated_data: &[&[u8]], plaintext: &[u8]) -> Result<[u8; 16]> {
+    let mut d = cmac.mac(&[&[0u8; 16]])?;
+    for data in associated_data {
+        dbl(&mut d);
+        xor(&mut d, &cmac.mac(&[data])?);
+    }
+    if plaintext.len() >= 16 {
+        // T = Sn xorend D
+        let (head, tail) = plaintext.split_at(plaintext.len() - 16);
+        xor(&mut d, tail);
+        cmac.mac(&[head, &d])
+    } else {
+        // T = dbl(D) xor pad(Sn)
+        dbl(&mut d);
+        let mut padded = [0u8; 16];
+        padded[..plaintext.len()].copy_from_slice(plaintext);
+        padded[plaintext.len()] = 0x80;
+        xor(&mut d, &padded);
+        cmac.mac(&[&d])
+    }
+}
+
+/// AES-CTR keyed with the second half of a SIV key, starting at the cleared synthetic IV
+fn ctr(key: &[u8], siv: &[u8; 16], data: &[u8]) -> Result<Vec<u8>> {
+    let cipher = match key.len() {
+        16 => Cipher::aes_128_ctr(),
+        24 => Cipher::aes_192_ctr(),
+        32 => Cipher::aes_256_ctr(),
+        _ => return Err(anyhow!("invalid SIV key length")),
+    };
+    let mut counter = *siv;
+    counter[8] &= 0x7f;
+    counter[12] &= 0x7f;
+    Ok(symm::encrypt(cipher, key, Some(&counter), data)?)
+}
+
+fn split_key(key: &[u8]) -> Result<(&[u8], &[u8])> {
+    match key.len() {
+        32 | 48 | 64 => Ok(key.split_at(key.len() / 2)),
+        n => Err(anyhow!("AES-SIV key must be 32, 48 or 64 bytes, got {n}")),
+    }
+}
+
+fn check_associated_data(associated_data: &[&[u8]]) -> Result<()> {
+    if associated_data.len() > MAX_ASSOCIATED_DATA {
+        return Err(anyhow!(
+            "at most {MAX_ASSOCIATED_DATA} associated data strings are supported"
+        ));
+    }
+    Ok(())
+}
+
+/// Random AES-256-SIV key
+pub fn generate_key() -> Result<Vec<u8>> {
+    let mut key = vec![0u8; KEY_LEN];
+    rand_bytes(&mut key)?;
+    Ok(key)
+}
+
+/// DETERMINISTIC AES-SIV encryption (RFC 5297) -> synthetic IV(16 bytes) || ciphertext.
+///
+/// The same key, associated data and plaintext always give the same output, which makes
+/// equality searches on encrypted fields possible but also reveals which values are equal.
+/// Only use it where that is intended; otherwise use a randomized mode.
+/// Each associated data string is authenticated as a separate component.
+pub fn encrypt_deterministic(
+    key: &[u8],
+    plaintext: &[u8],
+    associated_data: &[&[u8]],
+) -> Result<Vec<u8>> {
+    let (mac_key, ctr_key) = split_key(key)?;
+    check_associated_data(associated_data)?;
+    let siv = s2v(&Cmac::new(mac_key)?, associated_data, plaintext)?;
+    let mut out = siv.to_vec();
+    out.extend_from_slice(&ctr(ctr_key, &siv, plaintext)?);
+    Ok(out)
+}
+
+/// Decrypt the output of [`encrypt_deterministic`], failing when the synthetic IV does not
+/// authenticate the plaintext and associated data
+pub fn decrypt_deterministic(
+    key: &[u8],
+    sealed: &[u8],
+    associated_data: &[&[u8]],
+) -> Result<Vec<u8>> {
+    let (mac_key, ctr_key) = split_key(key)?;
+    check_associated_data(associated_data)?;
+    if sealed.len() < SIV_LEN {
+        return Err(anyhow!("input too short"));
+    }
+    let mut siv = [0u8; SIV_LEN];
+    siv.copy_from_slice(&sealed[..SIV_LEN]);
+    let mut plaintext = ctr(ctr_key, &siv, &sealed[SIV_LEN..])?;
+    let expected = s2v(&Cmac::new(mac_key)?, associated_data, &plaintext)?;
+    if !memcmp::eq(&expected, &siv) {
+        plaintext.fill(0);
+        return Err(anyhow!("Decryption failed: authentication failed"));
+    }
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn h(s: &str) -> Vec<u8> {
+        hex::decode(s.replace(' ', "")).unwrap()
+    }
+
+    #[test]
+    fn test_rfc5297_deterministic_vector() {
+        // RFC 5297 appendix A.1
+        let key = h("fffefdfc fbfaf9f8 f7f6f5f4 f3f2f1f0 f0f1f2f3 f4f5f6f7 f8f9fafb fcfdfeff");
+        let ad = h("10111213 14151617 18191a1b 1c1d1e1f 20212223 24252627");
+        let plaintext = h("11223344 55667788 99aabbcc ddee");
+        let expected = h("85632d07 c6e8f37f 950acd32 0a2ecc93 40c02b96 90c4dc04 daef7f6a fe5c");
+
+        assert_eq!(
+            encrypt_deterministic(&key, &plaintext, &[&ad]).unwrap(),
+            expected
+        );
+        assert_eq!(
+            decrypt_deterministic(&key, &expected, &[&ad]).unwrap(),
+            plaintext
+        );
+    }
+
+    #[test]
+    fn test_rfc5297_nonce_based_vector() {
+        // RFC 5297 appendix A.2, with the nonce as the last associated data string
+        let key = h("7f7e7d7c 7b7a7978 77767574 73727170 40414243 44454647 48494a4b 4c4d4e4f");
+        let ad1 = h(
+            "00112233 44556677 8899aabb ccddeeff deaddada deaddada ffeeddcc bbaa9988 \
+             77665544 33221100",
+        );
+        let ad2 = h("10203040 50607080 90a0");
+        let nonce = h("09f91102 9d74e35b d84156c5 635688c0");
+        let plaintext = h(
+            "74686973 20697320 736f6d65 20706c61 696e7465 78742074 6f20656e 63727970 \
+             74207573 696e6720 5349562d 414553",
+        );
+        let expected = h(
+            "7bdb6e3b 432667eb 06f4d14b ff2fbd0f cb900f2f ddbe4043 26601965 c889bf17 \
+             dba77ceb 094fa663 b7a3f748 ba8af829 ea64ad54 4a272e9c 485b62a3 fd5c0d",
+        );
+        let ad: [&[u8]; 3] = [&ad1, &ad2, &nonce];
+        assert_eq!(
+            encrypt_deterministic(&key, &plaintext, &ad).unwrap(),
+            expected
+        );
+        assert_eq!(
+            decrypt_deterministic(&key, &expected, &ad).unwrap(),
+            plaintext
+        );
+    }
+
+    #[test]
+    fn test_deterministic_and_authenticated() {
+        let key = generate_key().unwrap();
+        let first = encrypt_deterministic(&key, b"alice@example.com", &[b"users.email"]).unwrap();
+        let second = encrypt_deterministic(&key, b"alice@example.com", &[b"users.email"]).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(
+            first,
+            encrypt_deterministic(&key, b"alice@example.com", &[b"users.backup"]).unwrap()
+        );
+        assert_eq!(
+            decrypt_deterministic(&key, &first, &[b"users.email"]).unwrap(),
+            b"alice@example.com"
+        );
+
+        assert!(decrypt_deterministic(&key, &first, &[b"users.backup"]).is_err());
+        let mut tampered = first.clone();
+        tampered[SIV_LEN] ^= 1;
+        assert!(decrypt_deterministic(&key, &tampered, &[b"users.email"]).is_err());
+
+        let empty = encrypt_deterministic(&key, b"", &[]).unwrap();
+        assert_eq!(empty.len(), SIV_LEN);
+        assert_eq!(decrypt_deterministic(&key, &empty, &[]).unwrap(), b"");
+        assert!(encrypt_deterministic(&key[..16], b"x", &[]).is_err());
+    }
+}
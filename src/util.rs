@@ -1,4 +1,9 @@
-use std::{error::Error, fs, path::Path};
+use std::{
+    error::Error,
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+};
 
 pub fn data_source(input: &str) -> Result<Vec<u8>, Box<dyn Error>> {
     let path = Path::new(input);
@@ -13,6 +18,18 @@ pub fn data_source(input: &str) -> Result<Vec<u8>, Box<dyn Error>> {
     Ok(data)
 }
 
+/// Passphrase given on the command line, or read from stdin when it is `-`
+pub fn passphrase_arg(arg: &str, prompt: &str) -> Result<String, Box<dyn Error>> {
+    if arg != "-" {
+        return Ok(arg.to_string());
+    }
+    eprint!("{prompt}: ");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,522 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow};
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{HasPublic, Id, PKeyRef, Private};
+use openssl::stack::Stack;
+use openssl::x509::extension::{
+    AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+    SubjectKeyIdentifier,
+};
+use openssl::x509::{X509, X509Builder, X509Name, X509NameRef, X509Ref, X509Req, X509ReqBuilder};
+
+use crate::keys;
+
+pub const DEFAULT_DAYS: u32 = 365;
+pub const DEFAULT_CA_DAYS: u32 = 3650;
+// Random serial numbers, kept below 20 octets as required by RFC 5280
+const SERIAL_BITS: i32 = 159;
+
+/// Subject alternative name entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubjectAltName {
+    Dns(String),
+    Ip(IpAddr),
+    Email(String),
+    Uri(String),
+}
+
+impl fmt::Display for SubjectAltName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubjectAltName::Dns(name) => write!(f, "DNS:{name}"),
+            SubjectAltName::Ip(ip) => write!(f, "IP:{ip}"),
+            SubjectAltName::Email(email) => write!(f, "email:{email}"),
+            SubjectAltName::Uri(uri) => write!(f, "URI:{uri}"),
+        }
+    }
+}
+
+/// Parses `DNS:host`, `IP:addr`, `email:addr` or `URI:uri`. Without a prefix,
+/// IP addresses become IP entries and anything else a DNS name.
+impl FromStr for SubjectAltName {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let san = match s.split_once(':') {
+            Some((kind, value)) if kind.eq_ignore_ascii_case("dns") => {
+                SubjectAltName::Dns(value.to_string())
+            }
+            Some((kind, value)) if kind.eq_ignore_ascii_case("ip") => {
+                SubjectAltName::Ip(value.parse()?)
+            }
+            Some((kind, value)) if kind.eq_ignore_ascii_case("email") => {
+                SubjectAltName::Email(value.to_string())
+            }
+            Some((kind, value)) if kind.eq_ignore_ascii_case("uri") => {
+                SubjectAltName::Uri(value.to_string())
+            }
+            _ => match s.parse::<IpAddr>() {
+                Ok(ip) => SubjectAltName::Ip(ip),
+                Err(_) => SubjectAltName::Dns(s.to_string()),
+            },
+        };
+        Ok(san)
+    }
+}
+
+/// Subject and validity of a certificate or request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertParams {
+    pub common_name: String,
+    pub organization: Option<String>,
+    pub sans: Vec<SubjectAltName>,
+    pub days: u32,
+}
+
+impl CertParams {
+    pub fn new(common_name: &str) -> Self {
+        Self {
+            common_name: common_name.to_string(),
+            organization: None,
+            sans: Vec::new(),
+            days: DEFAULT_DAYS,
+        }
+    }
+
+    fn name(&self) -> Result<X509Name> {
+        let mut name = X509Name::builder()?;
+        if let Some(organization) = &self.organization {
+            name.append_entry_by_nid(Nid::ORGANIZATIONNAME, organization)?;
+        }
+        name.append_entry_by_nid(Nid::COMMONNAME, &self.common_name)?;
+        Ok(name.build())
+    }
+
+    /// Leaf certificates always carry a SAN, falling back to the common name
+    fn leaf_sans(&self) -> Result<Vec<SubjectAltName>> {
+        if self.sans.is_empty() {
+            Ok(vec![self.common_name.parse()?])
+        } else {
+            Ok(self.sans.clone())
+        }
+    }
+}
+
+/// Ed25519 signs the message directly, everything else uses SHA-256
+fn digest_for<T>(key: &PKeyRef<T>) -> MessageDigest {
+    if key.id() == Id::ED25519 {
+        MessageDigest::null()
+    } else {
+        MessageDigest::sha256()
+    }
+}
+
+fn san_extension(
+    sans: &[SubjectAltName],
+    context: &openssl::x509::X509v3Context,
+) -> Result<openssl::x509::X509Extension> {
+    let mut ext = SubjectAlternativeName::new();
+    for san in sans {
+        match san {
+            SubjectAltName::Dns(name) => ext.dns(name),
+            SubjectAltName::Ip(ip) => ext.ip(&ip.to_string()),
+            SubjectAltName::Email(email) => ext.email(email),
+            SubjectAltName::Uri(uri) => ext.uri(uri),
+        };
+    }
+    Ok(ext.build(context)?)
+}
+
+fn random_serial() -> Result<Asn1Integer> {
+    let mut serial = BigNum::new()?;
+    serial.rand(SERIAL_BITS, MsbOption::MAYBE_ZERO, false)?;
+    Ok(serial.to_asn1_integer()?)
+}
+
+fn builder<T: HasPublic>(
+    subject: &X509NameRef,
+    issuer: &X509NameRef,
+    public_key: &PKeyRef<T>,
+    days: u32,
+) -> Result<X509Builder> {
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    let serial = random_serial()?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(subject)?;
+    builder.set_issuer_name(issuer)?;
+    builder.set_pubkey(public_key)?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(days)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    Ok(builder)
+}
+
+/// Self-signed leaf certificate for `key`
+pub fn self_signed(key: &PKeyRef<Private>, params: &CertParams) -> Result<X509> {
+    let name = params.name()?;
+    let mut builder = builder(&name, &name, key, params.days)?;
+    add_leaf_extensions(&mut builder, None, &params.leaf_sans()?)?;
+    builder.sign(key, digest_for(key))?;
+    Ok(builder.build())
+}
+
+/// Self-signed CA certificate that can issue leaf certificates
+pub fn create_ca(key: &PKeyRef<Private>, params: &CertParams) -> Result<X509> {
+    let name = params.name()?;
+    let mut builder = builder(&name, &name, key, params.days)?;
+    builder.append_extension(BasicConstraints::new().critical().ca().pathlen(0).build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .key_cert_sign()
+            .crl_sign()
+            .digital_signature()
+            .build()?,
+    )?;
+    let ski = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+    builder.append_extension(ski)?;
+    builder.sign(key, digest_for(key))?;
+    Ok(builder.build())
+}
+
+fn add_leaf_extensions(
+    builder: &mut X509Builder,
+    issuer: Option<&X509Ref>,
+    sans: &[SubjectAltName],
+) -> Result<()> {
+    builder.append_extension(BasicConstraints::new().critical().build()?)?;
+    builder.append_extension(
+        KeyUsage::new()
+            .critical()
+            .digital_signature()
+            .key_encipherment()
+            .build()?,
+    )?;
+    builder.append_extension(
+        ExtendedKeyUsage::new()
+            .server_auth()
+            .client_auth()
+            .build()?,
+    )?;
+    let san = san_extension(sans, &builder.x509v3_context(issuer, None))?;
+    builder.append_extension(san)?;
+    let ski = SubjectKeyIdentifier::new().build(&builder.x509v3_context(issuer, None))?;
+    builder.append_extension(ski)?;
+    if issuer.is_some() {
+        let aki = AuthorityKeyIdentifier::new()
+            .keyid(true)
+            .build(&builder.x509v3_context(issuer, None))?;
+        builder.append_extension(aki)?;
+    }
+    Ok(())
+}
+
+/// Leaf certificate for `public_key` signed by the CA
+pub fn issue<T: HasPublic>(
+    ca_cert: &X509Ref,
+    ca_key: &PKeyRef<Private>,
+    public_key: &PKeyRef<T>,
+    params: &CertParams,
+) -> Result<X509> {
+    if !ca_cert.public_key()?.public_eq(ca_key) {
+        return Err(anyhow!("CA key does not match the CA certificate"));
+    }
+    let subject = params.name()?;
+    let mut builder = builder(&subject, ca_cert.subject_name(), public_key, params.days)?;
+    add_leaf_extensions(&mut builder, Some(ca_cert), &params.leaf_sans()?)?;
+    builder.sign(ca_key, digest_for(ca_key))?;
+    Ok(builder.build())
+}
+
+/// Certificate signing request for `key`, including the SANs as a requested extension
+pub fn create_csr(key: &PKeyRef<Private>, params: &CertParams) -> Result<X509Req> {
+    let mut builder = X509ReqBuilder::new()?;
+    builder.set_version(0)?;
+    let subject = params.name()?;
+    builder.set_subject_name(&subject)?;
+    builder.set_pubkey(key)?;
+    if !params.sans.is_empty() {
+        let mut extensions = Stack::new()?;
+        extensions.push(san_extension(&params.sans, &builder.x509v3_context(None))?)?;
+        builder.add_extensions(&extensions)?;
+    }
+    builder.sign(key, digest_for(key))?;
+    Ok(builder.build())
+}
+
+/// Issue a leaf certificate for a CSR after checking its self-signature.
+/// Requested extensions are not copied, `sans` decides the SANs
+/// (the CSR common name when empty).
+pub fn sign_csr(
+    ca_cert: &X509Ref,
+    ca_key: &PKeyRef<Private>,
+    csr: &X509Req,
+    sans: &[SubjectAltName],
+    days: u32,
+) -> Result<X509> {
+    let public_key = csr.public_key()?;
+    if !csr.verify(&public_key)? {
+        return Err(anyhow!("CSR signature is invalid"));
+    }
+    let common_name = name_entry(csr.subject_name(), Nid::COMMONNAME)
+        .ok_or_else(|| anyhow!("CSR has no common name"))?;
+    let params = CertParams {
+        common_name,
+        organization: name_entry(csr.subject_name(), Nid::ORGANIZATIONNAME),
+        sans: sans.to_vec(),
+        days,
+    };
+    issue(ca_cert, ca_key, &public_key, &params)
+}
+
+fn name_entry(name: &X509NameRef, nid: Nid) -> Option<String> {
+    name.entries_by_nid(nid)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|s| s.to_string())
+}
+
+/// `CN=..., O=...` rendering of a distinguished name
+pub fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry
+                .data()
+                .as_utf8()
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+            format!("{key}={value}")
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Load a PEM or DER certificate
+pub fn load_cert(data: &[u8]) -> Result<X509> {
+    if data.trim_ascii_start().starts_with(b"-----BEGIN") {
+        Ok(X509::from_pem(data)?)
+    } else {
+        Ok(X509::from_der(data)?)
+    }
+}
+
+/// Load one or more concatenated PEM certificates, or a single DER certificate
+pub fn load_chain(data: &[u8]) -> Result<Vec<X509>> {
+    if data.trim_ascii_start().starts_with(b"-----BEGIN") {
+        Ok(X509::stack_from_pem(data)?)
+    } else {
+        Ok(vec![X509::from_der(data)?])
+    }
+}
+
+/// Load a PEM or DER certificate signing request
+pub fn load_csr(data: &[u8]) -> Result<X509Req> {
+    if data.trim_ascii_start().starts_with(b"-----BEGIN") {
+        Ok(X509Req::from_pem(data)?)
+    } else {
+        Ok(X509Req::from_der(data)?)
+    }
+}
+
+/// Human readable summary of a certificate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub key: String,
+    pub signature_algorithm: String,
+    pub sans: Vec<String>,
+    pub fingerprint: String,
+}
+
+impl CertInfo {
+    pub fn from_cert(cert: &X509Ref) -> Result<Self> {
+        let sans = cert
+            .subject_alt_names()
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|name| {
+                        if let Some(dns) = name.dnsname() {
+                            Some(format!("DNS:{dns}"))
+                        } else if let Some(ip) = name.ipaddress() {
+                            ip_from_bytes(ip).map(|ip| format!("IP:{ip}"))
+                        } else if let Some(email) = name.email() {
+                            Some(format!("email:{email}"))
+                        } else {
+                            name.uri().map(|uri| format!("URI:{uri}"))
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let digest = cert.digest(MessageDigest::sha256())?;
+        let public_key = cert.public_key()?;
+        Ok(CertInfo {
+            subject: format_name(cert.subject_name()),
+            issuer: format_name(cert.issuer_name()),
+            serial: cert.serial_number().to_bn()?.to_hex_str()?.to_string(),
+            not_before: cert.not_before().to_string(),
+            not_after: cert.not_after().to_string(),
+            key: keys::describe(&public_key)?,
+            signature_algorithm: cert
+                .signature_algorithm()
+                .object()
+                .nid()
+                .long_name()
+                .unwrap_or("unknown")
+                .to_string(),
+            sans,
+            fingerprint: digest
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(":"),
+        })
+    }
+}
+
+fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+impl fmt::Display for CertInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Subject:    {}", self.subject)?;
+        writeln!(f, "Issuer:     {}", self.issuer)?;
+        writeln!(f, "Serial:     {}", self.serial)?;
+        writeln!(f, "Not before: {}", self.not_before)?;
+        writeln!(f, "Not after:  {}", self.not_after)?;
+        writeln!(f, "Key:        {}", self.key)?;
+        writeln!(f, "Signature:  {}", self.signature_algorithm)?;
+        if !self.sans.is_empty() {
+            writeln!(f, "SANs:       {}", self.sans.join(", "))?;
+        }
+        write!(f, "SHA256:     {}", self.fingerprint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{Curve, KeySpec};
+    use openssl::x509::X509StoreContext;
+    use openssl::x509::store::X509StoreBuilder;
+
+    fn verify_chain(ca: &X509, leaf: &X509) -> bool {
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(ca.clone()).unwrap();
+        let store = store.build();
+        let mut context = X509StoreContext::new().unwrap();
+        context
+            .init(&store, leaf, &Stack::new().unwrap(), |c| c.verify_cert())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_san_parsing() {
+        assert_eq!(
+            "example.com".parse::<SubjectAltName>().unwrap(),
+            SubjectAltName::Dns("example.com".to_string())
+        );
+        assert_eq!(
+            "127.0.0.1".parse::<SubjectAltName>().unwrap().to_string(),
+            "IP:127.0.0.1"
+        );
+        assert_eq!(
+            "IP:::1".parse::<SubjectAltName>().unwrap().to_string(),
+            "IP:::1"
+        );
+        assert_eq!(
+            "email:ops@example.com".parse::<SubjectAltName>().unwrap(),
+            SubjectAltName::Email("ops@example.com".to_string())
+        );
+        assert!("IP:not-an-ip".parse::<SubjectAltName>().is_err());
+    }
+
+    #[test]
+    fn test_self_signed() {
+        let key = keys::generate(KeySpec::Ec(Curve::P256)).unwrap();
+        let mut params = CertParams::new("localhost");
+        params.organization = Some("Test Org".to_string());
+        params.sans = vec!["localhost".parse().unwrap(), "127.0.0.1".parse().unwrap()];
+        let cert = self_signed(&key, &params).unwrap();
+
+        assert!(cert.verify(&key).unwrap());
+        let info = CertInfo::from_cert(&cert).unwrap();
+        assert_eq!(info.subject, "O=Test Org, CN=localhost");
+        assert_eq!(info.subject, info.issuer);
+        assert_eq!(info.sans, vec!["DNS:localhost", "IP:127.0.0.1"]);
+        assert_eq!(info.key, "EC P-256");
+        assert_eq!(info.fingerprint.len(), 32 * 3 - 1);
+
+        let pem = cert.to_pem().unwrap();
+        let der = cert.to_der().unwrap();
+        assert_eq!(load_cert(&pem).unwrap().to_der().unwrap(), der);
+        assert_eq!(load_cert(&der).unwrap().to_der().unwrap(), der);
+    }
+
+    #[test]
+    fn test_ca_issues_verifiable_leaf() {
+        let ca_key = keys::generate(KeySpec::Ec(Curve::P384)).unwrap();
+        let mut ca_params = CertParams::new("Local Test CA");
+        ca_params.days = DEFAULT_CA_DAYS;
+        let ca = create_ca(&ca_key, &ca_params).unwrap();
+
+        let leaf_key = keys::generate(KeySpec::Rsa(2048)).unwrap();
+        let mut params = CertParams::new("api.test");
+        params.sans = vec!["api.test".parse().unwrap(), "*.api.test".parse().unwrap()];
+        let leaf = issue(&ca, &ca_key, &leaf_key, &params).unwrap();
+
+        assert!(verify_chain(&ca, &leaf));
+        let info = CertInfo::from_cert(&leaf).unwrap();
+        assert_eq!(info.issuer, "CN=Local Test CA");
+        assert_eq!(info.sans, vec!["DNS:api.test", "DNS:*.api.test"]);
+
+        // A self-signed leaf is not trusted by the CA store
+        let other = self_signed(&leaf_key, &params).unwrap();
+        assert!(!verify_chain(&ca, &other));
+
+        // Mismatched CA key is rejected
+        assert!(issue(&ca, &leaf_key, &leaf_key, &params).is_err());
+    }
+
+    #[test]
+    fn test_csr_roundtrip_and_signing() {
+        let ca_key = keys::generate(KeySpec::Ed25519).unwrap();
+        let ca = create_ca(&ca_key, &CertParams::new("Ed25519 CA")).unwrap();
+
+        let key = keys::generate(KeySpec::Ec(Curve::P256)).unwrap();
+        let mut params = CertParams::new("service.local");
+        params.sans = vec!["service.local".parse().unwrap()];
+        let csr = create_csr(&key, &params).unwrap();
+
+        let csr = load_csr(&csr.to_pem().unwrap()).unwrap();
+        assert!(csr.verify(&key).unwrap());
+        assert_eq!(format_name(csr.subject_name()), "CN=service.local");
+
+        let leaf = sign_csr(&ca, &ca_key, &csr, &[], DEFAULT_DAYS).unwrap();
+        assert!(verify_chain(&ca, &leaf));
+        assert!(leaf.public_key().unwrap().public_eq(&key));
+        assert_eq!(
+            CertInfo::from_cert(&leaf).unwrap().sans,
+            vec!["DNS:service.local"]
+        );
+    }
+}
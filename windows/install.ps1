@@ -45,6 +45,16 @@ Copy-Item "$releaseDir\scrypt-encrypt.exe" "$DEST_DIR"
 Copy-Item "$releaseDir\scrypt-decrypt.exe" "$DEST_DIR"
 Copy-Item "$releaseDir\encrypt-file.exe" "$DEST_DIR"
 Copy-Item "$releaseDir\decrypt-file.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\kdf-bench.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\hashsum.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\random.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\split-secret.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\combine-secret.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\keytool.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\certtool.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\keywrap.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\agetool.exe" "$DEST_DIR"
+Copy-Item "$releaseDir\openssl-enc.exe" "$DEST_DIR"
 
 # ================================
 # Add DEST_DIR to PATH (User-level)